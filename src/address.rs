@@ -0,0 +1,217 @@
+use crate::errors::{Result, WalletError};
+use hex::FromHexError;
+use secp256k1::PublicKey;
+
+/// Cleans up a hex string as users tend to paste it.
+///
+/// Surrounding whitespace and newlines and a `0x` prefix are removed and
+/// the digits are lowercased. Whitespace inside the string is not removed.
+///
+/// # Arguments
+///
+/// * `input` - The hex string as given
+///
+/// # Returns
+///
+/// * `Ok(String)` - The bare lowercase hex digits
+/// * `Err(WalletError::KeyDecodingFailed)` - If a character is not a hex digit, with its
+///   position in `input`, or if the number of digits is odd
+pub fn normalize_hex(input: &str) -> Result<String> {
+    let trimmed = input.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    // Positions are reported in the input as given, not the trimmed string
+    let offset = input.len() - input.trim_start().len() + trimmed.len() - digits.len();
+    if let Some((index, c)) = digits.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(FromHexError::InvalidHexCharacter { c, index: offset + index }.into());
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(FromHexError::OddLength.into());
+    }
+    Ok(digits.to_ascii_lowercase())
+}
+
+/// Parses a hex-encoded secp256k1 public key.
+///
+/// Accepts both the compressed (33-byte, prefix `02`/`03`) and uncompressed
+/// (65-byte, prefix `04`) encodings. The hybrid encoding (prefix `06`/`07`)
+/// parses as a point too, but is rejected. The hex is normalized first, so
+/// a `0x` prefix, surrounding whitespace and uppercase digits are fine.
+///
+/// # Arguments
+///
+/// * `public_key_hex` - The hex-encoded public key
+///
+/// # Returns
+///
+/// * `Ok(PublicKey)` - The parsed public key
+/// * `Err(WalletError::KeyDecodingFailed)` - If the input is not hex
+/// * `Err(WalletError::AddressInvalid)` - If the input is not a valid public key
+pub fn parse_public_key(public_key_hex: &str) -> Result<PublicKey> {
+    let bytes = hex::decode(normalize_hex(public_key_hex)?)
+        .map_err(|_| WalletError::AddressInvalid(public_key_hex.to_string()))?;
+    if encoding_error(&bytes).is_some() {
+        return Err(WalletError::AddressInvalid(public_key_hex.to_string()));
+    }
+    PublicKey::from_slice(&bytes)
+        .map_err(|_| WalletError::AddressInvalid(public_key_hex.to_string()))
+}
+
+/// Converts a hex-encoded public key to its canonical address.
+///
+/// The canonical form is the lowercase hex of the compressed encoding, the
+/// form wallets are created with. Balance, send and history lookups all use
+/// it, so both encodings of a key reach the same account.
+///
+/// # Arguments
+///
+/// * `public_key_hex` - The public key, compressed or uncompressed
+///
+/// # Returns
+///
+/// * `Ok(String)` - The canonical address
+/// * `Err(WalletError::KeyDecodingFailed)` - If the input is not hex
+/// * `Err(WalletError::AddressInvalid)` - If the input is not an accepted public key
+pub fn normalize_address(public_key_hex: &str) -> Result<String> {
+    Ok(display_address(&parse_public_key(public_key_hex)?))
+}
+
+/// Shortest all-hex input taken for an attempted public key rather than a name.
+///
+/// Keys are 66 or 130 hex characters; names this long made only of hex
+/// digits are unlikely, while shorter ones such as "cafe" are common.
+const MIN_KEY_LIKE_LEN: usize = 40;
+
+/// Returns whether an input looks like an attempt at a public key rather than a name.
+///
+/// Used to decide whether an unresolvable recipient is reported as a bad
+/// key or as an unknown wallet name.
+pub fn looks_like_key(input: &str) -> bool {
+    let input = input.trim();
+    let digits = input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")).unwrap_or(input);
+    digits.len() >= MIN_KEY_LIKE_LEN && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Describes what is wrong with the length or prefix of an encoded public key.
+fn encoding_error(bytes: &[u8]) -> Option<String> {
+    match (bytes.len(), bytes.first()) {
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => None,
+        (33 | 65, Some(prefix)) => Some(format!(
+            "prefix {:02x} is not a compressed (02/03) or uncompressed (04) key",
+            prefix
+        )),
+        (len, _) => Some(format!("{} bytes, expected 33 or 65", len)),
+    }
+}
+
+/// Checks that a hex string is a valid secp256k1 public key.
+///
+/// Unlike `parse_public_key`, the error says what is wrong with the input:
+/// bad hex, a wrong length, or bytes that are not a point on the curve.
+///
+/// # Arguments
+///
+/// * `hex_key` - The hex-encoded public key
+///
+/// # Returns
+///
+/// * `Ok(Vec<u8>)` - The decoded key, compressed or uncompressed as given
+/// * `Err(WalletError::KeyDecodingFailed)` - If the input is not hex, with the offending character
+/// * `Err(WalletError::AddressInvalid)` - If it is not a public key, with the reason
+pub fn validate_public_key(hex_key: &str) -> Result<Vec<u8>> {
+    let invalid = |reason: String| WalletError::AddressInvalid(format!("{} ({})", hex_key, reason));
+
+    let bytes = hex::decode(normalize_hex(hex_key)?)?;
+    if let Some(reason) = encoding_error(&bytes) {
+        return Err(invalid(reason));
+    }
+    PublicKey::from_slice(&bytes)
+        .map(|_| bytes)
+        .map_err(|_| invalid("not a point on the secp256k1 curve".to_string()))
+}
+
+/// Returns the address form of a public key as this wallet displays it.
+///
+/// Addresses are the hex-encoded compressed public key, which is the form
+/// wallets are created with.
+pub fn display_address(public_key: &PublicKey) -> String {
+    hex::encode(public_key.serialize())
+}
+
+/// Abbreviates a long hex key for display as its first 8 and last 6 characters.
+///
+/// Inputs too short to abbreviate are returned unchanged.
+pub fn abbreviate_key(key: &str) -> String {
+    if key.len() <= 16 || !key.is_ascii() {
+        return key.to_string();
+    }
+    format!("{}…{}", &key[..8], &key[key.len() - 6..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The generator point, which is the public key for private key 1.
+    const COMPRESSED: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const UNCOMPRESSED: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                                483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    #[test]
+    fn both_encodings_normalize_to_the_compressed_key() {
+        assert_eq!(normalize_address(COMPRESSED).unwrap(), COMPRESSED);
+        assert_eq!(normalize_address(UNCOMPRESSED).unwrap(), COMPRESSED);
+        assert_eq!(normalize_address(&UNCOMPRESSED.to_uppercase()).unwrap(), COMPRESSED);
+    }
+
+    #[test]
+    fn hybrid_encoding_is_rejected() {
+        // Same point with the hybrid prefix for an even y coordinate
+        let hybrid = format!("06{}", &UNCOMPRESSED[2..]);
+        assert!(matches!(normalize_address(&hybrid), Err(WalletError::AddressInvalid(_))));
+        assert!(validate_public_key(&hybrid).unwrap_err().to_string().contains("prefix 06"));
+    }
+
+    #[test]
+    fn malformed_keys_are_rejected() {
+        assert!(normalize_address("zz").is_err());
+        assert!(normalize_address(&COMPRESSED[..64]).is_err());
+        assert!(validate_public_key(&COMPRESSED[..64]).unwrap_err().to_string().contains("32 bytes"));
+    }
+
+    #[test]
+    fn pasted_keys_are_normalized() {
+        let pasted = format!("  0x{}\n", COMPRESSED.to_uppercase());
+        assert_eq!(normalize_hex(&pasted).unwrap(), COMPRESSED);
+        assert_eq!(normalize_address(&pasted).unwrap(), COMPRESSED);
+        assert!(looks_like_key(&pasted));
+
+        let typo = format!(" 0x{}g{}", &COMPRESSED[..10], &COMPRESSED[11..]);
+        let error = validate_public_key(&typo).unwrap_err();
+        assert!(matches!(error, WalletError::KeyDecodingFailed { .. }));
+        assert!(error.to_string().contains("'g' at position 13"), "{}", error);
+        assert!(normalize_hex("abc").unwrap_err().to_string().contains("Odd number"));
+    }
+
+    #[test]
+    fn validated_keys_are_decoded_in_the_form_given() {
+        let compressed = validate_public_key(&format!("0x{}", COMPRESSED)).unwrap();
+        assert_eq!(compressed.len(), 33);
+        assert_eq!(hex::encode(compressed), COMPRESSED);
+
+        let uncompressed = validate_public_key(&format!("  {}\n", UNCOMPRESSED)).unwrap();
+        assert_eq!(uncompressed.len(), 65);
+        assert_eq!(hex::encode(uncompressed), UNCOMPRESSED);
+    }
+
+    #[test]
+    fn only_long_hex_strings_look_like_keys() {
+        assert!(looks_like_key(COMPRESSED));
+        assert!(looks_like_key(&COMPRESSED[..50]));
+        assert!(!looks_like_key("cafe"));
+        assert!(!looks_like_key("alice"));
+        assert!(!looks_like_key(&format!("{}z", &COMPRESSED[..50])));
+    }
+}
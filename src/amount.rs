@@ -0,0 +1,134 @@
+use crate::errors::{Result, WalletError};
+
+/// Largest supported number of decimal places; 10^19 no longer fits in a `u64`.
+pub const MAX_DECIMALS: u32 = 18;
+
+/// Formats an amount in base units as a decimal number of coins.
+///
+/// Trailing zeros of the fraction are dropped, so with 8 decimals
+/// `150000000` is shown as `1.5` and `100000000` as `1`.
+///
+/// # Arguments
+///
+/// * `raw` - The amount in base units
+/// * `decimals` - Number of decimal places in one coin
+pub fn format_amount(raw: u64, decimals: u32) -> String {
+    format_units(raw.into(), decimals)
+}
+
+/// Formats a signed change in base units as coins, always with its sign.
+///
+/// # Arguments
+///
+/// * `raw` - The change in base units
+/// * `decimals` - Number of decimal places in one coin
+///
+/// # Returns
+///
+/// The change such as `+1.5` or `-3`; zero is `0`.
+pub fn format_signed_amount(raw: i128, decimals: u32) -> String {
+    let sign = match raw {
+        0 => "",
+        raw if raw < 0 => "-",
+        _ => "+",
+    };
+    format!("{}{}", sign, format_units(raw.unsigned_abs(), decimals))
+}
+
+/// Formats base units as coins; wide enough for sums of amounts.
+fn format_units(raw: u128, decimals: u32) -> String {
+    if decimals == 0 {
+        return raw.to_string();
+    }
+
+    let unit = 10u128.pow(decimals);
+    let fraction = format!("{:0width$}", raw % unit, width = decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        (raw / unit).to_string()
+    } else {
+        format!("{}.{}", raw / unit, fraction)
+    }
+}
+
+/// Parses a decimal number of coins into base units.
+///
+/// # Arguments
+///
+/// * `input` - The amount, such as `1.5` or `42`
+/// * `decimals` - Number of decimal places in one coin
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The amount in base units
+/// * `Err(WalletError::InvalidAmount)` - If the input is not a non-negative decimal
+///   number, has more decimal places than allowed, or does not fit in a `u64`
+pub fn parse_amount(input: &str, decimals: u32) -> Result<u64> {
+    let invalid = |message: String| WalletError::InvalidAmount {
+        input: input.to_string(),
+        message,
+    };
+
+    let (whole, fraction) = input.trim().split_once('.').unwrap_or((input.trim(), ""));
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(invalid("expected a number like 12 or 1.5".to_string()));
+    }
+    if decimals == 0 && !fraction.is_empty() {
+        return Err(invalid("fractional amounts are not allowed".to_string()));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(invalid(format!("at most {} decimal places are allowed", decimals)));
+    }
+
+    let too_large = || invalid("amount is too large".to_string());
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| too_large())?
+    };
+    let fraction: u64 = format!("{:0<width$}", fraction, width = decimals as usize)
+        .parse()
+        .unwrap_or(0);
+
+    whole
+        .checked_mul(10u64.pow(decimals))
+        .and_then(|base| base.checked_add(fraction))
+        .ok_or_else(too_large)
+}
+
+/// A whole balance in hundredths of a percent, the unit `parse_percent` returns.
+pub const WHOLE_PERCENT: u64 = 10_000;
+
+/// Parses a percentage such as `50` or `12.5` into hundredths of a percent.
+///
+/// # Arguments
+///
+/// * `input` - The percentage, from 0 to 100 with at most two decimal places
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The percentage in hundredths, at most `WHOLE_PERCENT`
+/// * `Err(WalletError::InvalidAmount)` - If the input is not a number from 0 to 100
+pub fn parse_percent(input: &str) -> Result<u64> {
+    let number = input.trim().trim_end_matches('%');
+    let invalid = || WalletError::InvalidAmount {
+        input: format!("{}%", number),
+        message: "expected a percentage from 0 to 100 with at most 2 decimal places".to_string(),
+    };
+    let hundredths = parse_amount(number, 2).map_err(|_| invalid())?;
+    if hundredths > WHOLE_PERCENT {
+        return Err(invalid());
+    }
+    Ok(hundredths)
+}
+
+/// Computes a percentage of an amount, rounded down to whole base units.
+///
+/// # Arguments
+///
+/// * `amount` - The amount in base units
+/// * `hundredths` - The percentage in hundredths, as returned by `parse_percent`
+pub fn percent_of(amount: u64, hundredths: u64) -> u64 {
+    (u128::from(amount) * u128::from(hundredths) / u128::from(WHOLE_PERCENT)) as u64
+}
@@ -0,0 +1,84 @@
+use crate::errors::{Result, WalletError};
+use crate::proto::blockchain::Transaction;
+use crate::wallet::signing_digest;
+use secp256k1::{Message, Secp256k1};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Results of a signing benchmark run.
+pub struct BenchReport {
+    /// Number of signatures produced
+    pub count: u64,
+    /// Number of threads used
+    pub threads: usize,
+    /// Wall-clock time for the whole run
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    /// Signatures produced per second of wall-clock time.
+    pub fn signatures_per_sec(&self) -> f64 {
+        self.count as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Measures local transaction signing throughput.
+///
+/// Signs `count` synthetic transactions with a throwaway key, split evenly
+/// across `threads` threads. Neither wallet storage nor the blockchain
+/// service is touched.
+///
+/// # Arguments
+///
+/// * `count` - Total number of transactions to sign
+/// * `threads` - Number of signing threads
+///
+/// # Returns
+///
+/// * `Ok(BenchReport)` - Timing results for the run
+/// * `Err(WalletError)` - If a synthetic transaction cannot be signed
+pub fn bench_sign(count: u64, threads: usize) -> Result<BenchReport> {
+    let threads = threads.max(1);
+    let secp = Secp256k1::new();
+    let (secret_key, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+    let from = hex::encode(public_key.serialize());
+
+    let start = Instant::now();
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads as u64)
+            .map(|t| {
+                let secp = &secp;
+                let from = &from;
+                let share = count / threads as u64 + u64::from(t < count % threads as u64);
+                scope.spawn(move || {
+                    for i in 0..share {
+                        let tx = Transaction {
+                            from: from.clone(),
+                            to: from.clone(),
+                            amount: i,
+                            timestamp: t,
+                            ..Default::default()
+                        };
+                        let msg = Message::from_slice(&signing_digest(&tx)?)
+                            .map_err(|e| WalletError::SigningFailed { 
+                                message: e.to_string() 
+                            })?;
+                        secp.sign_ecdsa(&msg, &secret_key);
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        handles.into_iter()
+            .map(|h| h.join().unwrap_or_else(|_| Err(WalletError::SigningFailed { 
+                message: "signing thread panicked".to_string() 
+            })))
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    results.into_iter().collect::<Result<Vec<()>>>()?;
+
+    Ok(BenchReport { count, threads, elapsed })
+}
@@ -0,0 +1,36 @@
+use crate::errors::{Result, WalletError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for the timestamps the wallet creates.
+///
+/// Transactions are signed over their timestamp, so a pinned clock makes
+/// the signed bytes reproducible.
+pub trait Clock: Send + Sync {
+    /// Returns the current time in unix seconds.
+    fn now(&self) -> Result<u64>;
+}
+
+/// The system's wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<u64> {
+        Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| WalletError::SystemTimeError {
+                message: e.to_string(),
+            })?
+            .as_secs())
+    }
+}
+
+/// A clock stopped at a fixed time.
+#[cfg(test)]
+pub struct FixedClock(pub u64);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> Result<u64> {
+        Ok(self.0)
+    }
+}
@@ -0,0 +1,90 @@
+use owo_colors::OwoColorize;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to color terminal output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!("unknown color choice '{}', expected auto, always or never", other)),
+        }
+    }
+}
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Decides whether stdout and stderr are colored.
+///
+/// Must be called once at startup; until then nothing is colored.
+pub fn init(choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let (stdout, stderr) = match choice {
+        ColorChoice::Always => (true, true),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Auto => (
+            !no_color && io::stdout().is_terminal(),
+            !no_color && io::stderr().is_terminal(),
+        ),
+    };
+    STDOUT_COLOR.store(stdout, Ordering::Relaxed);
+    STDERR_COLOR.store(stderr, Ordering::Relaxed);
+}
+
+/// Returns whether stderr is colored.
+pub fn stderr_enabled() -> bool {
+    STDERR_COLOR.load(Ordering::Relaxed)
+}
+
+/// Styles a success message for stdout (green).
+pub fn success(text: impl Display) -> String {
+    if STDOUT_COLOR.load(Ordering::Relaxed) {
+        text.green().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Styles an address for stdout (cyan).
+pub fn address(text: impl Display) -> String {
+    if STDOUT_COLOR.load(Ordering::Relaxed) {
+        text.cyan().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Styles an amount of coins for stdout (yellow).
+pub fn amount(text: impl Display) -> String {
+    if STDOUT_COLOR.load(Ordering::Relaxed) {
+        text.yellow().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Styles an error message for stderr (red).
+pub fn error(text: impl Display) -> String {
+    if STDERR_COLOR.load(Ordering::Relaxed) {
+        text.red().to_string()
+    } else {
+        text.to_string()
+    }
+}
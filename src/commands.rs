@@ -1,5 +1,91 @@
+use crate::color::ColorChoice;
+use crate::keys::KeyFormat;
+use crate::output::{Essential, OutputFormat};
+use structopt::clap::{AppSettings, Shell};
 use structopt::StructOpt;
 
+/// Command-line options for the blockchain wallet CLI.
+///
+/// Holds the global flags shared by every command along with the command itself.
+#[derive(StructOpt)]
+#[structopt(name = "mockallet")]
+pub struct Opt {
+    /// URL of the blockchain service; repeat to fail over between several [env: MOCKCHAIN_SERVERS, MOCKCHAIN_SERVER] [default: http://[::1]:50051]
+    #[structopt(long, global = true, number_of_values = 1)]
+    pub server: Vec<String>,
+
+    /// Dumps every gRPC request and response to stderr
+    #[structopt(long, global = true)]
+    pub trace_rpc: bool,
+
+    /// Logs what the wallet is doing to stderr; repeat for more detail (-v RPCs, -vv storage, -vvv transport)
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    pub verbose: u8,
+
+    /// Prints only the essential result, such as a balance or transaction id, or nothing at all
+    #[structopt(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Writes logs as JSON lines instead of text
+    #[structopt(long, global = true)]
+    pub log_json: bool,
+
+    /// Maximum number of retries for RPCs that fail transiently
+    #[structopt(long, global = true, default_value = "3")]
+    pub max_retries: u32,
+
+    /// Seconds to wait for each RPC before giving up
+    #[structopt(long, global = true, default_value = "30")]
+    pub timeout: u64,
+
+    /// Seconds to wait for the connection to the blockchain service
+    #[structopt(long, global = true, default_value = "5")]
+    pub connect_timeout: u64,
+
+    /// PEM file with the CA certificate for an https:// server
+    #[structopt(long, global = true)]
+    pub ca_cert: Option<String>,
+
+    /// PEM file with a client certificate for mutual TLS
+    #[structopt(long, global = true)]
+    pub client_cert: Option<String>,
+
+    /// PEM file with the private key for --client-cert
+    #[structopt(long, global = true)]
+    pub client_key: Option<String>,
+
+    /// Proxy to reach the blockchain service through: socks5://, socks5h:// or http:// [env: HTTPS_PROXY]
+    #[structopt(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// Output format: text, json, jsonl or table
+    #[structopt(long, global = true, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// Prints addresses, hashes and signatures in full instead of abbreviated
+    #[structopt(long, global = true, overrides_with = "short")]
+    pub full: bool,
+
+    /// Abbreviates addresses, hashes and signatures (the default)
+    #[structopt(long, global = true, overrides_with = "full")]
+    pub short: bool,
+
+    /// Seconds to reuse a fetched balance before asking the node again (0 disables caching)
+    #[structopt(long, global = true, default_value = "0")]
+    pub cache_ttl: u64,
+
+    /// When to color output: auto, always or never [env: NO_COLOR disables auto]
+    #[structopt(long, global = true, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Directory holding wallets and settings [env: MOCKCHAIN_WALLET_DIR] [default: ./.wallets if it exists, else ~/.config/mockchain-wallet]
+    #[structopt(long, global = true)]
+    pub wallet_dir: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
 /// Commands supported by the blockchain wallet CLI.
 /// 
 /// Defines the command-line interface structure using StructOpt.
@@ -11,56 +97,817 @@ pub enum Command {
         /// Name to assign to the new wallet
         #[structopt(name = "name")]
         name: String,
+
+        /// Derives the key from a new BIP39 mnemonic with this many words (12 or 24)
+        #[structopt(long)]
+        mnemonic: Option<usize>,
+
+        /// Prints a recovery sheet with the private key (and mnemonic) to write down
+        #[structopt(long)]
+        show_secret: bool,
+
+        /// Shows the secrets only until Enter is pressed, then clears them from the terminal
+        #[structopt(long)]
+        brief: bool,
+    },
+
+    /// Recovers a wallet from a BIP39 mnemonic phrase
+    #[structopt(name = "recover")]
+    RecoverWallet {
+        /// Name to assign to the recovered wallet
+        #[structopt(name = "name")]
+        name: String,
+
+        /// Mnemonic phrase (quote it as a single argument)
+        #[structopt(name = "mnemonic")]
+        mnemonic: String,
+
+        /// Stores the key even if another wallet already holds it
+        #[structopt(long)]
+        allow_duplicate: bool,
+    },
+    
+    /// Deletes a wallet from local storage
+    #[structopt(name = "delete")]
+    DeleteWallet {
+        /// Name of the wallet to delete
+        #[structopt(name = "name")]
+        name: String,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        force: bool,
     },
     
+    /// Renames a wallet
+    #[structopt(name = "rename")]
+    RenameWallet {
+        /// Current name of the wallet
+        #[structopt(name = "old")]
+        old: String,
+
+        /// New name for the wallet
+        #[structopt(name = "new")]
+        new: String,
+    },
+    
+    /// Prints the private key of a wallet
+    #[structopt(name = "export-key")]
+    ExportKey {
+        /// Name of the wallet to export
+        #[structopt(name = "wallet")]
+        wallet_name: String,
+
+        /// Key encoding: hex or wif
+        #[structopt(long, default_value = "hex")]
+        format: KeyFormat,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        force: bool,
+
+        /// Shows the key only until Enter is pressed, then clears it from the terminal
+        #[structopt(long)]
+        brief: bool,
+    },
+    
+    /// Imports an existing private key as a new wallet
+    #[structopt(name = "import-key")]
+    ImportKey {
+        /// Name to assign to the imported wallet
+        #[structopt(name = "name")]
+        name: String,
+
+        /// Private key (hex or WIF)
+        #[structopt(name = "private_key", required_unless = "file")]
+        private_key: Option<String>,
+
+        /// Reads the key from a file: hex, WIF, a SEC1 or PKCS#8 PEM key, or keystore JSON [env: MOCKALLET_PASSPHRASE]
+        #[structopt(long, conflicts_with = "private_key")]
+        file: Option<String>,
+
+        /// Stores the key even if another wallet already holds it
+        #[structopt(long)]
+        allow_duplicate: bool,
+    },
+    
+    /// Watches an address without storing its private key
+    #[structopt(name = "watch")]
+    AddWatch {
+        /// Name to assign to the watched address
+        #[structopt(name = "name")]
+        name: String,
+
+        /// Public key to watch (hex)
+        #[structopt(name = "public_key")]
+        public_key: String,
+
+        /// Stores the address even if another wallet already holds it
+        #[structopt(long)]
+        allow_duplicate: bool,
+    },
+
+    /// Shows a wallet's address as a QR code
+    #[structopt(name = "show-qr")]
+    ShowQr {
+        /// Name of the wallet
+        #[structopt(name = "wallet")]
+        wallet: String,
+
+        /// Also saves the QR code as a PNG image at this path
+        #[structopt(long)]
+        png: Option<String>,
+    },
+
+    /// Writes an encrypted backup of all wallets, contacts and settings [env: MOCKALLET_PASSPHRASE]
+    #[structopt(name = "backup")]
+    Backup {
+        /// New file to write the backup to
+        #[structopt(name = "path")]
+        path: String,
+    },
+
+    /// Merges an encrypted backup into local storage [env: MOCKALLET_PASSPHRASE]
+    #[structopt(name = "restore")]
+    Restore {
+        /// Backup file written by `backup`
+        #[structopt(name = "path")]
+        path: String,
+
+        /// Overwrites wallets and contacts whose name is taken without asking
+        #[structopt(long)]
+        force: bool,
+    },
+
+    /// Restores the wallets that can still be read from damaged wallet files
+    #[structopt(name = "repair")]
+    Repair {
+        /// Damaged file to repair [default: every wallet file set aside as corrupt]
+        #[structopt(name = "file")]
+        file: Option<String>,
+    },
+
+    /// Checks local storage for problems such as wallets sharing a key
+    #[structopt(name = "doctor")]
+    Doctor {
+        /// Replaces public keys that do not match the wallet's private key
+        #[structopt(long)]
+        fix: bool,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        yes: bool,
+    },
+
     /// Lists all wallets in local storage
     #[structopt(name = "list")]
-    ListWallets,
+    ListWallets {
+        /// Lists only wallets with this tag
+        #[structopt(long)]
+        tag: Option<String>,
+
+        /// Also shows each wallet's balance, fetched concurrently
+        #[structopt(long)]
+        balances: bool,
+    },
+
+    /// Adds or removes a wallet's tags and sets its label
+    #[structopt(name = "tag")]
+    Tag {
+        /// Name of the wallet
+        #[structopt(name = "wallet")]
+        wallet: String,
+
+        /// Tags to add, such as `cold`
+        #[structopt(name = "tags")]
+        tags: Vec<String>,
+
+        /// Tag to remove (repeatable)
+        #[structopt(long, number_of_values = 1)]
+        remove: Vec<String>,
+
+        /// Label to show in listings; an empty label clears it
+        #[structopt(long)]
+        label: Option<String>,
+    },
     
     /// Gets the balance for a wallet
     #[structopt(name = "balance")]
     GetBalance {
-        /// Name of the wallet to check
+        /// Name of the wallet to check [default: the default wallet]
         #[structopt(name = "wallet")]
-        wallet_name: String,
+        wallet_name: Option<String>,
+
+        /// Asks the node even if a cached balance is still fresh
+        #[structopt(long)]
+        refresh: bool,
+
+        /// Adds the balances of addresses derived from the wallet
+        #[structopt(long)]
+        include_derived: bool,
+    },
+
+    /// Derives a new address from a mnemonic wallet's seed and adds it as a wallet
+    #[structopt(name = "derive-address")]
+    DeriveAddress {
+        /// Name of the mnemonic wallet to derive from
+        wallet: String,
+
+        /// Index of the address to derive
+        index: u32,
+
+        /// Name for the derived wallet [default: <wallet>-<index>]
+        #[structopt(long)]
+        name: Option<String>,
     },
     
+    /// Sets the wallet used when a command's wallet argument is omitted
+    #[structopt(name = "set-default")]
+    SetDefault {
+        /// Name of the wallet to use by default
+        #[structopt(name = "name")]
+        name: String,
+    },
+
+    /// Shows the balance of every wallet and their total
+    #[structopt(name = "total-balance")]
+    TotalBalance,
+
     /// Sends a transaction from one wallet to another
-    #[structopt(name = "send")]
+    #[structopt(name = "send", usage = "mockallet send [FLAGS] [OPTIONS] [from] <to> <amount>")]
     SendTransaction {
+        /// Sender's wallet (may be omitted when a default is set), recipient name or
+        /// address, and amount of coins to send, e.g. 1.5 (left out with --percent)
+        #[structopt(name = "args", min_values = 1, max_values = 3, required = true)]
+        args: Vec<String>,
+
+        /// Sends this percentage of the sender's balance instead of an amount, e.g. 50
+        #[structopt(long)]
+        percent: Option<String>,
+
+        /// Earliest time (unix seconds) the transaction may execute
+        #[structopt(long)]
+        not_before: Option<u64>,
+
+        /// Note to store with the transaction (at most 256 bytes)
+        #[structopt(long)]
+        memo: Option<String>,
+
+        /// Fee to pay on top of the amount, e.g. 0.01
+        #[structopt(long)]
+        fee: Option<String>,
+
+        /// Signs with this private key (hex or WIF) instead of a stored wallet; the key is not saved
+        #[structopt(long)]
+        from_key: Option<String>,
+
+        /// Signs and prints the transaction without submitting it
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        yes: bool,
+
+        /// Waits until the transaction is mined into a block
+        #[structopt(long)]
+        wait: bool,
+
+        /// Seconds to wait for confirmation with --wait
+        #[structopt(long, default_value = "120")]
+        wait_timeout: u64,
+
+        /// Submits without first checking the sender's balance
+        #[structopt(long)]
+        no_balance_check: bool,
+
+        /// Also prints the signed transaction as hex-encoded protobuf
+        #[structopt(long)]
+        emit_raw: bool,
+
+        /// Sends even if the recipient is the sender's own address
+        #[structopt(long)]
+        allow_self: bool,
+    },
+
+    /// Sends a wallet's entire balance to another address
+    #[structopt(name = "sweep")]
+    Sweep {
+        /// Name of the wallet to empty
+        #[structopt(name = "from")]
+        from: String,
+
+        /// Recipient name or address
+        #[structopt(name = "to")]
+        to: String,
+
+        /// Fee to pay out of the swept balance, e.g. 0.01
+        #[structopt(long)]
+        fee: Option<String>,
+
+        /// Sweeps only this percentage of the balance, e.g. 50
+        #[structopt(long)]
+        percent: Option<String>,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        yes: bool,
+    },
+
+    /// Sends payments to several recipients listed in a file
+    #[structopt(name = "send-batch")]
+    SendBatch {
+        /// Name of the sender's wallet
+        #[structopt(name = "from")]
+        from: String,
+
+        /// File of `address,amount` lines or a JSON array of {"to", "amount"} objects
+        #[structopt(name = "recipients_file")]
+        recipients_file: String,
+
+        /// Fee to pay on top of each payment, e.g. 0.01
+        #[structopt(long)]
+        fee: Option<String>,
+
+        /// Skips the confirmation prompt
+        #[structopt(long)]
+        yes: bool,
+    },
+
+    /// Sends a transaction described by a JSON template file
+    #[structopt(name = "send-template")]
+    SendTemplate {
+        /// Path to the template file
+        #[structopt(name = "path")]
+        path: String,
+
+        /// Overrides the template's sender wallet
+        #[structopt(long)]
+        from: Option<String>,
+
+        /// Overrides the template's recipient
+        #[structopt(long)]
+        to: Option<String>,
+
+        /// Overrides the template's amount, e.g. 1.5
+        #[structopt(long)]
+        amount: Option<String>,
+
+        /// Overrides the template's not-before time (unix seconds)
+        #[structopt(long)]
+        not_before: Option<u64>,
+
+        /// Overrides the template's memo
+        #[structopt(long)]
+        memo: Option<String>,
+
+        /// Overrides the template's fee, e.g. 0.01
+        #[structopt(long)]
+        fee: Option<String>,
+    },
+    
+    /// Signs a transaction offline and writes it to a JSON file
+    #[structopt(name = "sign")]
+    SignTransaction {
         /// Name of the sender's wallet
         #[structopt(name = "from")]
         from_wallet: String,
-        
+
         /// Name or address of the recipient
         #[structopt(name = "to")]
         to_wallet: String,
-        
-        /// Amount of coins to send
+
+        /// Amount of coins to send, e.g. 1.5
         #[structopt(name = "amount")]
-        amount: u64,
+        amount: String,
+
+        /// File to write the signed transaction to
+        #[structopt(long, required_unless = "emit-raw")]
+        out: Option<String>,
+
+        /// Creation time of the transaction (unix seconds) [default: now]
+        #[structopt(long)]
+        timestamp: Option<u64>,
+
+        /// Earliest time (unix seconds) the transaction may execute
+        #[structopt(long)]
+        not_before: Option<u64>,
+
+        /// Note to store with the transaction (at most 256 bytes)
+        #[structopt(long)]
+        memo: Option<String>,
+
+        /// Fee to pay on top of the amount, e.g. 0.01
+        #[structopt(long)]
+        fee: Option<String>,
+
+        /// Prints the signed transaction as hex-encoded protobuf
+        #[structopt(long)]
+        emit_raw: bool,
+
+        /// Signs even if the recipient is the sender's own address
+        #[structopt(long)]
+        allow_self: bool,
     },
-    
+
+    /// Broadcasts a transaction signed with `sign`
+    #[structopt(name = "submit")]
+    SubmitTransaction {
+        /// Path to the signed transaction file
+        #[structopt(name = "path")]
+        path: String,
+    },
+
+    /// Decodes a hex-encoded protobuf transaction, as printed by --emit-raw
+    #[structopt(name = "decode-tx")]
+    DecodeTx {
+        /// Hex-encoded protobuf transaction
+        #[structopt(name = "hex")]
+        hex: String,
+    },
+
     /// Requests funds from the blockchain faucet
     #[structopt(name = "faucet")]
     RequestFaucet {
-        /// Name of the wallet to receive funds
+        /// Name of the wallet to receive funds [default: the default wallet]
         #[structopt(name = "wallet")]
-        wallet_name: String,
+        wallet_name: Option<String>,
+
+        /// Amount to ask for [default: whatever the faucet gives]
+        #[structopt(long)]
+        amount: Option<String>,
+    },
+
+    /// Lists the funds received from the faucet, from the local log
+    #[structopt(name = "faucet-log")]
+    FaucetLog {
+        /// Only lists receipts of this wallet or address
+        #[structopt(name = "wallet")]
+        wallet: Option<String>,
     },
 
     /// Get the transaction history for a wallet (by name or address)
     GetHistory {
-        /// Wallet name or public key address (hex)
-        wallet_name_or_key: String,
+        /// Wallet name or public key address (hex) [default: the default wallet]
+        wallet_name_or_key: Option<String>,
+
+        /// Includes transactions of addresses derived from the wallet
+        #[structopt(long)]
+        include_derived: bool,
+
+        /// Maximum number of transactions to show
+        #[structopt(long)]
+        limit: Option<usize>,
+
+        /// Number of transactions to skip (oldest first)
+        #[structopt(long, default_value = "0")]
+        offset: usize,
+
+        /// Only show transactions at or after this time (unix seconds)
+        #[structopt(long)]
+        since: Option<u64>,
+
+        /// Only show transactions at or before this time (unix seconds)
+        #[structopt(long)]
+        until: Option<u64>,
+
+        /// Only show transactions of at least this amount (in coins)
+        #[structopt(long)]
+        min_amount: Option<String>,
+
+        /// Only show transactions of at most this amount (in coins)
+        #[structopt(long)]
+        max_amount: Option<String>,
+
+        /// Only show transactions from or to this wallet, contact or address
+        #[structopt(long)]
+        counterparty: Option<String>,
+    },
+
+    /// Shows the merged history of all local wallets, oldest first
+    #[structopt(name = "activity")]
+    Activity {
+        /// Maximum number of transactions to show
+        #[structopt(long)]
+        limit: Option<usize>,
+
+        /// Number of transactions to skip (oldest first)
+        #[structopt(long, default_value = "0")]
+        offset: usize,
+
+        /// Only show transactions at or after this time (unix seconds)
+        #[structopt(long)]
+        since: Option<u64>,
+
+        /// Only show transactions at or before this time (unix seconds)
+        #[structopt(long)]
+        until: Option<u64>,
     },
 
     /// Get the entire state of the blockchain (all blocks)
-    GetState,
+    GetState {
+        /// Lists the transactions of every block, as get-block does
+        #[structopt(long, overrides_with = "summary")]
+        with_txs: bool,
+
+        /// Prints only the number of transactions in each block (the default)
+        #[structopt(long, overrides_with = "with-txs")]
+        summary: bool,
+    },
+
+    /// Checks that the node is reachable and reports its response time
+    Ping,
+
+    /// Shows the configured servers, the last one connected to, TLS and the wallet directory
+    Status,
+
+    /// Summarize the blockchain: height, transaction count and latest block
+    ChainInfo,
+
+    /// Checks that every block links to the one before it
+    #[structopt(name = "verify-chain")]
+    VerifyChain,
 
     /// Get a specific block by its index
     GetBlock {
         /// Index of the block to retrieve
         index: u64,
     },
+
+    /// Get the blocks in an inclusive range of indices
+    GetBlocks {
+        /// Index of the first block
+        start: u64,
+
+        /// Index of the last block
+        end: u64,
+    },
+
+    /// Prints new blocks as they are mined until interrupted
+    #[structopt(name = "watch-blocks")]
+    Watch {
+        /// Seconds between polls when the server cannot stream blocks
+        #[structopt(long, default_value = "5")]
+        interval: u64,
+    },
+
+    /// Prints a wallet's transactions as they are mined until interrupted
+    #[structopt(name = "follow")]
+    Follow {
+        /// Wallet, contact or address to follow (defaults to the default wallet)
+        #[structopt(name = "wallet")]
+        wallet: Option<String>,
+
+        /// Stops after this many transactions
+        #[structopt(long)]
+        count: Option<u64>,
+
+        /// Gives up after this many seconds, failing if --count transactions have not arrived
+        #[structopt(long)]
+        wait_timeout: Option<u64>,
+
+        /// Seconds between polls when the server cannot stream blocks
+        #[structopt(long, default_value = "5")]
+        interval: u64,
+    },
+
+    /// Creates demo wallets with keys derived deterministically from a seed
+    #[structopt(name = "seed")]
+    Seed {
+        /// Number of demo wallets to create
+        #[structopt(long, default_value = "3")]
+        count: u32,
+
+        /// Seed string the keys are derived from
+        #[structopt(long, default_value = "mockchain-demo")]
+        seed: String,
+    },
+
+    /// Shows the address form of a public key
+    #[structopt(name = "address-of")]
+    AddressOf {
+        /// Public key (hex, compressed or uncompressed)
+        #[structopt(name = "public_key")]
+        public_key: String,
+    },
+
+    /// Checks whether an address is a valid public key, without contacting the node
+    #[structopt(name = "validate-address")]
+    ValidateAddress {
+        /// Address to check (hex public key)
+        #[structopt(name = "address")]
+        address: String,
+    },
+
+    /// Benchmarks local transaction signing throughput
+    #[structopt(name = "bench-sign")]
+    BenchSign {
+        /// Number of transactions to sign
+        #[structopt(long, default_value = "10000")]
+        count: u64,
+
+        /// Number of signing threads
+        #[structopt(long, default_value = "1")]
+        threads: usize,
+    },
+
+    /// Prints a shell completion script (bash, zsh, fish, powershell or elvish)
+    #[structopt(name = "completions")]
+    Completions {
+        /// Shell to generate the script for
+        #[structopt(name = "shell", possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+
+    /// Prints the names of local wallets, one per line (used by completion scripts)
+    #[structopt(name = "wallet-names", setting = AppSettings::Hidden)]
+    WalletNames,
+
+    /// Finds the block containing a transaction
+    #[structopt(name = "find-tx")]
+    FindTx {
+        /// Transaction signature (hex)
+        #[structopt(name = "signature")]
+        signature_hex: String,
+
+        /// Unix time the transaction was signed at; searches for its block by time, which is much faster on long chains
+        #[structopt(long)]
+        timestamp: Option<u64>,
+    },
+
+    /// Lists transactions sent from here that are not mined yet
+    #[structopt(name = "mempool")]
+    Mempool {
+        /// Only lists transactions from or to this wallet or address
+        #[structopt(name = "wallet")]
+        wallet: Option<String>,
+    },
+
+    /// Shows how many blocks confirm a transaction
+    #[structopt(name = "confirmations")]
+    Confirmations {
+        /// Transaction signature (hex)
+        #[structopt(name = "signature")]
+        signature_hex: String,
+
+        /// Unix time the transaction was signed at; searches for its block by time, which is much faster on long chains
+        #[structopt(long)]
+        timestamp: Option<u64>,
+    },
+
+    /// Shows the signing preimage and digest of a transaction
+    #[structopt(name = "show-preimage")]
+    ShowPreimage {
+        /// Transaction signature (hex)
+        #[structopt(name = "signature")]
+        signature: String,
+    },
+
+    /// Verifies the signature of a transaction in a block
+    #[structopt(name = "verify-tx")]
+    VerifyTx {
+        /// Index of the block holding the transaction
+        #[structopt(name = "block")]
+        block: u64,
+
+        /// Position of the transaction within the block (starting at 0)
+        #[structopt(name = "position")]
+        position: usize,
+    },
+
+    /// Signs a text message to prove control of a wallet's address
+    #[structopt(name = "sign-message")]
+    SignMessage {
+        /// Name of the wallet to sign with
+        #[structopt(name = "wallet")]
+        wallet: String,
+
+        /// Message to sign
+        #[structopt(name = "message")]
+        message: String,
+    },
+
+    /// Verifies a message signature made with `sign-message`
+    #[structopt(name = "verify-message")]
+    VerifyMessage {
+        /// Public key of the signer (hex)
+        #[structopt(name = "address")]
+        address: String,
+
+        /// Message that was signed
+        #[structopt(name = "message")]
+        message: String,
+
+        /// Signature to check (hex)
+        #[structopt(name = "signature")]
+        signature: String,
+    },
+
+    /// Adds a recipient to the address book
+    #[structopt(name = "add-contact")]
+    AddContact {
+        /// Alias for the contact
+        #[structopt(name = "alias")]
+        alias: String,
+
+        /// Public key of the contact (hex)
+        #[structopt(name = "address")]
+        address: String,
+    },
+
+    /// Lists the contacts in the address book
+    #[structopt(name = "contacts")]
+    ListContacts,
+
+    /// Removes a contact from the address book
+    #[structopt(name = "remove-contact")]
+    RemoveContact {
+        /// Alias of the contact to remove
+        #[structopt(name = "alias")]
+        alias: String,
+    },
+
+    /// Manages local notes attached to transactions
+    #[structopt(name = "tx-note")]
+    TxNote(TxNoteCommand),
+}
+
+impl Command {
+    /// What `--quiet` keeps of the command's result.
+    pub fn essential(&self) -> Essential {
+        match self {
+            Command::CreateWallet { .. }
+            | Command::RecoverWallet { .. }
+            | Command::ImportKey { .. }
+            | Command::AddWatch { .. }
+            | Command::ShowQr { .. }
+            | Command::DeriveAddress { .. }
+            | Command::AddressOf { .. } => Essential::Field("address"),
+            Command::ExportKey { .. } => Essential::Field("private_key"),
+            Command::ListWallets { .. } | Command::Seed { .. } => Essential::Field("name"),
+            Command::GetBalance { .. } => Essential::Amount("balance"),
+            Command::TotalBalance => Essential::Amount("total"),
+            Command::Sweep { .. } | Command::RequestFaucet { .. } => Essential::Amount("amount"),
+            Command::SendTransaction { .. }
+            | Command::SignTransaction { .. }
+            | Command::SubmitTransaction { .. }
+            | Command::SendTemplate { .. } => Essential::Field("id"),
+            Command::SendBatch { .. } => Essential::Field("sent"),
+            Command::DecodeTx { .. } | Command::FindTx { .. } => Essential::Field("transaction"),
+            Command::GetHistory { .. } | Command::Activity { .. } | Command::Mempool { .. } => {
+                Essential::Field("transactions")
+            }
+            Command::GetState { .. } | Command::GetBlocks { .. } => Essential::Field("blocks"),
+            Command::GetBlock { .. } => Essential::Field("block"),
+            Command::Ping => Essential::Field("latency_ms"),
+            Command::FaucetLog { .. } => Essential::Field("receipts"),
+            Command::Status => Essential::Field("servers"),
+            Command::ChainInfo => Essential::Field("height"),
+            Command::Confirmations { .. } => Essential::Field("confirmations"),
+            Command::ShowPreimage { .. } => Essential::Field("digest"),
+            Command::VerifyTx { .. } => Essential::Field("valid"),
+            Command::SignMessage { .. } => Essential::Field("signature"),
+            Command::ListContacts => Essential::Field("alias"),
+            Command::BenchSign { .. } => Essential::Field("signatures_per_sec"),
+            Command::Doctor { .. } => Essential::Field("problems"),
+            Command::TxNote(TxNoteCommand::List) => Essential::Whole,
+            Command::DeleteWallet { .. }
+            | Command::RenameWallet { .. }
+            | Command::Backup { .. }
+            | Command::Restore { .. }
+            | Command::Repair { .. }
+            | Command::Tag { .. }
+            | Command::SetDefault { .. }
+            | Command::VerifyChain
+            | Command::Watch { .. }
+            | Command::Follow { .. }
+            | Command::ValidateAddress { .. }
+            | Command::VerifyMessage { .. }
+            | Command::Completions { .. }
+            | Command::WalletNames
+            | Command::AddContact { .. }
+            | Command::RemoveContact { .. }
+            | Command::TxNote(TxNoteCommand::Add { .. }) => Essential::Nothing,
+        }
+    }
+}
+
+/// Subcommands for managing local transaction notes.
+#[derive(StructOpt)]
+pub enum TxNoteCommand {
+    /// Attaches a note to a transaction
+    #[structopt(name = "add")]
+    Add {
+        /// Transaction id (hex-encoded signature)
+        #[structopt(name = "id")]
+        id: String,
+
+        /// Note to attach
+        #[structopt(name = "note")]
+        note: String,
+    },
+
+    /// Lists all transaction notes
+    #[structopt(name = "list")]
+    List,
 }
@@ -0,0 +1,31 @@
+use crate::address::abbreviate_key;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static FULL: AtomicBool = AtomicBool::new(false);
+
+/// Decides whether keys are printed in full or abbreviated.
+///
+/// Must be called once at startup; until then everything is abbreviated.
+/// JSON output always carries full values and ignores this setting.
+pub fn init(full: bool) {
+    FULL.store(full, Ordering::Relaxed);
+}
+
+/// Whether keys are printed in full.
+pub fn is_full() -> bool {
+    FULL.load(Ordering::Relaxed)
+}
+
+/// Formats an address, hash or other hex key for human-readable output.
+pub fn key(text: &str) -> String {
+    if is_full() {
+        text.to_string()
+    } else {
+        abbreviate_key(text)
+    }
+}
+
+/// Formats a raw signature for human-readable output.
+pub fn signature(bytes: &[u8]) -> String {
+    key(&hex::encode(bytes))
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 use std::io;
+use tonic::{Code, Status};
 
 /// Custom error types for the blockchain wallet CLI.
 /// 
@@ -10,11 +11,41 @@ pub enum WalletError {
     /// Wallet name already exists.
     WalletExists(String),
 
+    /// Public key is already stored under another wallet name.
+    DuplicateKey { existing_name: String },
+
     /// Wallet with this name or address was not found.
     WalletNotFound(String),
 
     /// Invalid blockchain address format.
     AddressInvalid(String),
+
+    /// Recipient name matches different addresses in more than one namespace.
+    AmbiguousRecipient { name: String, choices: Vec<String> },
+
+    /// Wallet has no private key and cannot sign.
+    WatchOnly(String),
+
+    /// No wallet was given and no default wallet is set.
+    NoDefaultWallet,
+
+    /// Contact alias already exists in the address book.
+    ContactExists(String),
+
+    /// Contact alias was not found in the address book.
+    ContactNotFound(String),
+
+    /// Invalid transaction id (expected a hex-encoded signature).
+    InvalidTransactionId(String),
+
+    /// A command argument is out of range or inconsistent.
+    InvalidArgument { message: String },
+
+    /// The chain returned by the node does not link up.
+    ChainInconsistent { index: u64, reason: String },
+
+    /// An amount could not be parsed.
+    InvalidAmount { input: String, message: String },
     
     /// Failed to read from wallet storage file.
     StorageRead { path: String, error: io::Error },
@@ -25,23 +56,62 @@ pub enum WalletError {
     /// Failed to create storage directory or file.
     StorageCreate { path: String, error: io::Error },
 
+    /// Another process held the wallet storage lock for too long.
+    StorageLocked { path: String },
+
     /// Failed to parse JSON from storage.
     JsonParse { error: serde_json::Error },
 
     /// Failed to serialize data to JSON.
     JsonSerialize { error: serde_json::Error },
     
+    /// Blockchain service URL is malformed.
+    InvalidEndpoint { url: String, message: String },
+
+    /// TLS options or certificate files are invalid.
+    TlsConfig { message: String },
+
+    /// Proxy URL is malformed or uses an unsupported scheme.
+    ProxyConfig { message: String },
+
     /// Failed to connect to blockchain service.
     ConnectionFailed { error: tonic::transport::Error },
 
+    /// None of the configured blockchain services could be reached.
+    AllEndpointsFailed { attempted: Vec<String> },
+
+    /// Blockchain service did not respond in time.
+    Timeout { operation: String },
+
     /// Error response from blockchain gRPC service.
-    RpcError { status: Status },
+    RpcError { status: Box<Status> },
+
+    /// Transaction template failed validation.
+    InvalidTemplate { problems: Vec<String> },
+
+    /// Batch payment file has malformed entries.
+    InvalidBatchFile { path: String, problems: Vec<String> },
 
     /// Transaction was rejected by the blockchain.
-    TransactionFailed { message: String },
+    ///
+    /// `code` is the gRPC status code of the rejection, or `Code::Unknown`
+    /// when the node reported the failure in the response body instead.
+    TransactionFailed { code: Code, message: String },
+
+    /// Recipient is the sender's own address.
+    SelfSend,
+
+    /// Sender cannot cover the amount of a transaction.
+    InsufficientFunds { needed: u64, available: u64 },
+
+    /// Transaction was already submitted to the blockchain.
+    DuplicateTransaction { id: String },
 
     /// Faucet request was rejected.
-    FaucetFailed { message: String },
+    ///
+    /// `code` follows the same convention as in `TransactionFailed`.
+    /// `retry_after` is the remaining cooldown in seconds, if the faucet said.
+    FaucetFailed { code: Code, message: String, retry_after: Option<u64> },
     
     /// Failed to decode hex-encoded key.
     KeyDecodingFailed { error: hex::FromHexError },
@@ -49,8 +119,20 @@ pub enum WalletError {
     /// Invalid private key format or content.
     InvalidPrivateKey { message: String },
 
+    /// Mnemonic phrase is malformed or has an invalid checksum.
+    InvalidMnemonic { message: String },
+
     /// Failed to sign transaction with private key.
     SigningFailed { message: String },
+
+    /// Signature is malformed or does not match the message and address.
+    SignatureInvalid { message: String },
+
+    /// Raw transaction is not hex or not a protobuf-encoded transaction.
+    InvalidRawTransaction { message: String },
+
+    /// Encrypted data could not be decrypted: wrong passphrase or tampered data.
+    DecryptionFailed { path: String },
     
     /// Error with system time operations.
     SystemTimeError { message: String },
@@ -65,10 +147,30 @@ impl fmt::Display for WalletError {
         match self {
             WalletError::WalletExists(name) => 
                 write!(f, "Wallet '{}' already exists", name),
+            WalletError::DuplicateKey { existing_name } => 
+                write!(f, "This key is already stored as wallet '{}'; pass --allow-duplicate to add it anyway", existing_name),
             WalletError::WalletNotFound(name) => 
                 write!(f, "Wallet '{}' not found", name),
             WalletError::AddressInvalid(address) => 
                 write!(f, "Invalid address: {}", address),
+            WalletError::AmbiguousRecipient { name, choices } => 
+                write!(f, "'{}' refers to different addresses; use {} to choose one", name, choices.join(" or ")),
+            WalletError::WatchOnly(name) => 
+                write!(f, "Wallet '{}' is watch-only and cannot sign", name),
+            WalletError::NoDefaultWallet => 
+                write!(f, "No wallet given and no default wallet set; pass a wallet name or run 'mockallet set-default <name>'"),
+            WalletError::ContactExists(alias) => 
+                write!(f, "Contact '{}' already exists", alias),
+            WalletError::ContactNotFound(alias) => 
+                write!(f, "Contact '{}' not found", alias),
+            WalletError::InvalidTransactionId(id) => 
+                write!(f, "Invalid transaction id: {}", id),
+            WalletError::InvalidArgument { message } => 
+                write!(f, "Invalid argument: {}", message),
+            WalletError::ChainInconsistent { index, reason } => 
+                write!(f, "Chain is inconsistent at block {}: {}", index, reason),
+            WalletError::InvalidAmount { input, message } => 
+                write!(f, "Invalid amount '{}': {}", input, message),
                 
             WalletError::StorageRead { path, error } => 
                 write!(f, "Failed to read from {}: {}", path, error),
@@ -76,26 +178,58 @@ impl fmt::Display for WalletError {
                 write!(f, "Failed to write to {}: {}", path, error),
             WalletError::StorageCreate { path, error } => 
                 write!(f, "Failed to create {}: {}", path, error),
+            WalletError::StorageLocked { path } => 
+                write!(f, "Wallet storage is locked by another mockallet process ({}); try again when it finishes", path),
             WalletError::JsonParse { error } => 
                 write!(f, "Failed to parse JSON: {}", error),
             WalletError::JsonSerialize { error } => 
                 write!(f, "Failed to serialize to JSON: {}", error),
                 
+            WalletError::InvalidEndpoint { url, message } => 
+                write!(f, "Invalid server URL '{}': {}", url, message),
+            WalletError::TlsConfig { message } => 
+                write!(f, "Invalid TLS configuration: {}", message),
+            WalletError::ProxyConfig { message } => 
+                write!(f, "Invalid proxy configuration: {}", message),
             WalletError::ConnectionFailed { error } => 
                 write!(f, "Failed to connect to blockchain service: {}", error),
+            WalletError::AllEndpointsFailed { attempted } => 
+                write!(f, "Could not reach any blockchain service (tried {})", attempted.join(", ")),
+            WalletError::Timeout { operation } => 
+                write!(f, "Timed out: {}", operation),
             WalletError::RpcError { status } => 
                 write!(f, "RPC error: {}", status),
-            WalletError::TransactionFailed { message } => 
+            WalletError::InvalidTemplate { problems } => 
+                write!(f, "Invalid transaction template: {}", problems.join("; ")),
+            WalletError::InvalidBatchFile { path, problems } => 
+                write!(f, "Invalid batch file {}: {}", path, problems.join("; ")),
+            WalletError::TransactionFailed { message, .. } => 
                 write!(f, "Transaction failed: {}", message),
-            WalletError::FaucetFailed { message } => 
+            WalletError::SelfSend => 
+                write!(f, "Sender and recipient are the same address; pass --allow-self to send anyway"),
+            WalletError::InsufficientFunds { needed, available } => 
+                write!(f, "Insufficient funds: {} needed but only {} available; request coins with 'faucet' or send less", needed, available),
+            WalletError::DuplicateTransaction { id } => 
+                write!(f, "Transaction {} was already submitted", id),
+            WalletError::FaucetFailed { message, retry_after: Some(secs), .. } => 
+                write!(f, "Faucet request failed: {}; try again in {}s", message, secs),
+            WalletError::FaucetFailed { message, retry_after: None, .. } => 
                 write!(f, "Faucet request failed: {}", message),
                 
             WalletError::KeyDecodingFailed { error } => 
                 write!(f, "Failed to decode key: {}", error),
             WalletError::InvalidPrivateKey { message } => 
                 write!(f, "Invalid private key: {}", message),
+            WalletError::InvalidMnemonic { message } => 
+                write!(f, "Invalid mnemonic: {}", message),
             WalletError::SigningFailed { message } => 
                 write!(f, "Failed to sign transaction: {}", message),
+            WalletError::SignatureInvalid { message } => 
+                write!(f, "Invalid signature: {}", message),
+            WalletError::InvalidRawTransaction { message } => 
+                write!(f, "Invalid raw transaction: {}", message),
+            WalletError::DecryptionFailed { path } => 
+                write!(f, "Failed to decrypt {}: wrong passphrase, or the file was modified", path),
                 
             WalletError::SystemTimeError { message } => 
                 write!(f, "System time error: {}", message),
@@ -103,18 +237,24 @@ impl fmt::Display for WalletError {
     }
 }
 
-impl std::error::Error for WalletError {}
-
-/// Conversions from other error types to WalletError
-impl From<io::Error> for WalletError {
-    fn from(error: io::Error) -> Self {
-        WalletError::StorageRead { 
-            path: "unknown".to_string(), 
-            error 
+/// Exposes the underlying error, for variants that wrap one, so callers
+/// can walk the chain of causes.
+impl std::error::Error for WalletError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WalletError::StorageRead { error, .. }
+            | WalletError::StorageWrite { error, .. }
+            | WalletError::StorageCreate { error, .. } => Some(error),
+            WalletError::JsonParse { error } | WalletError::JsonSerialize { error } => Some(error),
+            WalletError::ConnectionFailed { error } => Some(error),
+            WalletError::RpcError { status } => Some(status.as_ref()),
+            WalletError::KeyDecodingFailed { error } => Some(error),
+            _ => None,
         }
     }
 }
 
+/// Conversions from other error types to WalletError
 impl From<serde_json::Error> for WalletError {
     fn from(error: serde_json::Error) -> Self {
         WalletError::JsonParse { error }
@@ -127,9 +267,81 @@ impl From<tonic::transport::Error> for WalletError {
     }
 }
 
+impl WalletError {
+    /// Returns the gRPC status code behind the error, if it came from the node.
+    pub fn code(&self) -> Option<Code> {
+        match self {
+            WalletError::RpcError { status } => Some(status.code()),
+            WalletError::TransactionFailed { code, .. } | WalletError::FaucetFailed { code, .. } => Some(*code),
+            WalletError::InsufficientFunds { .. } => Some(Code::FailedPrecondition),
+            _ => None,
+        }
+    }
+    /// Returns the process exit code for the error, so scripts can tell
+    /// categories apart without parsing the message.
+    ///
+    /// * `2` - A wallet, contact or other item was not found
+    /// * `3` - The node could not be reached or did not answer in time
+    /// * `4` - The sender cannot cover the amount
+    /// * `5` - Local storage could not be read, written or locked
+    /// * `6` - An argument, file or key given on the command line is invalid
+    /// * `7` - The node rejected the request
+    /// * `1` - Anything else
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WalletError::WalletNotFound(_)
+            | WalletError::ContactNotFound(_)
+            | WalletError::NoDefaultWallet => 2,
+            WalletError::ConnectionFailed { .. }
+            | WalletError::AllEndpointsFailed { .. }
+            | WalletError::Timeout { .. } => 3,
+            WalletError::InsufficientFunds { .. } => 4,
+            WalletError::StorageRead { .. }
+            | WalletError::StorageWrite { .. }
+            | WalletError::StorageCreate { .. }
+            | WalletError::StorageLocked { .. } => 5,
+            WalletError::WalletExists(_)
+            | WalletError::DuplicateKey { .. }
+            | WalletError::ContactExists(_)
+            | WalletError::AddressInvalid(_)
+            | WalletError::AmbiguousRecipient { .. }
+            | WalletError::WatchOnly(_)
+            | WalletError::InvalidTransactionId(_)
+            | WalletError::InvalidArgument { .. }
+            | WalletError::InvalidAmount { .. }
+            | WalletError::InvalidEndpoint { .. }
+            | WalletError::TlsConfig { .. }
+            | WalletError::ProxyConfig { .. }
+            | WalletError::InvalidTemplate { .. }
+            | WalletError::InvalidRawTransaction { .. }
+            | WalletError::SelfSend
+            | WalletError::InvalidBatchFile { .. }
+            | WalletError::KeyDecodingFailed { .. }
+            | WalletError::InvalidPrivateKey { .. }
+            | WalletError::InvalidMnemonic { .. }
+            | WalletError::DecryptionFailed { .. } => 6,
+            WalletError::TransactionFailed { .. }
+            | WalletError::DuplicateTransaction { .. }
+            | WalletError::FaucetFailed { .. } => 7,
+            WalletError::RpcError { status } => match status.code() {
+                Code::NotFound => 2,
+                Code::Unavailable | Code::DeadlineExceeded => 3,
+                Code::InvalidArgument => 6,
+                _ => 7,
+            },
+            _ => 1,
+        }
+    }
+}
+
 impl From<Status> for WalletError {
     fn from(status: Status) -> Self {
-        WalletError::RpcError { status }
+        match status.code() {
+            Code::DeadlineExceeded => WalletError::Timeout { 
+                operation: status.message().to_string() 
+            },
+            _ => WalletError::RpcError { status: Box::new(status) },
+        }
     }
 }
 
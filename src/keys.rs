@@ -0,0 +1,303 @@
+use crate::address::normalize_hex;
+use crate::errors::{Result, WalletError};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use pkcs8::der::oid::ObjectIdentifier;
+use pkcs8::der::pem;
+use pkcs8::PrivateKeyInfo;
+use sec1::EcPrivateKey;
+use secp256k1::SecretKey;
+use serde::Deserialize;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// WIF version byte for mainnet-style private keys.
+const WIF_VERSION: u8 = 0x80;
+
+/// WIF suffix marking the key's public key as compressed.
+const WIF_COMPRESSED: u8 = 0x01;
+
+/// Object identifier of elliptic-curve public key algorithms in PKCS#8.
+const EC_PUBLIC_KEY_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+
+/// Object identifier of the secp256k1 curve.
+const SECP256K1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.10");
+
+/// AES-128 in big-endian counter mode, the cipher of version 3 keystores.
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// Encodings a private key can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// Hex-encoded 32-byte secret
+    Hex,
+    /// Wallet Import Format (base58check, compressed)
+    Wif,
+}
+
+impl FromStr for KeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(KeyFormat::Hex),
+            "wif" => Ok(KeyFormat::Wif),
+            other => Err(format!("unknown key format '{}', expected hex or wif", other)),
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyFormat::Hex => write!(f, "hex"),
+            KeyFormat::Wif => write!(f, "wif"),
+        }
+    }
+}
+
+/// Encodes a private key in the given format.
+///
+/// The encoding and the raw bytes it is built from are erased from memory
+/// when dropped.
+pub fn encode_secret_key(secret_key: &SecretKey, format: KeyFormat) -> Zeroizing<String> {
+    let secret = Zeroizing::new(secret_key.secret_bytes());
+    match format {
+        KeyFormat::Hex => Zeroizing::new(hex::encode(secret.as_slice())),
+        KeyFormat::Wif => {
+            let mut payload = Zeroizing::new(Vec::with_capacity(34));
+            payload.push(WIF_VERSION);
+            payload.extend_from_slice(secret.as_slice());
+            payload.push(WIF_COMPRESSED);
+            Zeroizing::new(bs58::encode(payload.as_slice()).with_check().into_string())
+        }
+    }
+}
+
+/// Decodes a private key given as hex or WIF.
+///
+/// A 64-character hex string, or anything with a `0x` prefix, is read as a
+/// raw secret; anything else is decoded as a base58check WIF key. Surrounding
+/// whitespace is ignored.
+///
+/// # Arguments
+///
+/// * `input` - The encoded private key
+///
+/// # Returns
+///
+/// * `Ok(SecretKey)` - The decoded private key
+/// * `Err(WalletError::KeyDecodingFailed)` - If hex input has a character that is not a hex digit
+/// * `Err(WalletError::InvalidPrivateKey)` - If the input is not a valid key in either format
+pub fn decode_secret_key(input: &str) -> Result<SecretKey> {
+    let invalid = |message: String| WalletError::InvalidPrivateKey { message };
+
+    let input = input.trim();
+    let hex_like = input.starts_with("0x") || input.starts_with("0X");
+    let bytes = if hex_like || (input.len() == 64 && input.chars().all(|c| c.is_ascii_hexdigit())) {
+        let bytes = Zeroizing::new(hex::decode(Zeroizing::new(normalize_hex(input)?).as_str())?);
+        if bytes.len() != 32 {
+            return Err(invalid(format!("{} bytes, expected 32", bytes.len())));
+        }
+        bytes
+    } else {
+        let payload = Zeroizing::new(
+            bs58::decode(input)
+                .with_check(None)
+                .into_vec()
+                .map_err(|e| invalid(format!("not hex or WIF: {}", e)))?,
+        );
+
+        match payload.as_slice() {
+            [WIF_VERSION, secret @ ..] if secret.len() == 32 => Zeroizing::new(secret.to_vec()),
+            [WIF_VERSION, secret @ .., WIF_COMPRESSED] if secret.len() == 32 => Zeroizing::new(secret.to_vec()),
+            _ => return Err(invalid("unsupported WIF version or length".to_string())),
+        }
+    };
+
+    SecretKey::from_slice(&bytes).map_err(|e| invalid(e.to_string()))
+}
+
+/// A version 3 keystore file, the JSON format most wallets export encrypted keys in.
+#[derive(Deserialize)]
+struct Keystore {
+    version: u32,
+    #[serde(alias = "Crypto")]
+    crypto: KeystoreCrypto,
+}
+
+#[derive(Deserialize)]
+struct KeystoreCrypto {
+    cipher: String,
+    cipherparams: KeystoreCipherParams,
+    /// Encrypted private key (hex-encoded)
+    ciphertext: String,
+    kdf: String,
+    kdfparams: serde_json::Value,
+    /// Keccak-256 of the second half of the derived key and the ciphertext (hex-encoded)
+    mac: String,
+}
+
+#[derive(Deserialize)]
+struct KeystoreCipherParams {
+    /// Counter-mode initial value (hex-encoded)
+    iv: String,
+}
+
+#[derive(Deserialize)]
+struct ScryptParams {
+    n: u64,
+    r: u32,
+    p: u32,
+    dklen: usize,
+    /// hex-encoded
+    salt: String,
+}
+
+#[derive(Deserialize)]
+struct Pbkdf2Params {
+    c: u32,
+    dklen: usize,
+    prf: String,
+    /// hex-encoded
+    salt: String,
+}
+
+/// Returns whether an imported key looks like a keystore file, which needs a passphrase.
+pub fn is_keystore(input: &str) -> bool {
+    input.trim_start().starts_with('{')
+}
+
+/// Decodes a private key given in any format `import-key` accepts.
+///
+/// The format is detected from the input: a PEM block is read as a SEC1
+/// (`EC PRIVATE KEY`) or unencrypted PKCS#8 (`PRIVATE KEY`) secp256k1 key,
+/// a JSON object as a version 3 keystore decrypted with `passphrase`, and
+/// anything else as hex or WIF.
+///
+/// # Arguments
+///
+/// * `input` - The encoded private key
+/// * `passphrase` - Passphrase of a keystore file, ignored for other formats
+///
+/// # Returns
+///
+/// * `Ok(SecretKey)` - The decoded private key
+/// * `Err(WalletError::InvalidPrivateKey)` - If the input is malformed or in an unsupported variant of its format, naming the detected format
+/// * `Err(WalletError::KeyDecodingFailed)` - If hex input has a character that is not a hex digit
+pub fn decode_imported_key(input: &str, passphrase: Option<&str>) -> Result<SecretKey> {
+    let input = input.trim();
+    if input.starts_with("-----BEGIN") {
+        decode_pem_key(input)
+    } else if is_keystore(input) {
+        let passphrase = passphrase.ok_or_else(|| WalletError::InvalidPrivateKey {
+            message: "keystore JSON needs a passphrase".to_string(),
+        })?;
+        decode_keystore(input, passphrase)
+    } else {
+        decode_secret_key(input)
+    }
+}
+
+/// Decodes a PEM-encoded SEC1 or PKCS#8 secp256k1 private key.
+fn decode_pem_key(input: &str) -> Result<SecretKey> {
+    let invalid = |message: String| WalletError::InvalidPrivateKey { message };
+
+    let (label, der) = pem::decode_vec(input.as_bytes()).map_err(|e| invalid(format!("malformed PEM: {}", e)))?;
+    let der = Zeroizing::new(der);
+    let (private_key, curve) = match label {
+        "EC PRIVATE KEY" => {
+            let key = EcPrivateKey::try_from(der.as_slice())
+                .map_err(|e| invalid(format!("PEM EC PRIVATE KEY is not valid SEC1: {}", e)))?;
+            (key.private_key, key.parameters.and_then(|parameters| parameters.named_curve()))
+        }
+        "PRIVATE KEY" => {
+            let info = PrivateKeyInfo::try_from(der.as_slice())
+                .map_err(|e| invalid(format!("PEM PRIVATE KEY is not valid PKCS#8: {}", e)))?;
+            if info.algorithm.oid != EC_PUBLIC_KEY_OID {
+                return Err(invalid(format!(
+                    "PEM PRIVATE KEY holds a {} key, not an elliptic-curve key",
+                    info.algorithm.oid
+                )));
+            }
+            let key = EcPrivateKey::try_from(info.private_key)
+                .map_err(|e| invalid(format!("PEM PRIVATE KEY does not contain a SEC1 key: {}", e)))?;
+            (key.private_key, info.algorithm.parameters_oid().ok())
+        }
+        "ENCRYPTED PRIVATE KEY" => {
+            return Err(invalid(
+                "PEM ENCRYPTED PRIVATE KEY is not supported, decrypt it first (openssl pkcs8 -in <file>)".to_string(),
+            ))
+        }
+        other => return Err(invalid(format!("PEM {} is not a private key", other))),
+    };
+
+    match curve {
+        Some(SECP256K1_OID) | None => {}
+        Some(other) => return Err(invalid(format!("PEM key is on curve {}, not secp256k1", other))),
+    }
+    SecretKey::from_slice(private_key).map_err(|e| invalid(format!("PEM key: {}", e)))
+}
+
+/// Decrypts a version 3 keystore with scrypt or PBKDF2 key derivation and AES-128-CTR.
+fn decode_keystore(input: &str, passphrase: &str) -> Result<SecretKey> {
+    let invalid = |message: String| WalletError::InvalidPrivateKey { message };
+    let field = |name: &str, value: &str| {
+        hex::decode(value.trim_start_matches("0x")).map_err(|e| invalid(format!("keystore JSON {} is not hex: {}", name, e)))
+    };
+
+    let keystore: Keystore =
+        serde_json::from_str(input).map_err(|e| invalid(format!("malformed keystore JSON: {}", e)))?;
+    if keystore.version != 3 {
+        return Err(invalid(format!("keystore JSON version {} is not supported, expected 3", keystore.version)));
+    }
+    let crypto = keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(invalid(format!("keystore JSON cipher {} is not supported, expected aes-128-ctr", crypto.cipher)));
+    }
+    let params = |kdf: &str| invalid(format!("keystore JSON has malformed {} parameters", kdf));
+
+    let mut derived = Zeroizing::new(Vec::new());
+    match crypto.kdf.as_str() {
+        "scrypt" => {
+            let kdf: ScryptParams = serde_json::from_value(crypto.kdfparams).map_err(|_| params("scrypt"))?;
+            if !kdf.n.is_power_of_two() || kdf.dklen < 32 {
+                return Err(params("scrypt"));
+            }
+            let scrypt_params = scrypt::Params::new(kdf.n.trailing_zeros() as u8, kdf.r, kdf.p, kdf.dklen)
+                .map_err(|_| params("scrypt"))?;
+            derived.resize(kdf.dklen, 0);
+            scrypt::scrypt(passphrase.as_bytes(), &field("salt", &kdf.salt)?, &scrypt_params, &mut derived)
+                .map_err(|_| params("scrypt"))?;
+        }
+        "pbkdf2" => {
+            let kdf: Pbkdf2Params = serde_json::from_value(crypto.kdfparams).map_err(|_| params("pbkdf2"))?;
+            if kdf.prf != "hmac-sha256" {
+                return Err(invalid(format!("keystore JSON pbkdf2 prf {} is not supported, expected hmac-sha256", kdf.prf)));
+            }
+            if kdf.dklen < 32 || kdf.c == 0 {
+                return Err(params("pbkdf2"));
+            }
+            derived.resize(kdf.dklen, 0);
+            pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &field("salt", &kdf.salt)?, kdf.c, &mut derived);
+        }
+        other => return Err(invalid(format!("keystore JSON kdf {} is not supported, expected scrypt or pbkdf2", other))),
+    }
+
+    let ciphertext = field("ciphertext", &crypto.ciphertext)?;
+    let mac = Keccak256::new()
+        .chain_update(&derived[16..32])
+        .chain_update(&ciphertext)
+        .finalize();
+    if mac.as_slice() != field("mac", &crypto.mac)?.as_slice() {
+        return Err(invalid("keystore JSON could not be decrypted: wrong passphrase or corrupted file".to_string()));
+    }
+
+    let iv = field("iv", &crypto.cipherparams.iv)?;
+    let mut cipher = Aes128Ctr::new_from_slices(&derived[..16], &iv).map_err(|_| params("cipher"))?;
+    let mut secret = Zeroizing::new(ciphertext);
+    cipher.apply_keystream(&mut secret);
+    SecretKey::from_slice(&secret).map_err(|e| invalid(format!("keystore JSON key: {}", e)))
+}
@@ -0,0 +1,462 @@
+//! In-process blockchain service for exercising `WalletClient` in tests.
+//!
+//! `MockServer::start` serves a scripted `BlockchainService` on an ephemeral
+//! local port. Tests program balances, blocks and failures through the
+//! server handle and inspect the requests it received afterwards.
+
+use crate::clock::SystemClock;
+use crate::models::{ClientConfig, TlsOptions};
+use crate::proto::blockchain::blockchain_service_server::{BlockchainService, BlockchainServiceServer};
+use crate::proto::blockchain::{
+    BalanceRequest, BalanceResponse, Block, FaucetRequest, FaucetResponse, GetBlockRequest,
+    GetBlockResponse, GetStateRequest, HistoryRequest, HistoryResponse, StateResponse,
+    Transaction, TransactionResponse, WatchBlocksRequest,
+};
+use crate::wallet::WalletClient;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, MutexGuard};
+use tonic::codegen::BoxStream;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+/// Coins credited by the mock faucet when no amount is requested.
+pub const FAUCET_AMOUNT: u64 = 100;
+
+/// Chain state and request log shared between the mock server and the test.
+#[derive(Default)]
+struct MockState {
+    /// Balance of each address; unknown addresses have none
+    balances: HashMap<String, u64>,
+    /// Blocks served by `GetState` and `GetBlock`, in index order
+    blocks: Vec<Block>,
+    /// Transactions accepted by `SubmitTransaction`, in order
+    submitted: Vec<Transaction>,
+    /// Names of the RPCs received, in order
+    calls: Vec<String>,
+    /// Errors to answer the next calls of each RPC with
+    failures: HashMap<String, VecDeque<Status>>,
+    /// How long to wait before answering any RPC
+    delay: Duration,
+    /// Answer to faucet requests instead of paying out, such as a cooldown
+    faucet_refusal: Option<FaucetResponse>,
+    /// Answers already given to faucet requests, by idempotency key
+    faucet_answers: HashMap<String, FaucetResponse>,
+    /// Whether faucet requests are paid out but answered with an unavailable error
+    lose_faucet_answers: bool,
+    /// Whether transactions are accepted but answered with an unavailable error
+    lose_submit_answers: bool,
+}
+
+/// The `BlockchainService` implementation behind `MockServer`.
+struct MockService {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockService {
+    /// Logs a call, then waits out the delay or fails as programmed.
+    async fn begin(&self, method: &str) -> Result<(), Status> {
+        let (delay, failure) = {
+            let mut state = self.state.lock().unwrap();
+            state.calls.push(method.to_string());
+            let failure = state.failures.get_mut(method).and_then(VecDeque::pop_front);
+            (state.delay, failure)
+        };
+        tokio::time::sleep(delay).await;
+        failure.map_or(Ok(()), Err)
+    }
+}
+
+#[tonic::async_trait]
+impl BlockchainService for MockService {
+    async fn submit_transaction(
+        &self,
+        request: Request<Transaction>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.begin("SubmitTransaction").await?;
+        let tx = request.into_inner();
+        let mut state = self.state.lock().unwrap();
+        if state.submitted.iter().any(|known| known.signature == tx.signature) {
+            return Err(Status::already_exists("transaction already known"));
+        }
+        let available = state.balances.get(&tx.from).copied().unwrap_or(0);
+        if available < tx.amount + tx.fee {
+            return Ok(Response::new(TransactionResponse {
+                success: false,
+                message: "insufficient funds".to_string(),
+            }));
+        }
+        *state.balances.entry(tx.from.clone()).or_default() -= tx.amount + tx.fee;
+        *state.balances.entry(tx.to.clone()).or_default() += tx.amount;
+        state.submitted.push(tx);
+        if state.lose_submit_answers {
+            return Err(Status::unavailable("connection reset"));
+        }
+        Ok(Response::new(TransactionResponse {
+            success: true,
+            message: "transaction accepted".to_string(),
+        }))
+    }
+
+    async fn get_balance(
+        &self,
+        request: Request<BalanceRequest>,
+    ) -> Result<Response<BalanceResponse>, Status> {
+        self.begin("GetBalance").await?;
+        let state = self.state.lock().unwrap();
+        let balance = state.balances.get(&request.into_inner().address).copied().unwrap_or(0);
+        Ok(Response::new(BalanceResponse { balance }))
+    }
+
+    async fn request_faucet(
+        &self,
+        request: Request<FaucetRequest>,
+    ) -> Result<Response<FaucetResponse>, Status> {
+        self.begin("RequestFaucet").await?;
+        let request = request.into_inner();
+        let mut state = self.state.lock().unwrap();
+        if let Some(refusal) = &state.faucet_refusal {
+            return Ok(Response::new(refusal.clone()));
+        }
+        let answer = match state.faucet_answers.get(&request.idempotency_key) {
+            Some(answer) => answer.clone(),
+            None => {
+                let amount = match request.amount {
+                    0 => FAUCET_AMOUNT,
+                    amount => amount,
+                };
+                *state.balances.entry(request.address).or_default() += amount;
+                let answer = FaucetResponse {
+                    success: true,
+                    amount,
+                    message: "funds sent".to_string(),
+                    retry_after: 0,
+                };
+                if !request.idempotency_key.is_empty() {
+                    state.faucet_answers.insert(request.idempotency_key, answer.clone());
+                }
+                answer
+            }
+        };
+        if state.lose_faucet_answers {
+            return Err(Status::unavailable("connection reset"));
+        }
+        Ok(Response::new(answer))
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<HistoryResponse>, Status> {
+        self.begin("GetHistory").await?;
+        let address = request.into_inner().address;
+        let state = self.state.lock().unwrap();
+        let transactions = state
+            .submitted
+            .iter()
+            .filter(|tx| tx.from == address || tx.to == address)
+            .cloned()
+            .collect();
+        Ok(Response::new(HistoryResponse { transactions }))
+    }
+
+    async fn get_state(
+        &self,
+        _request: Request<GetStateRequest>,
+    ) -> Result<Response<StateResponse>, Status> {
+        self.begin("GetState").await?;
+        let blocks = self.state.lock().unwrap().blocks.clone();
+        Ok(Response::new(StateResponse { blocks }))
+    }
+
+    async fn get_block(
+        &self,
+        request: Request<GetBlockRequest>,
+    ) -> Result<Response<GetBlockResponse>, Status> {
+        self.begin("GetBlock").await?;
+        let index = request.into_inner().index;
+        let state = self.state.lock().unwrap();
+        match state.blocks.iter().find(|block| block.index == index) {
+            Some(block) => Ok(Response::new(GetBlockResponse { block: Some(block.clone()) })),
+            None => Err(Status::not_found(format!("block {} not found", index))),
+        }
+    }
+
+    type WatchBlocksStream = BoxStream<Block>;
+
+    async fn watch_blocks(
+        &self,
+        _request: Request<WatchBlocksRequest>,
+    ) -> Result<Response<Self::WatchBlocksStream>, Status> {
+        self.begin("WatchBlocks").await?;
+        Err(Status::unimplemented("the mock service does not stream blocks"))
+    }
+}
+
+/// A running mock blockchain service, shut down when dropped.
+pub struct MockServer {
+    /// URL clients connect to
+    pub url: String,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Starts a mock service with an empty chain on an ephemeral port.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let service = MockService { state: state.clone() };
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(
+            Server::builder()
+                .add_service(BlockchainServiceServer::new(service))
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = stopped.await;
+                }),
+        );
+
+        MockServer {
+            url,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// Returns a wallet client for this server with fast retries and timeouts.
+    pub fn client(&self) -> WalletClient {
+        WalletClient::new(ClientConfig {
+            servers: vec![self.url.clone()],
+            trace_rpc: false,
+            max_retries: 3,
+            connect_timeout: Duration::from_secs(1),
+            call_timeout: Duration::from_secs(1),
+            tls: TlsOptions::default(),
+            proxy: None,
+            cache_ttl: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+            signers: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    /// Sets the balance of an address.
+    pub fn set_balance(&self, address: &str, balance: u64) {
+        self.state.lock().unwrap().balances.insert(address.to_string(), balance);
+    }
+
+    /// Returns the balance the mock holds for an address.
+    pub fn balance(&self, address: &str) -> u64 {
+        self.state.lock().unwrap().balances.get(address).copied().unwrap_or(0)
+    }
+
+    /// Adds a block to the chain.
+    pub fn push_block(&self, block: Block) {
+        self.state.lock().unwrap().blocks.push(block);
+    }
+
+    /// Makes the next call of `method` fail with `status`; queued failures are used in order.
+    pub fn fail_next(&self, method: &str, status: Status) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .entry(method.to_string())
+            .or_default()
+            .push_back(status);
+    }
+
+    /// Makes the faucet pay out but answer with an error, as if the answer were lost.
+    pub fn lose_faucet_answers(&self, lose: bool) {
+        self.state.lock().unwrap().lose_faucet_answers = lose;
+    }
+
+    /// Makes the node accept transactions but answer with an error, as if the answer were lost.
+    pub fn lose_submit_answers(&self, lose: bool) {
+        self.state.lock().unwrap().lose_submit_answers = lose;
+    }
+
+    /// Makes the faucet refuse every request with `message` and a cooldown of `retry_after` seconds.
+    pub fn refuse_faucet(&self, message: &str, retry_after: u64) {
+        self.state.lock().unwrap().faucet_refusal = Some(FaucetResponse {
+            success: false,
+            amount: 0,
+            message: message.to_string(),
+            retry_after,
+        });
+    }
+
+    /// Delays every response by `delay`.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Returns the transactions accepted so far, in order.
+    pub fn submitted(&self) -> Vec<Transaction> {
+        self.state.lock().unwrap().submitted.clone()
+    }
+
+    /// Returns the names of the RPCs received so far, in order.
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Which protocol a `MockProxy` speaks.
+#[derive(Clone, Copy)]
+pub enum MockProxyKind {
+    /// HTTP `CONNECT` tunnels
+    Http,
+    /// SOCKS5 without authentication
+    Socks5,
+}
+
+/// A local proxy that tunnels to whatever it is asked for, shut down when dropped.
+pub struct MockProxy {
+    /// Proxy URL clients connect to
+    pub url: String,
+    /// Targets requested so far, as `host:port`
+    requests: Arc<Mutex<Vec<String>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockProxy {
+    /// Starts a proxy of the given kind on an ephemeral port.
+    pub async fn start(kind: MockProxyKind) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let scheme = match kind {
+            MockProxyKind::Http => "http",
+            MockProxyKind::Socks5 => "socks5h",
+        };
+        let url = format!("{}://{}", scheme, listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let target = match kind {
+                        MockProxyKind::Http => read_connect_request(&mut client).await,
+                        MockProxyKind::Socks5 => read_socks5_request(&mut client).await,
+                    };
+                    let Some(target) = target else { return };
+                    log.lock().unwrap().push(target.clone());
+                    let Ok(mut server) = TcpStream::connect(target).await else { return };
+                    let reply: &[u8] = match kind {
+                        MockProxyKind::Http => b"HTTP/1.1 200 Connection established\r\n\r\n",
+                        MockProxyKind::Socks5 => &[5, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+                    };
+                    if client.write_all(reply).await.is_ok() {
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                    }
+                });
+            }
+        });
+        MockProxy { url, requests, task }
+    }
+
+    /// Returns the targets requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Reads an HTTP `CONNECT` request and returns its target.
+async fn read_connect_request(client: &mut TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        request.push(client.read_u8().await.ok()?);
+    }
+    let request = String::from_utf8(request).ok()?;
+    let mut words = request.split_whitespace();
+    (words.next()? == "CONNECT").then_some(())?;
+    words.next().map(str::to_string)
+}
+
+/// Reads a SOCKS5 greeting and connect request and returns the target.
+async fn read_socks5_request(client: &mut TcpStream) -> Option<String> {
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await.ok()?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    client.read_exact(&mut methods).await.ok()?;
+    client.write_all(&[5, 0]).await.ok()?;
+
+    let mut header = [0u8; 4];
+    client.read_exact(&mut header).await.ok()?;
+    let host = match header[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await.ok()?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        3 => {
+            let mut name = vec![0u8; client.read_u8().await.ok()? as usize];
+            client.read_exact(&mut name).await.ok()?;
+            String::from_utf8(name).ok()?
+        }
+        _ => return None,
+    };
+    let port = client.read_u16().await.ok()?;
+    Some(format!("{}:{}", host, port))
+}
+
+/// Serializes tests that use the wallet directory, which is relative to the
+/// process-wide working directory.
+static WALLET_DIR_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Distinguishes the temporary directories of one test run.
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty working directory for one test, removed when dropped.
+///
+/// Holds a lock for its lifetime so only one test touches wallet storage
+/// at a time.
+pub struct TestDir {
+    path: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestDir {
+    /// Waits for other storage tests to finish, then enters a fresh directory.
+    pub async fn enter() -> Self {
+        let lock = WALLET_DIR_LOCK.lock().await;
+        let path = std::env::temp_dir().join(format!(
+            "mockallet-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        std::env::set_current_dir(&path).unwrap();
+        TestDir { path, _lock: lock }
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(std::env::temp_dir());
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,15 +1,68 @@
+use crate::clock::Clock;
+use crate::errors::WalletError;
+use crate::output::TransactionView;
+use crate::proxy::Proxy;
+use crate::signer::Signer;
+use crate::warnings::Warning;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 /// A cryptographic key pair for a wallet.
 ///
-/// Contains the private and public keys as hex-encoded strings.
+/// Contains the private and public keys as hex-encoded strings. Watch-only
+/// wallets have no private key and can be queried but not spent from,
+/// unless an external signer for their public key is configured. The
+/// private key is erased from memory when a key pair, or any clone of it,
+/// is dropped.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct KeyPair {
     /// The private key used for signing transactions (hex-encoded)
-    pub private_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<Zeroizing<String>>,
     /// The public key used as the wallet address (hex-encoded)
     pub public_key: String,
+    /// Whether the key was derived from a public demo seed
+    #[serde(default)]
+    pub demo: bool,
+    /// Whether the key was derived from a BIP39 mnemonic
+    #[serde(default)]
+    pub mnemonic: bool,
+    /// Nonce of the last transaction the node accepted from this wallet, 0 if none
+    #[serde(default)]
+    pub nonce: u64,
+    /// BIP32 chain code of a mnemonic wallet's master key (hex-encoded), needed to derive addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_code: Option<String>,
+    /// Where the key comes from, for addresses derived from another wallet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation: Option<Derivation>,
+    /// Free-form description shown in listings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Tags for grouping wallets, sorted and without duplicates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// When the wallet was added (unix seconds), 0 for wallets older than this field
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+/// Position of a derived address in its parent wallet's key tree.
+///
+/// Together with the parent's mnemonic this is enough to derive the same
+/// key again.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Derivation {
+    /// Name of the mnemonic wallet the key was derived from
+    pub parent: String,
+    /// Index of the address under the parent
+    pub index: u32,
+    /// Full BIP32 derivation path, such as `m/44'/1'/0'/0/3`
+    pub path: String,
 }
 
 /// Collection of wallets stored by name.
@@ -19,4 +72,393 @@ pub struct KeyPair {
 pub struct Wallets {
     /// Map of wallet names to key pairs
     pub wallets: HashMap<String, KeyPair>,
+    /// Damaged wallet files found and worked around while loading
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
+}
+
+/// Local notes attached to transactions.
+///
+/// Maps transaction ids (hex-encoded signatures) to the notes recorded
+/// for them. Notes are never sent to the blockchain.
+#[derive(Serialize, Deserialize, Default)]
+pub struct TxNotes {
+    /// Map of transaction ids to notes
+    pub notes: HashMap<String, Vec<String>>,
+}
+
+/// Named addresses of frequent recipients.
+///
+/// Maps contact aliases to hex-encoded public keys. Contacts can be used
+/// anywhere a recipient is expected.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AddressBook {
+    /// Map of aliases to addresses
+    pub contacts: HashMap<String, String>,
+}
+
+/// Everything stored in a wallet backup.
+///
+/// Serialized and encrypted as a whole, so one file restores a wallet store.
+#[derive(Serialize, Deserialize, Default)]
+pub struct WalletBackup {
+    /// When the backup was made (unix seconds)
+    pub created_at: u64,
+    /// Map of wallet names to key pairs
+    pub wallets: HashMap<String, KeyPair>,
+    /// Map of contact aliases to addresses
+    pub contacts: HashMap<String, String>,
+    /// User settings at the time of the backup
+    pub config: Config,
+}
+
+/// What `restore` did with the entries of a backup.
+#[derive(Default)]
+pub struct RestoreSummary {
+    /// Wallets that did not exist locally and were added
+    pub added: Vec<String>,
+    /// Local wallets that were overwritten with the backup's key
+    pub replaced: Vec<String>,
+    /// Wallets whose name was taken by a different key and were left alone
+    pub kept: Vec<String>,
+    /// Wallets already present with the same key
+    pub unchanged: Vec<String>,
+    /// Number of contacts added or overwritten
+    pub contacts: usize,
+}
+
+/// Balances recently fetched from the blockchain service.
+///
+/// Lets repeated balance lookups within the cache TTL skip the round-trip.
+/// Entries only apply to the server they were fetched from.
+#[derive(Serialize, Deserialize, Default)]
+pub struct BalanceCache {
+    /// URLs of the blockchain services the balances came from, comma-separated
+    pub server: String,
+    /// Map of addresses to their last fetched balance
+    pub balances: HashMap<String, CachedBalance>,
+}
+
+/// When each address last received faucet funds, and what it received.
+///
+/// Lets the wallet warn about repeated requests before the faucet refuses
+/// them, and retry a request whose answer was lost without being paid twice.
+#[derive(Serialize, Deserialize, Default)]
+pub struct FaucetLog {
+    /// Map of addresses to the time of their last successful request (unix seconds)
+    pub last_requests: HashMap<String, u64>,
+    /// Requests sent but not answered, by address; retried with the same key
+    #[serde(default)]
+    pub pending: HashMap<String, PendingFaucetRequest>,
+    /// Successful requests, oldest first
+    #[serde(default)]
+    pub receipts: Vec<FaucetReceipt>,
+}
+
+/// A faucet request that may or may not have been paid out.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingFaucetRequest {
+    /// Idempotency key the request was sent with
+    pub key: String,
+    /// Amount asked for, 0 for the faucet's default
+    pub amount: u64,
+    /// When the request was first sent (unix seconds)
+    pub requested_at: u64,
+}
+
+/// Funds received from the faucet.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FaucetReceipt {
+    /// The address that received the funds
+    pub address: String,
+    /// Amount received in base units
+    pub amount: u64,
+    /// When the request succeeded (unix seconds)
+    pub timestamp: u64,
+    /// Idempotency key the request was sent with
+    pub key: String,
+}
+
+/// Transactions submitted from this wallet directory that were not yet seen in a block.
+///
+/// The node has no mempool RPC, so `mempool` lists these instead, pruning
+/// the ones mined in the meantime.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PendingTransactions {
+    /// Submitted transactions, oldest first
+    pub transactions: Vec<TransactionView>,
+}
+
+/// A balance and when it was fetched.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CachedBalance {
+    /// Balance in base units
+    pub balance: u64,
+    /// When the balance was fetched (unix seconds)
+    pub fetched_at: u64,
+}
+
+/// A reusable transaction description loaded from a template file.
+///
+/// Every field is optional so command-line flags can fill in or override
+/// individual values before the template is validated. The file gives
+/// amounts in coins, like the command line; they are held here in base units.
+#[derive(Default)]
+pub struct TransactionTemplate {
+    /// Name of the sender's wallet
+    pub from: Option<String>,
+    /// Name or address of the recipient
+    pub to: Option<String>,
+    /// Amount to send, in base units
+    pub amount: Option<u64>,
+    /// Earliest time (unix seconds) the transaction may execute
+    pub not_before: Option<u64>,
+    /// Note from the sender stored with the transaction
+    pub memo: Option<String>,
+    /// Fee paid on top of the amount, in base units
+    pub fee: u64,
+}
+
+/// One payment in a batch send.
+#[derive(Clone)]
+pub struct BatchPayment {
+    /// Name or address of the recipient
+    pub to: String,
+    /// Amount to send, in base units
+    pub amount: u64,
+}
+
+/// Optional settings for sending a transaction.
+#[derive(Default, Clone)]
+pub struct SendOptions {
+    /// Earliest time (unix seconds) the transaction may execute, 0 for none
+    pub not_before: u64,
+    /// Note from the sender stored with the transaction, empty for none
+    pub memo: String,
+    /// Fee paid on top of the amount, 0 for none
+    pub fee: u64,
+    /// Submit without first checking that the sender can cover the amount
+    pub skip_balance_check: bool,
+    /// Sign even if the recipient is the sender's own address
+    pub allow_self: bool,
+}
+
+/// Filters and paging for a wallet's transaction history.
+///
+/// Filters are applied first, then `offset` and `limit` select a page of the
+/// remaining transactions in oldest-first order.
+#[derive(Default, Clone)]
+pub struct HistoryQuery {
+    /// Maximum number of transactions to return, `None` for all
+    pub limit: Option<usize>,
+    /// Number of transactions to skip
+    pub offset: usize,
+    /// Only include transactions at or after this time (unix seconds)
+    pub since: Option<u64>,
+    /// Only include transactions at or before this time (unix seconds)
+    pub until: Option<u64>,
+    /// Only include transactions of at least this amount (base units)
+    pub min_amount: Option<u64>,
+    /// Only include transactions of at most this amount (base units)
+    pub max_amount: Option<u64>,
+    /// Only include transactions from or to this wallet, contact or address
+    pub counterparty: Option<String>,
+}
+
+impl HistoryQuery {
+    /// Returns whether any filter is set, as opposed to paging only.
+    pub fn is_filtered(&self) -> bool {
+        self.since.is_some()
+            || self.until.is_some()
+            || self.min_amount.is_some()
+            || self.max_amount.is_some()
+            || self.counterparty.is_some()
+    }
+}
+
+/// Where a recipient name was found when resolving it to an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSource {
+    /// The name of a local wallet
+    Wallet,
+    /// An alias in the address book
+    Contact,
+    /// A public key given directly
+    Key,
+}
+
+impl AddressSource {
+    /// Prefix that restricts resolution to this source, e.g. `contact:alice`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            AddressSource::Wallet => "wallet:",
+            AddressSource::Contact => "contact:",
+            AddressSource::Key => "key:",
+        }
+    }
+}
+
+/// A recipient resolved to an address.
+#[derive(Clone, Debug)]
+pub struct ResolvedAddress {
+    /// The address (public key, hex-encoded)
+    pub address: String,
+    /// Where the name was found
+    pub source: AddressSource,
+}
+
+/// Which way a transaction moved funds, seen from the addresses a history belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Paid from one of the addresses to someone else
+    Sent,
+    /// Paid to one of the addresses by someone else
+    Received,
+    /// Paid between the addresses, so only the fee left them
+    #[serde(rename = "self")]
+    SelfTransfer,
+}
+
+impl Direction {
+    /// Returns the label shown in text and table output.
+    pub fn label(self) -> &'static str {
+        match self {
+            Direction::Sent => "SENT",
+            Direction::Received => "RECEIVED",
+            Direction::SelfTransfer => "SELF",
+        }
+    }
+}
+
+/// What `repair` did with one wallet salvaged from a damaged file.
+pub struct RepairedWallet {
+    /// Name the wallet was stored under in the damaged file
+    pub name: String,
+    /// The wallet's address, empty if its key could not be read
+    pub address: String,
+    /// Why the wallet was not restored, `None` if it was
+    pub skipped: Option<String>,
+}
+
+/// A problem `doctor` found in local storage.
+pub enum StoreProblem {
+    /// Several wallets hold the same public key, so its balance and history
+    /// are attributed to whichever name is found first
+    DuplicateKey { address: String, names: Vec<String> },
+    /// A wallet's stored public key is not the one its private key derives,
+    /// so it signs for another account than the one it shows and queries
+    KeyMismatch { name: String, stored: String, derived: String },
+}
+
+/// Summary of the blockchain's current state.
+pub struct ChainInfo {
+    /// Number of blocks in the chain
+    pub height: u64,
+    /// Number of transactions in all blocks
+    pub transactions: u64,
+    /// Timestamp of the latest block, `None` for an empty chain
+    pub latest_timestamp: Option<i64>,
+    /// Hash of the latest block, `None` for an empty chain
+    pub latest_hash: Option<String>,
+}
+
+/// Why a block failed chain verification.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChainFault {
+    /// The block's index does not follow the previous block's
+    IndexGap { previous: u64 },
+    /// The block's previous hash is not the hash of the block before it
+    BrokenLink { expected: String, found: String },
+}
+
+impl fmt::Display for ChainFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainFault::IndexGap { previous } =>
+                write!(f, "it does not follow block {}", previous),
+            ChainFault::BrokenLink { expected, found } =>
+                write!(f, "its previous hash {:?} is not the previous block's hash {:?}", found, expected),
+        }
+    }
+}
+
+/// Result of checking that the chain links up.
+pub struct ChainVerification {
+    /// Number of blocks checked
+    pub blocks: u64,
+    /// Index of the first inconsistent block and what is wrong with it, `None` if the chain is consistent
+    pub first_invalid: Option<(u64, ChainFault)>,
+}
+
+/// Balances of every local wallet and their sum.
+pub struct BalanceSummary {
+    /// Wallets whose balance was retrieved, sorted by name
+    pub balances: Vec<(String, u64)>,
+    /// Wallets whose balance could not be retrieved, sorted by name
+    pub failures: Vec<(String, WalletError)>,
+    /// Sum of the retrieved balances
+    pub total: u64,
+}
+
+/// Settings for the wallet client's connection to the blockchain service.
+#[derive(Clone)]
+pub struct ClientConfig {
+    /// URLs of the blockchain services, tried in order until one works
+    pub servers: Vec<String>,
+    /// Dump every gRPC request and response to stderr
+    pub trace_rpc: bool,
+    /// Maximum number of retries for transient RPC failures
+    pub max_retries: u32,
+    /// Time allowed to establish the connection
+    pub connect_timeout: Duration,
+    /// Time allowed for each RPC attempt
+    pub call_timeout: Duration,
+    /// TLS settings for https:// endpoints
+    pub tls: TlsOptions,
+    /// Proxy to connect through, if any
+    pub proxy: Option<Proxy>,
+    /// How long fetched balances are reused; zero disables the cache
+    pub cache_ttl: Duration,
+    /// Source of the timestamps put on new transactions and cache entries
+    pub clock: Arc<dyn Clock>,
+    /// External signers, used instead of a stored key for wallets with their public key
+    pub signers: Vec<Arc<dyn Signer>>,
+}
+
+/// TLS certificate files for connecting to an https:// endpoint.
+///
+/// All paths are optional; without a CA certificate the system roots are used.
+#[derive(Default, Clone)]
+pub struct TlsOptions {
+    /// PEM file with the CA certificate that signed the server's certificate
+    pub ca_cert: Option<String>,
+    /// PEM file with the client certificate for mutual TLS
+    pub client_cert: Option<String>,
+    /// PEM file with the client's private key for mutual TLS
+    pub client_key: Option<String>,
+}
+
+impl TlsOptions {
+    /// Returns whether any TLS option was given.
+    pub fn is_set(&self) -> bool {
+        self.ca_cert.is_some() || self.client_cert.is_some() || self.client_key.is_some()
+    }
+}
+
+/// User settings stored in the wallet directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    /// URL of the blockchain service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Number of decimal places in one coin, used to format and parse amounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u32>,
+    /// Wallet used when a command's wallet argument is omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_wallet: Option<String>,
+    /// Server that last accepted a connection, tried first when several are configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_endpoint: Option<String>,
 }
@@ -0,0 +1,275 @@
+use crate::amount::format_amount;
+use crate::errors::WalletError;
+use crate::proto::blockchain::{Block, Transaction};
+use crate::warnings::Warning;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How command results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    Text,
+    /// A single JSON object on stdout
+    Json,
+    /// One JSON object per line: an object per transaction or block for
+    /// listings and for `watch-blocks` and `follow`, printed as they arrive,
+    /// and the `Json` object otherwise
+    JsonLines,
+    /// Aligned tables for listings, text for everything else
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "table" => Ok(OutputFormat::Table),
+            other => Err(format!("unknown output format '{}', expected text, json, jsonl or table", other)),
+        }
+    }
+}
+
+/// The part of a command's result that `--quiet` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Essential {
+    /// Nothing: the exit code alone tells whether the command succeeded
+    Nothing,
+    /// A field of the result, or of each entry when the result is a list
+    Field(&'static str),
+    /// An amount field, in display units like the text output
+    Amount(&'static str),
+    /// The whole result, as one line of JSON
+    Whole,
+}
+
+/// JSON representation of a transaction.
+///
+/// Also the format of signed transaction files written by `sign`.
+#[derive(Serialize, Deserialize)]
+pub struct TransactionView {
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub not_before: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub memo: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub fee: u64,
+    /// Hex-encoded signature, which doubles as the transaction id
+    pub signature: String,
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(tx: &Transaction) -> Self {
+        TransactionView {
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            amount: tx.amount,
+            timestamp: tx.timestamp,
+            not_before: tx.not_before,
+            memo: tx.memo.clone(),
+            nonce: tx.nonce,
+            fee: tx.fee,
+            signature: hex::encode(&tx.signature),
+        }
+    }
+}
+
+impl TryFrom<TransactionView> for Transaction {
+    type Error = WalletError;
+
+    fn try_from(view: TransactionView) -> Result<Self, Self::Error> {
+        Ok(Transaction {
+            from: view.from,
+            to: view.to,
+            amount: view.amount,
+            timestamp: view.timestamp,
+            not_before: view.not_before,
+            memo: view.memo,
+            nonce: view.nonce,
+            fee: view.fee,
+            signature: hex::decode(&view.signature)?,
+        })
+    }
+}
+
+/// JSON representation of a block.
+#[derive(Serialize)]
+pub struct BlockView {
+    pub index: u64,
+    pub timestamp: i64,
+    pub hash: String,
+    pub previous_hash: String,
+    pub nonce: u64,
+    pub miner: String,
+    pub transactions: Vec<TransactionView>,
+}
+
+impl From<&Block> for BlockView {
+    fn from(block: &Block) -> Self {
+        BlockView {
+            index: block.index,
+            timestamp: block.timestamp,
+            hash: block.hash.clone(),
+            previous_hash: block.previous_hash.clone(),
+            nonce: block.nonce,
+            miner: block.miner.clone(),
+            transactions: block.transactions.iter().map(TransactionView::from).collect(),
+        }
+    }
+}
+
+/// A plain ASCII table with a header row.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Creates an empty table with the given column headers.
+    pub fn new(headers: &[&str]) -> Self {
+        Table {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Appends a row. Missing cells are left blank and extra cells are dropped.
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Prints the table to stdout with every column padded to its widest cell.
+    pub fn print(&self) {
+        let cell = |row: &[String], i: usize| row.get(i).cloned().unwrap_or_default();
+        let widths: Vec<usize> = (0..self.headers.len())
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .map(|row| cell(row, i).chars().count())
+                    .chain(std::iter::once(self.headers[i].chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let separator = widths
+            .iter()
+            .map(|w| "-".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join("+");
+        let format_row = |row: &[String]| {
+            widths
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let value = cell(row, i);
+                    let padding = w - value.chars().count();
+                    format!(" {}{} ", value, " ".repeat(padding))
+                })
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+
+        println!("+{}+", separator);
+        println!("|{}|", format_row(&self.headers));
+        println!("+{}+", separator);
+        for row in &self.rows {
+            println!("|{}|", format_row(row));
+        }
+        println!("+{}+", separator);
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Prints a command result as JSON on stdout.
+///
+/// Any warnings raised by the command are added under a `warnings` key so
+/// scripts can inspect them without parsing stderr.
+pub fn print_json(result: Value, warnings: &[Warning]) {
+    let output = if warnings.is_empty() {
+        result
+    } else {
+        match result {
+            Value::Object(mut object) => {
+                object.insert("warnings".to_string(), json!(warnings));
+                Value::Object(object)
+            }
+            other => json!({ "result": other, "warnings": warnings }),
+        }
+    };
+    println!("{}", output);
+}
+
+/// Prints one item of a `--output jsonl` stream and flushes it.
+///
+/// The item goes out as soon as it is printed, so consumers can process it
+/// while the command is still running.
+pub fn print_json_line<T: Serialize>(item: &T) {
+    let mut stdout = io::stdout().lock();
+    let _ = serde_json::to_writer(&mut stdout, item);
+    let _ = writeln!(stdout);
+    let _ = stdout.flush();
+}
+
+/// Prints only the essential part of a command result, for `--quiet`.
+///
+/// Strings are printed bare, lists one entry per line and objects as a line
+/// of JSON. A missing or null field prints nothing, so an aborted or empty
+/// result leaves stdout empty.
+pub fn print_essential(result: &Value, essential: Essential, decimals: u32) {
+    let (key, amount) = match essential {
+        Essential::Nothing => return,
+        Essential::Whole => return println!("{}", result),
+        Essential::Field(key) => (key, false),
+        Essential::Amount(key) => (key, true),
+    };
+    let values = match result {
+        Value::Array(entries) => entries.iter().map(|entry| &entry[key]).collect(),
+        result => vec![&result[key]],
+    };
+    for value in values {
+        match value {
+            Value::Array(items) => items.iter().for_each(print_essential_value),
+            Value::Number(number) if amount => {
+                if let Some(raw) = number.as_u64() {
+                    println!("{}", format_amount(raw, decimals));
+                }
+            }
+            value => print_essential_value(value),
+        }
+    }
+}
+
+fn print_essential_value(value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::String(string) => println!("{}", string),
+        value => println!("{}", value),
+    }
+}
+
+/// Prints an error as JSON on stdout.
+///
+/// Errors returned by the node also carry their gRPC status code, such as
+/// `"FailedPrecondition"`, so scripts can branch on it.
+pub fn print_json_error(error: &WalletError) {
+    let mut value = json!({ "error": error.to_string() });
+    if let Some(code) = error.code() {
+        value["code"] = json!(format!("{:?}", code));
+    }
+    println!("{}", value);
+}
@@ -0,0 +1,43 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+/// Progress bar on stderr for operations that walk the chain.
+///
+/// Shows a spinner until the client reports a total, then a bar of blocks
+/// done out of that total. Hidden entirely for JSON output or when stderr
+/// is not a terminal, so scripts never see it. Cleared when dropped.
+pub struct ChainProgress {
+    bar: ProgressBar,
+}
+
+impl ChainProgress {
+    /// Starts a spinner with `message`, or a hidden bar if `enabled` is false
+    /// or stderr is not a terminal.
+    pub fn start(enabled: bool, message: &str) -> Self {
+        if !enabled || !io::stderr().is_terminal() {
+            return ChainProgress { bar: ProgressBar::hidden() };
+        }
+        let bar = ProgressBar::new_spinner().with_message(message.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        ChainProgress { bar }
+    }
+
+    /// Records that `done` of `total` blocks have been processed.
+    pub fn update(&self, done: u64, total: u64) {
+        if self.bar.length().is_none() {
+            let style = ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} blocks")
+                .expect("progress template is valid")
+                .progress_chars("=> ");
+            self.bar.set_style(style);
+        }
+        self.bar.set_length(total);
+        self.bar.set_position(done);
+    }
+}
+
+impl Drop for ChainProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,63 @@
+use crate::errors::{Result, WalletError};
+use image::Luma;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use std::io;
+
+/// Size in pixels of one QR module in saved PNG images.
+const PNG_MODULE_PIXELS: u32 = 8;
+
+/// Encodes text as a QR code.
+fn encode(data: &str) -> Result<QrCode> {
+    QrCode::new(data.as_bytes()).map_err(|e| WalletError::InvalidArgument {
+        message: format!("cannot encode '{}' as a QR code: {}", data, e),
+    })
+}
+
+/// Renders text as a QR code made of Unicode half blocks for the terminal.
+///
+/// Colors are inverted so the code scans on the usual dark terminal
+/// background, and the quiet zone around it is kept.
+///
+/// # Arguments
+///
+/// * `data` - The text to encode
+///
+/// # Returns
+///
+/// * `Ok(String)` - The QR code as printable lines
+/// * `Err(WalletError::InvalidArgument)` - If the text is too long to encode
+pub fn render_terminal(data: &str) -> Result<String> {
+    Ok(encode(data)?
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+/// Saves text as a QR code PNG image.
+///
+/// # Arguments
+///
+/// * `data` - The text to encode
+/// * `path` - File to write the image to
+///
+/// # Returns
+///
+/// * `Ok(())` - If the image is written successfully
+/// * `Err(WalletError::InvalidArgument)` - If the text is too long to encode
+/// * `Err(WalletError::StorageWrite)` - If the image cannot be written
+pub fn save_png(data: &str, path: &str) -> Result<()> {
+    encode(data)?
+        .render::<Luma<u8>>()
+        .module_dimensions(PNG_MODULE_PIXELS, PNG_MODULE_PIXELS)
+        .build()
+        .save(path)
+        .map_err(|e| WalletError::StorageWrite {
+            path: path.to_string(),
+            error: match e {
+                image::ImageError::IoError(error) => error,
+                other => io::Error::other(other),
+            },
+        })
+}
@@ -0,0 +1,64 @@
+use crate::errors::{Result, WalletError};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use std::sync::Arc;
+
+/// A compact ECDSA signature: 32 bytes of `r` followed by 32 bytes of `s`.
+pub type CompactSignature = [u8; 64];
+
+/// Produces the signatures on the transactions a wallet sends.
+///
+/// Only the signing digest is handed over, so the private key never has to
+/// be in local storage; a hardware wallet can implement this by forwarding
+/// the digest to the device.
+pub trait Signer: Send + Sync {
+    /// Returns the public key the signatures verify against.
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs a 32-byte message digest.
+    fn sign(&self, message: &[u8]) -> Result<CompactSignature>;
+}
+
+impl<S: Signer + ?Sized> Signer for Arc<S> {
+    fn public_key(&self) -> PublicKey {
+        (**self).public_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<CompactSignature> {
+        (**self).sign(message)
+    }
+}
+
+/// Signs with a private key held in memory, such as one from a wallet's key file.
+pub struct LocalSigner {
+    secret_key: SecretKey,
+}
+
+impl LocalSigner {
+    /// Creates a signer for a private key.
+    pub fn new(secret_key: SecretKey) -> Self {
+        LocalSigner { secret_key }
+    }
+}
+
+/// Overwrites the key when the signer is no longer needed.
+impl Drop for LocalSigner {
+    fn drop(&mut self) {
+        self.secret_key.non_secure_erase();
+    }
+}
+
+impl Signer for LocalSigner {
+    fn public_key(&self) -> PublicKey {
+        self.secret_key.public_key(&Secp256k1::signing_only())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<CompactSignature> {
+        let message = Message::from_slice(message)
+            .map_err(|e| WalletError::SigningFailed {
+                message: e.to_string()
+            })?;
+        Ok(Secp256k1::signing_only()
+            .sign_ecdsa(&message, &self.secret_key)
+            .serialize_compact())
+    }
+}
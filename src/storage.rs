@@ -1,109 +1,283 @@
+use crate::address::normalize_address;
+use crate::amount::parse_amount;
 use crate::errors::{Result, WalletError};
-use crate::models::{KeyPair, Wallets};
-use secp256k1::PublicKey;
-use std::fs::{self, File};
+use crate::models::{AddressBook, BalanceCache, BatchPayment, Config, FaucetLog, FaucetReceipt, KeyPair, PendingFaucetRequest, PendingTransactions, TransactionTemplate, TxNotes, Wallets};
+use crate::output::TransactionView;
+use crate::proto::blockchain::Transaction;
+use crate::warnings::Warning;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use fs2::FileExt;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+use zeroize::Zeroizing;
+
+/// Wallet directory of earlier releases, relative to the working directory.
+///
+/// Still used when it exists and no other directory was chosen, and
+/// whenever `init` has not been called.
+const LEGACY_WALLET_DIR: &str = ".wallets";
+
+/// Environment variable that sets the wallet directory.
+const WALLET_DIR_ENV: &str = "MOCKCHAIN_WALLET_DIR";
+
+/// Name of the per-user wallet directory inside the config directory.
+const APP_DIR: &str = "mockchain-wallet";
 
-pub const WALLET_DIR: &str = ".wallets";
 const WALLET_FILE: &str = "wallets.json";
+const KEYS_DIR: &str = "keys";
+const TX_NOTES_FILE: &str = "tx_notes.json";
+const CONFIG_FILE: &str = "config.json";
+const ADDRESS_BOOK_FILE: &str = "addressbook.json";
+const BALANCE_CACHE_FILE: &str = "cache.json";
+const FAUCET_LOG_FILE: &str = "faucet.json";
+const PENDING_FILE: &str = "pending.json";
+const LOCK_FILE: &str = ".lock";
+
+/// Marks a wallet file that was moved aside because it could not be parsed.
+const CORRUPT_SUFFIX: &str = ".corrupt-";
+
+/// How long to wait for another process to release the storage lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to retry a contended storage lock.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static WALLET_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory all wallet data is read from and written to.
+///
+/// Must be called once at startup, before any storage is touched; until
+/// then the legacy `.wallets` in the working directory is used. Later calls
+/// are ignored.
+pub fn init(dir: PathBuf) {
+    debug!(path = %dir.display(), "using wallet directory");
+    let _ = WALLET_DIR.set(dir);
+}
+
+/// Returns the wallet directory in use.
+pub fn wallet_dir() -> &'static Path {
+    WALLET_DIR.get().map_or(Path::new(LEGACY_WALLET_DIR), PathBuf::as_path)
+}
+
+/// Chooses the wallet directory.
+///
+/// Uses, in priority order, the `--wallet-dir` flag, the
+/// `MOCKCHAIN_WALLET_DIR` environment variable, the legacy `.wallets` in the
+/// working directory if it exists, and finally `mockchain-wallet` in the
+/// per-user config directory (such as `~/.config/mockchain-wallet`).
+///
+/// # Arguments
+///
+/// * `flag` - The value of the `--wallet-dir` flag
+///
+/// # Returns
+///
+/// The directory to pass to `init`.
+pub fn resolve_wallet_dir(flag: Option<&str>) -> PathBuf {
+    if let Some(dir) = flag {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os(WALLET_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if Path::new(LEGACY_WALLET_DIR).is_dir() {
+        return PathBuf::from(LEGACY_WALLET_DIR);
+    }
+    // Without a home directory there is no config dir to use
+    directories::BaseDirs::new()
+        .map(|dirs| dirs.config_dir().join(APP_DIR))
+        .unwrap_or_else(|| PathBuf::from(LEGACY_WALLET_DIR))
+}
+
+/// Returns the path of a file in the wallet directory.
+fn storage_path(file_name: &str) -> String {
+    wallet_dir().join(file_name).to_string_lossy().into_owned()
+}
 
 impl Wallets {
     /// Loads wallet data from local storage.
     ///
-    /// Attempts to read wallet data from the wallet file in the .wallets directory.
-    /// If the directory or file doesn't exist, it creates an empty wallets collection.
+    /// Each wallet lives in its own file, `keys/<name>.json` in the wallet
+    /// directory, and all of them are merged into one collection. A legacy
+    /// single-file `wallets.json` is migrated to that layout first; the old
+    /// file is kept as `wallets.json.migrated`.
+    ///
+    /// A file that no longer parses does not stop the load. It is replaced
+    /// by its `.bak` copy if that still parses, and otherwise moved aside to
+    /// `<file>.corrupt-<unix time>` for `repair` and left out. Either way a
+    /// warning is recorded in `warnings`.
     ///
     /// # Returns
     ///
-    /// * `Ok(Wallets)` - The loaded wallets collection
-    /// * `Err(WalletError)` - If an error occurs while reading or parsing wallet data
+    /// * `Ok(Wallets)` - The loaded wallets collection, empty if no wallets exist yet
+    /// * `Err(WalletError)` - If an error occurs while reading or migrating wallet data
     pub fn load() -> Result<Self> {
-        // Create wallet directory if it doesn't exist
-        let wallet_path = Path::new(WALLET_DIR);
-        if !wallet_path.exists() {
-            fs::create_dir_all(wallet_path).map_err(|e| WalletError::StorageCreate {
-                path: WALLET_DIR.to_string(),
-                error: e,
-            })?;
-            return Ok(Self::default());
+        let mut wallets = Wallets::default();
+        if storage_file_exists(WALLET_FILE) {
+            let _lock = StorageLock::acquire()?;
+            // Another process may have finished the migration while we waited
+            if storage_file_exists(WALLET_FILE) {
+                migrate_wallet_file(&mut wallets.warnings)?;
+            }
         }
 
-        let wallet_file = format!("{}/{}", WALLET_DIR, WALLET_FILE);
-        let file_path = Path::new(&wallet_file);
-        if !file_path.exists() {
-            return Ok(Self::default());
+        let keys_dir = storage_path(KEYS_DIR);
+        if !Path::new(&keys_dir).exists() {
+            return Ok(wallets);
         }
 
-        let mut file = File::open(file_path).map_err(|e| WalletError::StorageRead {
-            path: wallet_file.clone(),
+        let entries = fs::read_dir(&keys_dir).map_err(|e| WalletError::StorageRead {
+            path: keys_dir.clone(),
             error: e,
         })?;
-
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| WalletError::StorageRead {
-                path: wallet_file,
-                error: e,
-            })?;
-
-        let wallets =
-            serde_json::from_str(&contents).map_err(|e| WalletError::JsonParse { error: e })?;
-
+        for entry in entries {
+            let path = entry
+                .map_err(|e| WalletError::StorageRead {
+                    path: keys_dir.clone(),
+                    error: e,
+                })?
+                .path();
+            // Skip anything that isn't a key file, such as editor leftovers
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if let Some(keypair) = read_key_file(&path.to_string_lossy(), &mut wallets.warnings)? {
+                    wallets.wallets.insert(name.to_string(), keypair);
+                }
+            }
+        }
         Ok(wallets)
     }
 
-    /// Saves wallet data to local storage.
+    /// Adds a new wallet to the collection and saves to disk.
+    ///
+    /// # Arguments
     ///
-    /// Serializes the wallets collection to JSON and writes it to the wallet file.
-    /// Creates the .wallets directory if it doesn't exist.
+    /// * `name` - The name to associate with the wallet
+    /// * `keypair` - The key pair for the wallet
+    /// * `allow_duplicate` - Whether to store the key even if another wallet already holds it
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the wallets are saved successfully
-    /// * `Err(WalletError)` - If an error occurs while writing wallet data
-    pub fn save(&self) -> Result<()> {
-        // Create wallet directory if it doesn't exist
-        let wallet_path = Path::new(WALLET_DIR);
-        if !wallet_path.exists() {
-            fs::create_dir_all(wallet_path).map_err(|e| WalletError::StorageCreate {
-                path: WALLET_DIR.to_string(),
-                error: e,
-            })?;
+    /// * `Ok(())` - If the wallet is added and saved successfully
+    /// * `Err(WalletError::WalletExists)` - If another process created the wallet in the meantime
+    /// * `Err(WalletError::DuplicateKey)` - If another wallet holds the same public key
+    /// * `Err(WalletError::InvalidArgument)` - If the name cannot be used as a file name
+    /// * `Err(WalletError::StorageLocked)` - If another process holds the storage lock too long
+    /// * `Err(WalletError)` - If an error occurs while saving  
+    pub fn add_wallet(&mut self, name: &str, keypair: KeyPair, allow_duplicate: bool) -> Result<()> {
+        let file_name = key_file(name)?;
+        let _lock = StorageLock::acquire()?;
+        if storage_file_exists(&file_name) {
+            return Err(WalletError::WalletExists(name.to_string()));
+        }
+        if !allow_duplicate {
+            if let Some(existing_name) = self.name_for_key(&keypair.public_key) {
+                return Err(WalletError::DuplicateKey { existing_name: existing_name.to_string() });
+            }
         }
 
-        let wallet_file = format!("{}/{}", WALLET_DIR, WALLET_FILE);
-        let mut file = File::create(&wallet_file).map_err(|e| WalletError::StorageWrite {
-            path: wallet_file.clone(),
-            error: e,
-        })?;
+        save_json(&file_name, &keypair)?;
+        self.wallets.insert(name.to_string(), keypair);
+        Ok(())
+    }
 
-        let json = serde_json::to_string_pretty(self)
-            .map_err(|e| WalletError::JsonSerialize { error: e })?;
+    /// Removes a wallet from the collection and saves to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet to remove
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the wallet is removed and saved successfully
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn remove_wallet(&mut self, name: &str) -> Result<()> {
+        let file_name = key_file(name)?;
+        let _lock = StorageLock::acquire()?;
+        if !self.wallets.contains_key(name) || !storage_file_exists(&file_name) {
+            return Err(WalletError::WalletNotFound(name.to_string()));
+        }
 
-        file.write_all(json.as_bytes())
-            .map_err(|e| WalletError::StorageWrite {
-                path: wallet_file.clone(),
-                error: e,
-            })?;
+        remove_file(&file_name)?;
+        self.wallets.remove(name);
+        Ok(())
+    }
 
+    /// Renames a wallet and saves to disk.
+    ///
+    /// The key pair is moved unchanged, so the wallet's address, balance and
+    /// history are unaffected.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - The current name of the wallet
+    /// * `new` - The new name for the wallet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the wallet is renamed and saved successfully
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet named `old` exists
+    /// * `Err(WalletError::WalletExists)` - If a wallet named `new` already exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn rename_wallet(&mut self, old: &str, new: &str) -> Result<()> {
+        if !self.wallets.contains_key(old) {
+            return Err(WalletError::WalletNotFound(old.to_string()));
+        }
+        if self.wallets.contains_key(new) {
+            return Err(WalletError::WalletExists(new.to_string()));
+        }
+
+        // Write the new file before removing the old one so the key is never missing
+        let new_file = key_file(new)?;
+        let _lock = StorageLock::acquire()?;
+        if storage_file_exists(&new_file) {
+            return Err(WalletError::WalletExists(new.to_string()));
+        }
+        if let Some(keypair) = self.wallets.get(old) {
+            save_json(&new_file, keypair)?;
+        }
+        remove_file(&key_file(old)?)?;
+        if let Some(keypair) = self.wallets.remove(old) {
+            self.wallets.insert(new.to_string(), keypair);
+        }
         Ok(())
     }
 
-    /// Adds a new wallet to the collection and saves to disk.
+    /// Changes a stored wallet and saves it to disk.
+    ///
+    /// The key file is re-read under the storage lock before the change is
+    /// applied, so changes another process made in the meantime are kept.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name to associate with the wallet
-    /// * `keypair` - The key pair for the wallet
+    /// * `name` - The name of the wallet to change
+    /// * `update` - Applies the change to the wallet's key pair
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the wallet is added and saved successfully
-    /// * `Err(WalletError)` - If an error occurs while saving  
-    pub fn add_wallet(&mut self, name: &str, keypair: KeyPair) -> Result<()> {
+    /// * `Ok(())` - If the wallet is changed and saved successfully
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError)` - If an error occurs while reading or saving the key file
+    pub fn update_wallet(&mut self, name: &str, update: impl FnOnce(&mut KeyPair)) -> Result<()> {
+        let file_name = key_file(name)?;
+        let _lock = StorageLock::acquire()?;
+        if !storage_file_exists(&file_name) {
+            return Err(WalletError::WalletNotFound(name.to_string()));
+        }
+
+        let mut keypair: KeyPair = read_json(&storage_path(&file_name))?;
+        update(&mut keypair);
+        save_json(&file_name, &keypair)?;
         self.wallets.insert(name.to_string(), keypair);
-        self.save()?;
         Ok(())
     }
 
@@ -121,39 +295,1008 @@ impl Wallets {
         self.wallets.get(name)
     }
 
-    /// Resolves a wallet name or public key to an address.
+    /// Finds the name of the wallet that owns an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key address to look up
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&str)` - The name of the wallet with that public key
+    /// * `None` - If no local wallet has that public key
+    pub fn name_for_address(&self, address: &str) -> Option<&str> {
+        self.wallets.iter()
+            .find(|(_, kp)| kp.public_key == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Finds the first wallet, by name, holding a public key in any encoding.
+    fn name_for_key(&self, public_key: &str) -> Option<&str> {
+        let key = canonical_key(public_key);
+        self.wallets.iter()
+            .filter(|(_, kp)| canonical_key(&kp.public_key) == key)
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+
+    /// Groups the wallets that hold the same public key.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(String, Vec<String>)>` - Each shared address with the sorted names holding it, by address
+    pub fn duplicate_keys(&self) -> Vec<(String, Vec<String>)> {
+        let mut by_key: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, keypair) in &self.wallets {
+            by_key.entry(canonical_key(&keypair.public_key)).or_default().push(name.clone());
+        }
+        by_key.into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(address, mut names)| {
+                names.sort();
+                (address, names)
+            })
+            .collect()
+    }
+}
+
+impl TxNotes {
+    /// Loads transaction notes from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(TxNotes)` - The loaded notes, or an empty collection if none exist yet
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the notes file
+    pub fn load() -> Result<Self> {
+        load_json(TX_NOTES_FILE)
+    }
+
+    /// Saves transaction notes to local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the notes are saved successfully
+    /// * `Err(WalletError)` - If an error occurs while writing the notes file
+    pub fn save(&self) -> Result<()> {
+        save_json(TX_NOTES_FILE, self)
+    }
+
+    /// Attaches a note to a transaction and saves to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The transaction id (hex-encoded signature)
+    /// * `note` - The note text
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the note is added and saved successfully
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn add_note(&mut self, id: &str, note: &str) -> Result<()> {
+        // Reload under the lock so notes added by another process are kept
+        let _lock = StorageLock::acquire()?;
+        *self = Self::load()?;
+        self.notes
+            .entry(id.to_string())
+            .or_default()
+            .push(note.to_string());
+        self.save()?;
+        Ok(())
+    }
+
+    /// Gets the notes attached to a transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The transaction id (hex-encoded signature)
+    ///
+    /// # Returns
+    ///
+    /// The notes for the transaction, empty if none were recorded.
+    pub fn get_notes(&self, id: &str) -> &[String] {
+        self.notes.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+impl Config {
+    /// Loads user settings from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Config)` - The loaded settings, or defaults if no config file exists
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the config file
+    pub fn load() -> Result<Self> {
+        load_json(CONFIG_FILE)
+    }
+
+    /// Saves user settings to local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the settings are saved successfully
+    /// * `Err(WalletError)` - If an error occurs while writing the config file
+    pub fn save(&self) -> Result<()> {
+        save_json(CONFIG_FILE, self)
+    }
+
+    /// Applies a change to the stored settings under the storage lock.
+    ///
+    /// The settings are reloaded first, so a concurrent change made by
+    /// another process is not overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - Modifies the settings; returns whether anything changed
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the settings were updated (or left as they were)
+    /// * `Err(WalletError::StorageLocked)` - If another process holds the storage lock too long
+    /// * `Err(WalletError)` - If an error occurs while reading or writing the config file
+    pub fn update(change: impl FnOnce(&mut Config) -> bool) -> Result<()> {
+        let _lock = StorageLock::acquire()?;
+        let mut settings = Self::load()?;
+        if change(&mut settings) {
+            settings.save()?;
+        }
+        Ok(())
+    }
+}
+
+impl BalanceCache {
+    /// Loads the balance cache from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BalanceCache)` - The cached balances, or an empty cache if none exist yet
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the cache file
+    pub fn load() -> Result<Self> {
+        load_json(BALANCE_CACHE_FILE)
+    }
+
+    /// Saves the balance cache to local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the cache is saved successfully
+    /// * `Err(WalletError)` - If an error occurs while writing the cache file
+    pub fn save(&self) -> Result<()> {
+        save_json(BALANCE_CACHE_FILE, self)
+    }
+}
+
+impl FaucetLog {
+    /// Loads the faucet log from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(FaucetLog)` - The recorded requests, or an empty log if none exist yet
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the log file
+    pub fn load() -> Result<Self> {
+        load_json(FAUCET_LOG_FILE)
+    }
+
+    /// Records a successful faucet request and saves the log.
+    ///
+    /// The log is re-read under the storage lock, so requests recorded by
+    /// another process in the meantime are kept. The address's pending
+    /// request, if any, has now been answered and is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `receipt` - The address, amount, time and key of the request
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the request is recorded successfully
+    /// * `Err(WalletError)` - If an error occurs while reading or writing the log file
+    pub fn record(&mut self, receipt: FaucetReceipt) -> Result<()> {
+        let _lock = StorageLock::acquire()?;
+        let mut log: FaucetLog = load_json(FAUCET_LOG_FILE)?;
+        log.last_requests.insert(receipt.address.clone(), receipt.timestamp);
+        log.pending.remove(&receipt.address);
+        log.receipts.push(receipt);
+        save_json(FAUCET_LOG_FILE, &log)?;
+        *self = log;
+        Ok(())
+    }
+
+    /// Sets or clears the request awaiting an answer for an address and saves the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address the request is for
+    /// * `request` - The request about to be sent, or `None` once it was answered
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the log is saved successfully
+    /// * `Err(WalletError)` - If an error occurs while reading or writing the log file
+    pub fn set_pending(&mut self, address: &str, request: Option<PendingFaucetRequest>) -> Result<()> {
+        let _lock = StorageLock::acquire()?;
+        let mut log: FaucetLog = load_json(FAUCET_LOG_FILE)?;
+        match request {
+            Some(request) => log.pending.insert(address.to_string(), request),
+            None => log.pending.remove(address),
+        };
+        save_json(FAUCET_LOG_FILE, &log)?;
+        *self = log;
+        Ok(())
+    }
+}
+
+impl PendingTransactions {
+    /// Loads the pending transactions from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PendingTransactions)` - The recorded transactions, or none if nothing is pending
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the pending file
+    pub fn load() -> Result<Self> {
+        load_json(PENDING_FILE)
+    }
+
+    /// Records a submitted transaction and saves the list.
+    ///
+    /// The list is re-read under the storage lock, so transactions recorded
+    /// by another process in the meantime are kept. A transaction already
+    /// in the list is not added twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction the node accepted
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the transaction is recorded successfully
+    /// * `Err(WalletError)` - If an error occurs while reading or writing the pending file
+    pub fn add(&mut self, transaction: &Transaction) -> Result<()> {
+        let _lock = StorageLock::acquire()?;
+        let mut pending: PendingTransactions = load_json(PENDING_FILE)?;
+        let view = TransactionView::from(transaction);
+        if !pending.transactions.iter().any(|tx| tx.signature == view.signature) {
+            pending.transactions.push(view);
+            save_json(PENDING_FILE, &pending)?;
+        }
+        *self = pending;
+        Ok(())
+    }
+
+    /// Drops the transactions that have been mined and saves the list.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_mined` - Tells whether the transaction with a hex-encoded signature is in a block
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of transactions dropped
+    /// * `Err(WalletError)` - If an error occurs while reading or writing the pending file
+    pub fn prune(&mut self, is_mined: impl Fn(&str) -> bool) -> Result<usize> {
+        let _lock = StorageLock::acquire()?;
+        let mut pending: PendingTransactions = load_json(PENDING_FILE)?;
+        let before = pending.transactions.len();
+        pending.transactions.retain(|tx| !is_mined(&tx.signature));
+        let pruned = before - pending.transactions.len();
+        if pruned > 0 {
+            save_json(PENDING_FILE, &pending)?;
+        }
+        *self = pending;
+        Ok(pruned)
+    }
+}
+
+impl AddressBook {
+    /// Loads the address book from local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AddressBook)` - The loaded contacts, or an empty book if none exist yet
+    /// * `Err(WalletError)` - If an error occurs while reading or parsing the address book
+    pub fn load() -> Result<Self> {
+        load_json(ADDRESS_BOOK_FILE)
+    }
+
+    /// Saves the address book to local storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the address book is saved successfully
+    /// * `Err(WalletError)` - If an error occurs while writing the address book
+    pub fn save(&self) -> Result<()> {
+        save_json(ADDRESS_BOOK_FILE, self)
+    }
+
+    /// Adds a contact and saves to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The name of the contact
+    /// * `address` - The contact's hex-encoded public key
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the contact is added and saved successfully
+    /// * `Err(WalletError::ContactExists)` - If a contact with the alias already exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn add_contact(&mut self, alias: &str, address: &str) -> Result<()> {
+        let _lock = StorageLock::acquire()?;
+        *self = Self::load()?;
+        if self.contacts.contains_key(alias) {
+            return Err(WalletError::ContactExists(alias.to_string()));
+        }
+
+        self.contacts.insert(alias.to_string(), address.to_string());
+        self.save()
+    }
+
+    /// Removes a contact and saves to disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The name of the contact to remove
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The address of the removed contact
+    /// * `Err(WalletError::ContactNotFound)` - If no contact with the alias exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn remove_contact(&mut self, alias: &str) -> Result<String> {
+        let _lock = StorageLock::acquire()?;
+        *self = Self::load()?;
+        let address = self.contacts.remove(alias)
+            .ok_or_else(|| WalletError::ContactNotFound(alias.to_string()))?;
+        self.save()?;
+        Ok(address)
+    }
+}
+
+/// A transaction template as written in its file; amounts may be numbers or decimal strings.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    from: Option<String>,
+    to: Option<String>,
+    amount: Option<serde_json::Value>,
+    not_before: Option<u64>,
+    memo: Option<String>,
+    fee: Option<serde_json::Value>,
+}
+
+impl TransactionTemplate {
+    /// Loads a transaction template from a JSON file.
     ///
-    /// Attempts to resolve the input as:
-    /// 1. A wallet name in the collection
-    /// 2. A public key that matches one of the wallets in the collection
-    /// 3. A valid public key in general
+    /// The amount and fee are given in coins, as on the command line and in
+    /// batch files, and converted to base units.
     ///
     /// # Arguments
     ///
-    /// * `name_or_key` - Wallet name or public key to resolve
+    /// * `path` - Path to the template file
+    /// * `decimals` - Number of decimal places amounts are given with
     ///
     /// # Returns
     ///
-    /// * `Some(String)` - The resolved public key address
-    /// * `None` - If the input cannot be resolved to a valid address
-    pub fn resolve_address(&self, name_or_key: &str) -> Option<String> {
-        // Check if it's a wallet name we know
-        if let Some(keypair) = self.wallets.get(name_or_key) {
-            return Some(keypair.public_key.clone());
+    /// * `Ok(TransactionTemplate)` - The parsed template
+    /// * `Err(WalletError::InvalidTemplate)` - If the amount or fee is not a valid amount, listing each
+    /// * `Err(WalletError)` - If the file cannot be read or is not a valid template
+    pub fn load(path: &str, decimals: u32) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| WalletError::StorageRead {
+            path: path.to_string(),
+            error: e,
+        })?;
+        let file: TemplateFile = serde_json::from_str(&contents).map_err(|e| WalletError::JsonParse { error: e })?;
+
+        let mut problems = Vec::new();
+        let mut parse = |field: &str, value: Option<serde_json::Value>| {
+            let amount = match value? {
+                serde_json::Value::String(amount) => amount,
+                other => other.to_string(),
+            };
+            parse_amount(&amount, decimals)
+                .map_err(|e| problems.push(format!("'{}': {}", field, e)))
+                .ok()
+        };
+        let amount = parse("amount", file.amount);
+        let fee = parse("fee", file.fee).unwrap_or(0);
+        if !problems.is_empty() {
+            return Err(WalletError::InvalidTemplate { problems });
+        }
+
+        Ok(TransactionTemplate {
+            from: file.from,
+            to: file.to,
+            amount,
+            not_before: file.not_before,
+            memo: file.memo,
+            fee,
+        })
+    }
+}
+
+/// Lists wallet files that were moved aside because they could not be parsed.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - Paths of the damaged files, sorted
+/// * `Err(WalletError::StorageRead)` - If the wallet directory cannot be read
+pub fn corrupt_wallet_files() -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for dir in [wallet_dir().to_string_lossy().into_owned(), storage_path(KEYS_DIR)] {
+        if !Path::new(&dir).exists() {
+            continue;
+        }
+        let entries = fs::read_dir(&dir).map_err(|e| WalletError::StorageRead {
+            path: dir.clone(),
+            error: e,
+        })?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| WalletError::StorageRead {
+                    path: dir.clone(),
+                    error: e,
+                })?
+                .path();
+            let is_wallet_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split_once(CORRUPT_SUFFIX))
+                .is_some_and(|(original, _)| original.ends_with(".json"));
+            if is_wallet_file {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Salvages whatever wallets can still be read from a damaged wallet file.
+///
+/// Works on both key files and legacy `wallets.json` files. Every JSON
+/// object that still parses as a key pair is kept, named after the key it
+/// is stored under or, for a key file, after the file. Private keys whose
+/// surrounding object is broken are kept on their own, with an empty public
+/// key for the caller to fill in. Wallets that cannot be named get
+/// `recovered-<n>`.
+///
+/// # Arguments
+///
+/// * `path` - Path of the damaged file
+///
+/// # Returns
+///
+/// * `Ok(Vec<(String, KeyPair)>)` - The salvaged wallets in file order, possibly empty
+/// * `Err(WalletError::StorageRead)` - If the file cannot be read
+pub fn salvage_wallets(path: &str) -> Result<Vec<(String, KeyPair)>> {
+    let bytes = fs::read(path).map_err(|e| WalletError::StorageRead {
+        path: path.to_string(),
+        error: e,
+    })?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    // A key file is named after its wallet; a legacy file names each entry
+    let file_name = Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let file_wallet = match file_name.split_once(".json") {
+        Some((stem, _)) if stem != "wallets" => Some(stem.to_string()),
+        _ => None,
+    };
+
+    let mut found: Vec<(Option<String>, KeyPair)> = Vec::new();
+    // Byte ranges of the objects already salvaged, so nested braces are skipped
+    let mut parsed: Vec<(usize, usize)> = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = text[start..].find('{') {
+        let open = start + offset;
+        let mut stream = serde_json::Deserializer::from_str(&text[open..]).into_iter::<KeyPair>();
+        match stream.next() {
+            Some(Ok(keypair)) => {
+                let end = open + stream.byte_offset();
+                found.push((preceding_key(&text, open), keypair));
+                parsed.push((open, end));
+                start = end;
+            }
+            _ => start = open + 1,
+        }
+    }
+
+    // Fall back to bare private keys for entries too damaged to parse whole
+    const PRIVATE_KEY_FIELD: &str = "\"private_key\"";
+    let mut start = 0;
+    while let Some(offset) = text[start..].find(PRIVATE_KEY_FIELD) {
+        let at = start + offset;
+        start = at + 1;
+        if parsed.iter().any(|&(open, end)| open <= at && at < end) {
+            continue;
+        }
+        let value = text[at + PRIVATE_KEY_FIELD.len()..].trim_start();
+        let Some(value) = value.strip_prefix(':').map(str::trim_start) else {
+            continue;
+        };
+        let Some(private_key) = value
+            .strip_prefix('"')
+            .and_then(|value| value.get(..64))
+            .filter(|key| key.bytes().all(|b| b.is_ascii_hexdigit()))
+        else {
+            continue;
+        };
+        let name = text[..at].rfind('{').and_then(|open| preceding_key(&text, open));
+        let keypair = KeyPair {
+            private_key: Some(Zeroizing::new(private_key.to_string())),
+            public_key: String::new(),
+            demo: false,
+            mnemonic: false,
+            nonce: 0,
+            chain_code: None,
+            derivation: None,
+            label: None,
+            tags: Vec::new(),
+            created_at: 0,
+        };
+        found.push((name, keypair));
+    }
+
+    let mut wallets: Vec<(String, KeyPair)> = Vec::new();
+    let mut unnamed = 0;
+    for (name, keypair) in found {
+        let name = match name.or_else(|| file_wallet.clone()) {
+            Some(name) if !wallets.iter().any(|(existing, _)| *existing == name) => name,
+            _ => {
+                unnamed += 1;
+                format!("recovered-{}", unnamed)
+            }
+        };
+        wallets.push((name, keypair));
+    }
+    info!(path, wallets = wallets.len(), "salvaged wallets from damaged file");
+    Ok(wallets)
+}
+
+/// Returns the JSON object key directly before the `{` at `open`, if any.
+fn preceding_key(text: &str, open: usize) -> Option<String> {
+    let before = text[..open].trim_end().strip_suffix(':')?.trim_end();
+    let before = before.strip_suffix('"')?;
+    let quote = before.rfind('"')?;
+    let key = &before[quote + 1..];
+    // The outer key of a legacy file is not a wallet name
+    (key != "wallets").then(|| key.to_string())
+}
+
+/// Writes a signed transaction to a JSON file.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file to write
+/// * `transaction` - The signed transaction
+///
+/// # Returns
+///
+/// * `Ok(())` - If the file is written successfully
+/// * `Err(WalletError)` - If the transaction cannot be serialized or written
+pub fn save_signed_transaction(path: &str, transaction: &Transaction) -> Result<()> {
+    let json = serde_json::to_string_pretty(&TransactionView::from(transaction))
+        .map_err(|e| WalletError::JsonSerialize { error: e })?;
+
+    fs::write(path, json).map_err(|e| WalletError::StorageWrite {
+        path: path.to_string(),
+        error: e,
+    })
+}
+
+/// Reads a signed transaction from a JSON file written by `save_signed_transaction`.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file to read
+///
+/// # Returns
+///
+/// * `Ok(Transaction)` - The signed transaction
+/// * `Err(WalletError)` - If the file cannot be read or does not hold a transaction
+pub fn load_signed_transaction(path: &str) -> Result<Transaction> {
+    let contents = fs::read_to_string(path).map_err(|e| WalletError::StorageRead {
+        path: path.to_string(),
+        error: e,
+    })?;
+
+    let view: TransactionView = serde_json::from_str(&contents)
+        .map_err(|e| WalletError::JsonParse { error: e })?;
+    Transaction::try_from(view)
+}
+
+/// An entry of a JSON batch file; amounts may be numbers or decimal strings.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchEntry {
+    to: String,
+    amount: serde_json::Value,
+}
+
+/// Reads the payments of a batch send from a file.
+///
+/// The file is either a JSON array of `{"to": ..., "amount": ...}` objects or
+/// CSV-style lines of `address,amount`. In the CSV form, blank lines and lines
+/// starting with `#` are ignored. Amounts are in coins and may use up to
+/// `decimals` decimal places.
+///
+/// # Arguments
+///
+/// * `path` - Path to the batch file
+/// * `decimals` - Number of decimal places in one coin
+///
+/// # Returns
+///
+/// * `Ok(Vec<BatchPayment>)` - The payments in file order
+/// * `Err(WalletError::InvalidBatchFile)` - If any entry is malformed, listing every problem
+/// * `Err(WalletError)` - If the file cannot be read or parsed
+pub fn load_batch_file(path: &str, decimals: u32) -> Result<Vec<BatchPayment>> {
+    let contents = fs::read_to_string(path).map_err(|e| WalletError::StorageRead {
+        path: path.to_string(),
+        error: e,
+    })?;
+
+    let entries: Vec<(String, String, String)> = if contents.trim_start().starts_with('[') {
+        let entries: Vec<BatchEntry> = serde_json::from_str(&contents)
+            .map_err(|e| WalletError::JsonParse { error: e })?;
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let amount = match entry.amount {
+                    serde_json::Value::String(amount) => amount,
+                    other => other.to_string(),
+                };
+                (format!("entry {}", i + 1), entry.to, amount)
+            })
+            .collect()
+    } else {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| {
+                let (to, amount) = line.split_once(',').unwrap_or((line, ""));
+                (format!("line {}", i + 1), to.trim().to_string(), amount.trim().to_string())
+            })
+            .collect()
+    };
+
+    let mut payments = Vec::with_capacity(entries.len());
+    let mut problems = Vec::new();
+    for (entry, to, amount) in entries {
+        if to.is_empty() {
+            problems.push(format!("{}: missing recipient", entry));
+            continue;
         }
+        match parse_amount(&amount, decimals) {
+            Ok(0) => problems.push(format!("{}: amount must be greater than zero", entry)),
+            Ok(amount) => payments.push(BatchPayment { to, amount }),
+            Err(e) => problems.push(format!("{}: {}", entry, e)),
+        }
+    }
+    if payments.is_empty() && problems.is_empty() {
+        problems.push("no payments found".to_string());
+    }
+
+    if problems.is_empty() {
+        Ok(payments)
+    } else {
+        Err(WalletError::InvalidBatchFile {
+            path: path.to_string(),
+            problems,
+        })
+    }
+}
+
+/// Reads a JSON file from the wallet directory.
+///
+/// Creates the wallet directory if it doesn't exist. Missing files yield
+/// the default value of `T`.
+fn load_json<T: DeserializeOwned + Default>(file_name: &str) -> Result<T> {
+    // Create wallet directory if it doesn't exist
+    let wallet_path = wallet_dir();
+    if !wallet_path.exists() {
+        fs::create_dir_all(wallet_path).map_err(|e| WalletError::StorageCreate {
+            path: wallet_path.to_string_lossy().into_owned(),
+            error: e,
+        })?;
+        return Ok(T::default());
+    }
+
+    let file_name = storage_path(file_name);
+    if !Path::new(&file_name).exists() {
+        return Ok(T::default());
+    }
+
+    read_json(&file_name)
+}
+
+/// Reads and parses a JSON file at the given path.
+fn read_json<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let mut file = File::open(path).map_err(|e| WalletError::StorageRead {
+        path: path.to_string(),
+        error: e,
+    })?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| WalletError::StorageRead {
+            path: path.to_string(),
+            error: e,
+        })?;
+
+    let value =
+        serde_json::from_str(&contents).map_err(|e| WalletError::JsonParse { error: e })?;
+
+    debug!(path, bytes = contents.len(), "read storage file");
+    Ok(value)
+}
+
+/// Writes a value as pretty-printed JSON to a file in the wallet directory.
+///
+/// The write is atomic: the JSON goes to `<file>.tmp`, is flushed to disk,
+/// and is then renamed over the real file, so a crash leaves either the old
+/// or the new contents and never a truncated file. The previous contents are
+/// kept in `<file>.bak`. Creates the file's directory if it doesn't exist.
+fn save_json<T: Serialize>(file_name: &str, value: &T) -> Result<()> {
+    let file_name = storage_path(file_name);
+
+    // Create wallet directory (or a subdirectory such as keys/) if it doesn't exist
+    if let Some(dir) = Path::new(&file_name).parent() {
+        if !dir.exists() {
+            fs::create_dir_all(dir).map_err(|e| WalletError::StorageCreate {
+                path: dir.to_string_lossy().into_owned(),
+                error: e,
+            })?;
+        }
+    }
+
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| WalletError::JsonSerialize { error: e })?;
+
+    let tmp_name = format!("{}.tmp", file_name);
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = File::create(&tmp_name)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()
+    };
+    if let Err(e) = write_tmp() {
+        // The real file was never touched; just drop the partial copy
+        let _ = fs::remove_file(&tmp_name);
+        return Err(WalletError::StorageWrite {
+            path: tmp_name,
+            error: e,
+        });
+    }
+
+    if Path::new(&file_name).exists() {
+        let bak_name = format!("{}.bak", file_name);
+        fs::copy(&file_name, &bak_name).map_err(|e| WalletError::StorageWrite {
+            path: bak_name,
+            error: e,
+        })?;
+    }
 
-        // Check if it's a valid public key stored with us
-        if self.wallets.values().any(|kp| kp.public_key == name_or_key) {
-            return Some(name_or_key.to_string());
+    fs::rename(&tmp_name, &file_name).map_err(|e| WalletError::StorageWrite {
+        path: file_name.clone(),
+        error: e,
+    })?;
+
+    // Persist the rename itself; directories can only be synced this way on Unix
+    #[cfg(unix)]
+    if let Some(dir) = Path::new(&file_name).parent() {
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| WalletError::StorageWrite {
+                path: dir.to_string_lossy().into_owned(),
+                error: e,
+            })?;
+    }
+
+    debug!(path = file_name, bytes = json.len(), "wrote storage file");
+    Ok(())
+}
+
+/// Exclusive advisory lock on the wallet directory.
+///
+/// Held around each read-modify-write of a storage file so two commands
+/// running at once cannot clobber each other's changes. The lock belongs to
+/// the open file, so the OS releases it on drop and also when the process
+/// dies; a crash never leaves the wallet directory locked.
+struct StorageLock {
+    _file: File,
+}
+
+impl StorageLock {
+    /// Waits up to `LOCK_TIMEOUT` for the lock.
+    fn acquire() -> Result<Self> {
+        fs::create_dir_all(wallet_dir()).map_err(|e| WalletError::StorageCreate {
+            path: wallet_dir().to_string_lossy().into_owned(),
+            error: e,
+        })?;
+
+        let path = storage_path(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| WalletError::StorageCreate {
+                path: path.clone(),
+                error: e,
+            })?;
+
+        let start = Instant::now();
+        while file.try_lock_exclusive().is_err() {
+            if start.elapsed() >= LOCK_TIMEOUT {
+                return Err(WalletError::StorageLocked { path });
+            }
+            std::thread::sleep(LOCK_POLL_INTERVAL);
         }
+        debug!(path, waited_ms = start.elapsed().as_millis() as u64, "locked storage");
+        Ok(StorageLock { _file: file })
+    }
+}
+
+/// Returns whether a file exists in the wallet directory.
+fn storage_file_exists(file_name: &str) -> bool {
+    Path::new(&storage_path(file_name)).exists()
+}
 
-        // Check if it's a valid public key in general
-        if let Ok(public_key_bytes) = hex::decode(name_or_key) {
-            if PublicKey::from_slice(&public_key_bytes).is_ok() {
-                return Some(name_or_key.to_string());
+/// Deletes a file in the wallet directory together with its backup.
+///
+/// The backup has to go too: otherwise a later file of the same name could
+/// be "restored" from it after corruption, bringing back the old contents.
+fn remove_file(file_name: &str) -> Result<()> {
+    let file_name = storage_path(file_name);
+    fs::remove_file(&file_name).map_err(|e| WalletError::StorageWrite {
+        path: file_name.clone(),
+        error: e,
+    })?;
+    let bak_name = format!("{}.bak", file_name);
+    if Path::new(&bak_name).exists() {
+        fs::remove_file(&bak_name).map_err(|e| WalletError::StorageWrite {
+            path: bak_name,
+            error: e,
+        })?;
+    }
+    debug!(path = file_name, "removed storage file");
+    Ok(())
+}
+
+/// Returns the path of a wallet's key file, relative to the wallet directory.
+///
+/// Fails with `WalletError::InvalidArgument` for names that are not a
+/// single plain file name, so a wallet cannot be written outside `keys/`.
+fn key_file(name: &str) -> Result<String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+        return Err(WalletError::InvalidArgument {
+            message: format!(
+                "wallet name '{}' must not be empty, start with '.' or contain path separators",
+                name
+            ),
+        });
+    }
+    Ok(format!("{}/{}.json", KEYS_DIR, name))
+}
+
+/// Returns the canonical form of a stored public key, so compressed and
+/// uncompressed copies of one key compare equal. Keys that do not parse
+/// are compared as stored.
+fn canonical_key(public_key: &str) -> String {
+    normalize_address(public_key).unwrap_or_else(|_| public_key.to_string())
+}
+
+/// Moves the wallets of a legacy `wallets.json` into per-wallet key files.
+///
+/// Wallets that already have a key file are left alone. The legacy file is
+/// renamed to `wallets.json.migrated` rather than deleted, so the original
+/// keys survive until the user removes it. A legacy file that no longer
+/// parses is restored from its backup or moved aside like a key file. The
+/// caller must hold the storage lock.
+fn migrate_wallet_file(warnings: &mut Vec<Warning>) -> Result<()> {
+    let legacy: Wallets = match load_json(WALLET_FILE) {
+        Err(WalletError::JsonParse { error }) => {
+            let path = storage_path(WALLET_FILE);
+            let (legacy, warning) = recover_corrupt_file(&path, &error)?;
+            warnings.push(warning);
+            match legacy {
+                Some(legacy) => legacy,
+                // Moved aside, so there is nothing left to migrate
+                None => return Ok(()),
             }
         }
+        other => other?,
+    };
+    for (name, keypair) in &legacy.wallets {
+        let file_name = key_file(name)?;
+        if !storage_file_exists(&file_name) {
+            save_json(&file_name, keypair)?;
+        }
+    }
 
-        None
+    let legacy_path = storage_path(WALLET_FILE);
+    fs::rename(&legacy_path, format!("{}.migrated", legacy_path)).map_err(|e| {
+        WalletError::StorageWrite {
+            path: legacy_path.clone(),
+            error: e,
+        }
+    })?;
+    info!(wallets = legacy.wallets.len(), "migrated {} to per-wallet key files", legacy_path);
+    Ok(())
+}
+
+/// Reads one wallet's key file, recovering it if it no longer parses.
+///
+/// # Returns
+///
+/// * `Ok(Some(KeyPair))` - The key pair, possibly restored from the file's backup
+/// * `Ok(None)` - If the file was corrupt and has been moved aside
+/// * `Err(WalletError)` - If the file cannot be read or recovered
+fn read_key_file(path: &str, warnings: &mut Vec<Warning>) -> Result<Option<KeyPair>> {
+    match read_json(path) {
+        Err(WalletError::JsonParse { .. }) => {}
+        other => return other.map(Some),
+    }
+
+    let _lock = StorageLock::acquire()?;
+    // Another process may have dealt with the file while we waited
+    if !Path::new(path).exists() {
+        return Ok(None);
     }
+    match read_json(path) {
+        Err(WalletError::JsonParse { error }) => {
+            let (keypair, warning) = recover_corrupt_file(path, &error)?;
+            warnings.push(warning);
+            Ok(keypair)
+        }
+        other => other.map(Some),
+    }
+}
+
+/// Deals with a storage file that exists but no longer parses.
+///
+/// The file's `.bak` copy is put back if it still parses. Otherwise the
+/// file is renamed to `<file>.corrupt-<unix time>`, where `repair` can
+/// salvage what is left of it, and callers carry on as if it were missing.
+/// The caller must hold the storage lock.
+///
+/// # Returns
+///
+/// * `Ok((Some(T), Warning))` - The contents restored from the backup
+/// * `Ok((None, Warning))` - If the file was moved aside
+/// * `Err(WalletError::StorageWrite)` - If the file cannot be restored or moved
+fn recover_corrupt_file<T: DeserializeOwned>(
+    path: &str,
+    error: &serde_json::Error,
+) -> Result<(Option<T>, Warning)> {
+    let bak_path = format!("{}.bak", path);
+    if let Ok(value) = read_json(&bak_path) {
+        fs::copy(&bak_path, path).map_err(|e| WalletError::StorageWrite {
+            path: path.to_string(),
+            error: e,
+        })?;
+        info!(path, %error, "restored corrupt storage file from its backup");
+        let warning = Warning::CorruptFileRestored {
+            path: path.to_string(),
+            error: error.to_string(),
+        };
+        return Ok((Some(value), warning));
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let moved_to = format!("{}{}{}", path, CORRUPT_SUFFIX, timestamp);
+    fs::rename(path, &moved_to).map_err(|e| WalletError::StorageWrite {
+        path: path.to_string(),
+        error: e,
+    })?;
+    info!(path, moved_to, %error, "moved corrupt storage file aside");
+    let warning = Warning::CorruptFileMoved {
+        path: path.to_string(),
+        moved_to,
+        error: error.to_string(),
+    };
+    Ok((None, warning))
 }
@@ -1,253 +1,5050 @@
+use crate::amount::percent_of;
+use crate::address::{display_address, looks_like_key, normalize_address, parse_public_key, validate_public_key};
+use crate::backup;
+use crate::display;
 use crate::errors::{Result, WalletError};
-use crate::models::{KeyPair, Wallets};
+use crate::keys::{decode_imported_key, decode_secret_key, encode_secret_key, KeyFormat};
+use crate::signer::{LocalSigner, Signer};
+use crate::models::{AddressBook, AddressSource, BalanceCache, BalanceSummary, CachedBalance, BatchPayment, ChainFault, ChainInfo, ChainVerification, ClientConfig, Config, Derivation, Direction, FaucetLog, FaucetReceipt, HistoryQuery, KeyPair, PendingFaucetRequest, PendingTransactions, RepairedWallet, ResolvedAddress, RestoreSummary, SendOptions, StoreProblem, TlsOptions, TransactionTemplate, TxNotes, WalletBackup, Wallets};
+use crate::storage;
+use crate::warnings::Warning;
 use crate::proto::blockchain::{
     blockchain_service_client::BlockchainServiceClient,
     BalanceRequest,
-    Block as ProtoBlock, // Added
+    Block as ProtoBlock,
     FaucetRequest,
-    FaucetResponse as ProtoFaucetResponse, // Added Response type
-    GetBlockRequest,                       // Added
-    GetStateRequest,                       // Added
-    HistoryRequest,                        // Added
-    Transaction,                           // Renamed for clarity
+    GetBlockRequest,
+    GetStateRequest,
+    HistoryRequest,
+    Transaction,
+    WatchBlocksRequest,
 };
-use secp256k1::{Secp256k1, SecretKey};
-use sha2::{Digest, Sha256};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tonic::Request;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Scalar, Secp256k1, SecretKey};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use prost::Message as _;
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{debug, info, Instrument};
+use zeroize::Zeroizing;
+use tonic::codegen::http::uri::InvalidUri;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, Uri};
+use tonic::{Code, Request, Response, Status};
+
+/// Blockchain service address used when none is configured.
+pub const DEFAULT_SERVER: &str = "http://[::1]:50051";
+
+/// Environment variable that overrides the configured server URL.
+pub const SERVER_ENV: &str = "MOCKCHAIN_SERVER";
+
+/// Environment variable listing comma-separated server URLs to fail over between.
+pub const SERVERS_ENV: &str = "MOCKCHAIN_SERVERS";
+
+/// Maximum number of concurrent queries `wallet_balances` and `get_blocks` keep in flight.
+const MAX_CONCURRENT_QUERIES: usize = 8;
+
+/// Largest number of blocks `get_blocks` fetches in one call.
+const MAX_BLOCK_RANGE: u64 = 1000;
+
+/// Blocks on each side of the timestamp search's guess that are scanned first.
+const TIME_SEARCH_WINDOW: u64 = 4;
+
+/// How many ever wider windows the timestamp search scans before giving up on it.
+const TIME_SEARCH_WIDENINGS: u32 = 3;
+
+/// Longest memo, in bytes, a transaction may carry.
+pub const MAX_MEMO_BYTES: usize = 256;
+
+/// First BIP32 index of hardened child keys.
+const HARDENED_INDEX: u32 = 0x8000_0000;
+
+/// Path of the account that derived addresses live under, `m/44'/1'/0'/0`.
+///
+/// BIP44 with coin type 1, which is reserved for test networks.
+const DERIVATION_PATH_PREFIX: [u32; 4] = [44 + HARDENED_INDEX, 1 + HARDENED_INDEX, HARDENED_INDEX, 0];
+
+/// How far in the future a not-before time may be before the user is warned.
+const NOT_BEFORE_WARNING_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Repeating a faucet request for the same address within this many seconds raises a warning.
+const FAUCET_REPEAT_WARNING_SECS: u64 = 60;
 
 /// Client for interacting with the blockchain service.
 ///
 /// Provides functionality for managing wallets and performing
 /// blockchain operations like checking balances and sending transactions.
 pub struct WalletClient {
-    client: BlockchainServiceClient<tonic::transport::Channel>,
+    /// One endpoint per configured server, tried in order
+    endpoints: Vec<Endpoint>,
+    /// Index of the endpoint currently in use; earlier ones have failed
+    active: usize,
+    /// Connected on the first RPC, so purely local commands never touch the network
+    client: Option<BlockchainServiceClient<Channel>>,
+    config: ClientConfig,
     wallets: Wallets,
+    tx_notes: TxNotes,
+    address_book: AddressBook,
+    balance_cache: BalanceCache,
+    warnings: Vec<Warning>,
 }
 
 impl WalletClient {
-    /// Creates a new wallet client connected to the blockchain service.
+    /// Creates a new wallet client for the blockchain service.
+    ///
+    /// Loads wallet data from local storage and prepares the endpoints, but
+    /// does not connect; the connection is made by the first RPC. Commands
+    /// that only touch local storage therefore work without a reachable node.
+    ///
+    /// # Arguments
     ///
-    /// Establishes a connection to the blockchain service at the default address
-    /// (http://[::1]:50051) and loads wallet data from local storage.
+    /// * `config` - Client settings such as the server URL and RPC tracing
     ///
     /// # Returns
     ///
     /// * `Ok(WalletClient)` - A new client instance ready to use
-    /// * `Err(WalletError)` - If connection to the service fails or wallet data cannot be loaded
-    pub async fn new() -> Result<Self> {
-        let client = BlockchainServiceClient::connect("http://[::1]:50051").await?;
-        let wallets = Wallets::load()?;
-        Ok(WalletClient { client, wallets })
+    /// * `Err(WalletError::TlsConfig)` - If the TLS options or certificate files are invalid
+    /// * `Err(WalletError)` - If the server URL is malformed or wallet data cannot be loaded
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        let mut endpoints = Vec::new();
+        for server in &config.servers {
+            let mut endpoint = Endpoint::from_shared(server.clone())?;
+            if server.starts_with("https://") {
+                endpoint = endpoint.tls_config(tls_config(&config.tls)?)?;
+            } else if config.tls.is_set() {
+                return Err(WalletError::TlsConfig {
+                    message: format!("TLS options require an https:// server, got {}", server),
+                });
+            }
+            endpoints.push(endpoint);
+        }
+
+        let mut wallets = Wallets::load()?;
+        let tx_notes = TxNotes::load()?;
+        let address_book = AddressBook::load()?;
+        // Loaded even when caching is off so sends still invalidate stale entries.
+        // The cache is only an optimization, so an unreadable or foreign one starts over
+        let balance_cache = match BalanceCache::load() {
+            Ok(cache) if cache.server == config.servers.join(",") => cache,
+            _ => BalanceCache {
+                server: config.servers.join(","),
+                ..BalanceCache::default()
+            },
+        };
+        Ok(WalletClient {
+            endpoints,
+            active: 0,
+            client: None,
+            config,
+            tx_notes,
+            address_book,
+            balance_cache,
+            warnings: std::mem::take(&mut wallets.warnings),
+            wallets,
+        })
+    }
+
+    /// Returns the gRPC client, connecting to the blockchain service on first use.
+    ///
+    /// Endpoints are tried in order starting from the active one. The first
+    /// that accepts the connection stays in use for the rest of the session,
+    /// so later RPCs do not probe endpoints that already failed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BlockchainServiceClient)` - A handle to the shared connection
+    /// * `Err(WalletError::Timeout)` - If the only service does not accept the connection in time
+    /// * `Err(WalletError::ConnectionFailed)` - If the only service cannot be connected to
+    /// * `Err(WalletError::AllEndpointsFailed)` - If none of several services can be connected to
+    async fn client(&mut self) -> Result<BlockchainServiceClient<Channel>> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+
+        loop {
+            let server = self.server();
+            let start = Instant::now();
+            let endpoint = &self.endpoints[self.active];
+            let connect = async {
+                match &self.config.proxy {
+                    Some(proxy) if proxy.applies_to(endpoint.uri()) => {
+                        let proxy = proxy.clone();
+                        endpoint
+                            .connect_with_connector(tower::service_fn(move |server: Uri| {
+                                let proxy = proxy.clone();
+                                async move { proxy.connect(server).await }
+                            }))
+                            .await
+                    }
+                    _ => endpoint.connect().await,
+                }
+            };
+            let connected = tokio::time::timeout(self.config.connect_timeout, connect)
+                .await
+                .map_err(|_| WalletError::Timeout {
+                    operation: format!("connect to {}", server),
+                })
+                .and_then(|result| result.map_err(WalletError::from));
+            let latency_ms = start.elapsed().as_millis() as u64;
+            match &connected {
+                Ok(_) => info!(endpoint = server, latency_ms, "connected"),
+                Err(e) => info!(endpoint = server, latency_ms, error = %e, "connection failed"),
+            }
+            match connected {
+                Ok(channel) => {
+                    let client = BlockchainServiceClient::new(channel);
+                    self.client = Some(client.clone());
+                    return Ok(client);
+                }
+                Err(_) if self.fail_over() => {}
+                Err(_) if self.endpoints.len() > 1 => {
+                    return Err(WalletError::AllEndpointsFailed {
+                        attempted: self.config.servers.clone(),
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the URL of the endpoint currently in use.
+    pub fn server(&self) -> &str {
+        &self.config.servers[self.active]
+    }
+
+    /// Returns the URL of the endpoint this session is connected to, if any.
+    ///
+    /// `None` until the first RPC connects, and after a failed endpoint
+    /// was given up on until the next one has accepted the connection.
+    pub fn connected_endpoint(&self) -> Option<&str> {
+        self.client.as_ref().map(|_| self.server())
+    }
+
+    /// Moves on to the next endpoint after the active one failed.
+    ///
+    /// # Returns
+    ///
+    /// Whether there was another endpoint to move on to.
+    fn fail_over(&mut self) -> bool {
+        if self.active + 1 >= self.endpoints.len() {
+            return false;
+        }
+        self.warnings.push(Warning::EndpointUnavailable {
+            url: self.config.servers[self.active].clone(),
+        });
+        self.active += 1;
+        self.client = None;
+        true
+    }
+
+    /// Makes an RPC with retries, failing over to the next endpoint if the
+    /// active one is unavailable.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - RPC name, used for timeout errors
+    /// * `call` - Builds and sends the request on the given client; invoked once per attempt
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Result)` - The RPC's response or the status it failed with
+    /// * `Err(WalletError)` - If no endpoint can be connected to
+    async fn call<F, Fut, T>(&mut self, method: &str, mut call: F) -> Result<std::result::Result<T, Status>>
+    where
+        F: FnMut(BlockchainServiceClient<Channel>) -> Fut,
+        Fut: Future<Output = std::result::Result<T, Status>>,
+    {
+        loop {
+            let client = self.client().await?;
+            let result = with_retry(method, self.server(), &self.config, || call(client.clone())).await;
+            match result {
+                Err(status) if status.code() == Code::Unavailable && self.fail_over() => {}
+                result => return Ok(result),
+            }
+        }
     }
 
     /// Creates a new wallet with the given name.
     ///
     /// Generates a new secp256k1 key pair and stores it in local storage
-    /// associated with the provided name.
+    /// associated with the provided name. When a mnemonic word count is given,
+    /// the key is derived from a freshly generated BIP39 mnemonic instead, so
+    /// the wallet can later be restored with `recover_wallet`.
     ///
     /// # Arguments
     ///
     /// * `name` - The name to assign to the new wallet
+    /// * `mnemonic_words` - Number of mnemonic words (12 or 24) to derive the key from, if any
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the wallet is created successfully
+    /// * `Ok(Some(Zeroizing<String>))` - The mnemonic phrase, if the wallet is mnemonic-derived
+    /// * `Ok(None)` - If the wallet was created from a random key
     /// * `Err(WalletError::WalletExists)` - If a wallet with the given name already exists
+    /// * `Err(WalletError::InvalidMnemonic)` - If the word count is not supported
     /// * `Err(WalletError)` - If an error occurs while generating or storing the wallet
-    pub fn create_wallet(&mut self, name: &str) -> Result<()> {
+    pub fn create_wallet(&mut self, name: &str, mnemonic_words: Option<usize>) -> Result<Option<Zeroizing<String>>> {
         if self.wallets.get_wallet(name).is_some() {
             return Err(WalletError::WalletExists(name.to_string()));
         }
 
+        if let Some(words) = mnemonic_words {
+            if words != 12 && words != 24 {
+                return Err(WalletError::InvalidMnemonic {
+                    message: format!("unsupported word count {}, expected 12 or 24", words),
+                });
+            }
+            let mnemonic = Mnemonic::generate(words)
+                .map_err(|e| WalletError::InvalidMnemonic { 
+                    message: e.to_string() 
+                })?;
+            let keypair = keypair_from_mnemonic(&mnemonic, self.now()?)?;
+            self.wallets.add_wallet(name, keypair, false)?;
+            return Ok(Some(Zeroizing::new(mnemonic.to_string())));
+        }
+
         let secp = Secp256k1::new();
         let (secret_key, public_key) = secp.generate_keypair(&mut rand::thread_rng());
 
-        let secret_hex = hex::encode(secret_key.secret_bytes());
+        let secret_hex = encode_secret_key(&secret_key, KeyFormat::Hex);
         let public_hex = hex::encode(public_key.serialize());
 
         let keypair = KeyPair {
-            private_key: secret_hex,
+            private_key: Some(secret_hex),
             public_key: public_hex,
+            demo: false,
+            mnemonic: false,
+            nonce: 0,
+            chain_code: None,
+            derivation: None,
+            label: None,
+            tags: Vec::new(),
+            created_at: self.now()?,
         };
 
-        self.wallets.add_wallet(name, keypair)?;
+        self.wallets.add_wallet(name, keypair, false)?;
+        Ok(None)
+    }
+
+    /// Recovers a wallet from a BIP39 mnemonic phrase.
+    ///
+    /// Derives the same key pair `create_wallet` produced for the phrase and
+    /// stores it under the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to assign to the recovered wallet
+    /// * `mnemonic` - The mnemonic phrase
+    /// * `allow_duplicate` - Whether to store the key even if another wallet already holds it
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the wallet is recovered successfully
+    /// * `Err(WalletError::WalletExists)` - If a wallet with the given name already exists
+    /// * `Err(WalletError::DuplicateKey)` - If another wallet holds the same key
+    /// * `Err(WalletError::InvalidMnemonic)` - If the phrase has unknown words or a bad checksum
+    /// * `Err(WalletError)` - If an error occurs while deriving or storing the wallet
+    pub fn recover_wallet(&mut self, name: &str, mnemonic: &str, allow_duplicate: bool) -> Result<()> {
+        if self.wallets.get_wallet(name).is_some() {
+            return Err(WalletError::WalletExists(name.to_string()));
+        }
+
+        let mnemonic = Mnemonic::parse(mnemonic)
+            .map_err(|e| WalletError::InvalidMnemonic { 
+                message: e.to_string() 
+            })?;
+        let keypair = keypair_from_mnemonic(&mnemonic, self.now()?)?;
+
+        self.wallets.add_wallet(name, keypair, allow_duplicate)?;
         Ok(())
     }
 
-    /// Gets the balance for a wallet.
+    /// Imports an existing private key as a named wallet.
     ///
-    /// Queries the blockchain service for the current balance of the wallet
-    /// specified by name or public key.
+    /// # Arguments
+    ///
+    /// * `name` - The name to assign to the imported wallet
+    /// * `private_key` - The private key as hex, WIF, a SEC1 or PKCS#8 PEM block, or keystore JSON
+    /// * `passphrase` - Passphrase to decrypt a keystore with
+    /// * `allow_duplicate` - Whether to store the key even if another wallet already holds it
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(KeyPair)` - The key pair of the imported wallet
+    /// * `Err(WalletError::WalletExists)` - If a wallet with the given name already exists
+    /// * `Err(WalletError::DuplicateKey)` - If another wallet holds the same key
+    /// * `Err(WalletError::InvalidPrivateKey)` - If the key cannot be decoded or decrypted
+    /// * `Err(WalletError)` - If an error occurs while storing the wallet
+    pub fn import_key(&mut self, name: &str, private_key: &str, passphrase: Option<&str>, allow_duplicate: bool) -> Result<KeyPair> {
+        if self.wallets.get_wallet(name).is_some() {
+            return Err(WalletError::WalletExists(name.to_string()));
+        }
+
+        let secret_key = decode_imported_key(private_key, passphrase)?;
+        let public_key = secret_key.public_key(&Secp256k1::new());
+
+        let keypair = KeyPair {
+            private_key: Some(encode_secret_key(&secret_key, KeyFormat::Hex)),
+            public_key: hex::encode(public_key.serialize()),
+            demo: false,
+            mnemonic: false,
+            nonce: 0,
+            chain_code: None,
+            derivation: None,
+            label: None,
+            tags: Vec::new(),
+            created_at: self.now()?,
+        };
+
+        self.wallets.add_wallet(name, keypair.clone(), allow_duplicate)?;
+        Ok(keypair)
+    }
+
+    /// Adds a watch-only wallet that tracks an address without its private key.
     ///
     /// # Arguments
     ///
-    /// * `wallet_name_or_key` - Name of a wallet in local storage or a public key
+    /// * `name` - The name to assign to the watched address
+    /// * `public_key` - The hex-encoded public key to watch
+    /// * `allow_duplicate` - Whether to store the address even if another wallet already holds it
     ///
     /// # Returns
     ///
-    /// * `Ok(u64)` - The wallet's balance in coins
-    /// * `Err(WalletError::WalletNotFound)` - If the wallet or address cannot be resolved
-    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
-    pub async fn get_balance(&mut self, wallet_name_or_key: &str) -> Result<u64> {
-        let address = self.wallets.resolve_address(wallet_name_or_key)
-            .ok_or_else(|| WalletError::WalletNotFound(wallet_name_or_key.to_string()))?;
-        
-        let request = Request::new(BalanceRequest {
-            address
-        });
-        
-        let response = self.client.get_balance(request).await?;
-        Ok(response.into_inner().balance)
+    /// * `Ok(String)` - The stored address
+    /// * `Err(WalletError::WalletExists)` - If a wallet with the given name already exists
+    /// * `Err(WalletError::DuplicateKey)` - If another wallet holds the same key
+    /// * `Err(WalletError::AddressInvalid)` - If the public key is invalid
+    /// * `Err(WalletError)` - If an error occurs while storing the wallet
+    pub fn add_watch(&mut self, name: &str, public_key: &str, allow_duplicate: bool) -> Result<String> {
+        if self.wallets.get_wallet(name).is_some() {
+            return Err(WalletError::WalletExists(name.to_string()));
+        }
+
+        let address = display_address(&parse_public_key(public_key)?);
+        let keypair = KeyPair {
+            private_key: None,
+            public_key: address.clone(),
+            demo: false,
+            mnemonic: false,
+            nonce: 0,
+            chain_code: None,
+            derivation: None,
+            label: None,
+            tags: Vec::new(),
+            created_at: self.now()?,
+        };
+
+        self.wallets.add_wallet(name, keypair, allow_duplicate)?;
+        Ok(address)
     }
 
-    /// Sends a transaction from one wallet to another.
+    /// Restores the wallets that can still be read from damaged wallet files.
     ///
-    /// Signs and submits a transaction to transfer coins from the sender's wallet
-    /// to the recipient. The recipient can be specified by wallet name or public key.
+    /// Damaged files are the ones the wallet moved aside when they stopped
+    /// parsing, unless a file is given. Salvaged keys are checked before
+    /// they are stored: a private key always wins over the public key next
+    /// to it. Wallets whose name is already taken are skipped, and the
+    /// damaged files are left in place.
     ///
     /// # Arguments
     ///
-    /// * `from_wallet` - Name of the sender's wallet in local storage
-    /// * `to_name_or_key` - Name or public key of the recipient
-    /// * `amount` - Number of coins to transfer
+    /// * `file` - The damaged file to repair, or `None` for every file moved aside
     ///
     /// # Returns
     ///
-    /// * `Ok(bool)` - True if the transaction was successful
-    /// * `Err(WalletError::WalletNotFound)` - If the sender wallet cannot be found
-    /// * `Err(WalletError::AddressInvalid)` - If the recipient address is invalid
-    /// * `Err(WalletError)` - If an error occurs during signing or submission
-    pub async fn send_transaction(
-        &mut self,
-        from_wallet: &str,
-        to_name_or_key: &str,
-        amount: u64,
-    ) -> Result<bool> {
-        // Get sender's keypair
-        let keypair = self.wallets.get_wallet(from_wallet)
-            .ok_or_else(|| WalletError::WalletNotFound(from_wallet.to_string()))?;
+    /// * `Ok(Vec<(String, Vec<RepairedWallet>)>)` - Each repaired file with what became of its wallets
+    /// * `Err(WalletError::StorageRead)` - If a damaged file cannot be read
+    /// * `Err(WalletError)` - If an error occurs while storing a wallet
+    pub fn repair(&mut self, file: Option<&str>) -> Result<Vec<(String, Vec<RepairedWallet>)>> {
+        let files = match file {
+            Some(file) => vec![file.to_string()],
+            None => storage::corrupt_wallet_files()?,
+        };
 
-        // Resolve recipient
-        let to_address = self.wallets.resolve_address(to_name_or_key)
-            .ok_or_else(|| WalletError::AddressInvalid(to_name_or_key.to_string()))?;
+        let mut repaired = Vec::new();
+        for file in files {
+            let mut outcomes = Vec::new();
+            for (name, mut keypair) in storage::salvage_wallets(&file)? {
+                let checked = match &keypair.private_key {
+                    Some(private_key) => decode_secret_key(private_key)
+                        .map(|secret_key| display_address(&secret_key.public_key(&Secp256k1::new()))),
+                    None => parse_public_key(&keypair.public_key).map(|public_key| display_address(&public_key)),
+                };
+                let address = match checked {
+                    Ok(address) => address,
+                    Err(e) => {
+                        outcomes.push(RepairedWallet {
+                            name,
+                            address: String::new(),
+                            skipped: Some(format!("unusable key: {}", e)),
+                        });
+                        continue;
+                    }
+                };
+                keypair.public_key = address.clone();
 
-        // Decode private key
-        let secret_key_bytes = hex::decode(&keypair.private_key)?;
-        let secret_key = SecretKey::from_slice(&secret_key_bytes)
-            .map_err(|e| WalletError::InvalidPrivateKey { 
-                message: e.to_string() 
-            })?;
-        
-        // Get current timestamp for transaction
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| WalletError::SystemTimeError { 
-                message: e.to_string() 
-            })?
-            .as_secs();
-
-        // Create message to sign
-        let mut hasher = Sha256::new();
-        hasher.update(
-            serde_json::to_string(&(
-                &keypair.public_key,
-                &to_address,
-                amount,
-                timestamp,
-            )).map_err(|e| WalletError::JsonSerialize { 
-                error: e 
-            })?.as_bytes()
+                let skipped = match self.wallets.get_wallet(&name) {
+                    Some(existing) if existing.public_key == address => Some("already restored".to_string()),
+                    Some(_) => Some(format!("a different wallet named '{}' exists", name)),
+                    None => match self.wallets.add_wallet(&name, keypair, false) {
+                        Ok(()) => None,
+                        Err(e @ (WalletError::WalletExists(_)
+                            | WalletError::DuplicateKey { .. }
+                            | WalletError::InvalidArgument { .. })) => {
+                            Some(e.to_string())
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                outcomes.push(RepairedWallet { name, address, skipped });
+            }
+            repaired.push((file, outcomes));
+        }
+        Ok(repaired)
+    }
+
+    /// Checks local storage for problems that make wallets ambiguous.
+    ///
+    /// Reports wallets sharing a public key, which adding a wallet refuses
+    /// unless duplicates are allowed but restored backups and older stores
+    /// may still hold. Also reports wallets whose stored public key does not
+    /// match their private key, as a hand-edited key file can have; their
+    /// transactions would only fail verification once sent.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<StoreProblem>` - The problems found, empty if there are none
+    pub fn doctor(&self) -> Vec<StoreProblem> {
+        let mut problems: Vec<StoreProblem> = self.wallets.duplicate_keys()
+            .into_iter()
+            .map(|(address, names)| StoreProblem::DuplicateKey { address, names })
+            .collect();
+        problems.extend(
+            self.key_mismatches()
+                .into_iter()
+                .map(|(name, stored, derived)| StoreProblem::KeyMismatch { name, stored, derived }),
         );
+        problems
+    }
 
-        let message = hasher.finalize();
-        
-        // Sign transaction
-        let secp = Secp256k1::new();
-        let msg = secp256k1::Message::from_slice(&message)
-            .map_err(|e| WalletError::SigningFailed { 
-                message: e.to_string() 
-            })?;
-            
-        let signature = secp.sign_ecdsa(&msg, &secret_key);
-        
-        // Create and send transaction
-        let transaction = Transaction {
-            from: keypair.public_key.clone(),
-            to: to_address,
-            amount,
-            timestamp,
-            signature: signature.serialize_compact().to_vec(),
+    /// Replaces stored public keys that do not match the wallet's private key.
+    ///
+    /// The private key is what signs, so it is taken as the truth: each
+    /// mismatched wallet gets the public key derived from it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - Names of the corrected wallets, sorted
+    /// * `Err(WalletError)` - If a key file cannot be written
+    pub fn fix_key_mismatches(&mut self) -> Result<Vec<String>> {
+        let mut fixed = Vec::new();
+        for (name, _, derived) in self.key_mismatches() {
+            self.wallets.update_wallet(&name, |keypair| keypair.public_key = derived)?;
+            fixed.push(name);
+        }
+        Ok(fixed)
+    }
+
+    /// Finds wallets whose stored public key differs from the one their private key derives.
+    ///
+    /// Returns (name, stored, derived) for each, sorted by name. Keys are
+    /// compared in canonical form, so an uncompressed stored key matches.
+    /// Watch-only wallets and undecodable private keys are skipped.
+    fn key_mismatches(&self) -> Vec<(String, String, String)> {
+        let secp = Secp256k1::signing_only();
+        let mut mismatches = Vec::new();
+        for (name, keypair) in &self.wallets.wallets {
+            let Ok(mut secret_key) = decode_stored_key(name, keypair) else {
+                continue;
+            };
+            let derived = display_address(&secret_key.public_key(&secp));
+            secret_key.non_secure_erase();
+            let stored = normalize_address(&keypair.public_key).unwrap_or_else(|_| keypair.public_key.clone());
+            if stored != derived {
+                mismatches.push((name.clone(), keypair.public_key.clone(), derived));
+            }
+        }
+        mismatches.sort();
+        mismatches
+    }
+
+    /// Writes an encrypted backup of every wallet, contact and setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - New file to write the backup to
+    /// * `passphrase` - Passphrase to encrypt the backup with
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of wallets backed up
+    /// * `Err(WalletError::InvalidArgument)` - If the file already exists
+    /// * `Err(WalletError)` - If the settings cannot be read or the backup cannot be written
+    pub fn backup(&self, path: &str, passphrase: &str) -> Result<usize> {
+        let backup = WalletBackup {
+            created_at: self.config.clock.now()?,
+            wallets: self.wallets.wallets.clone(),
+            contacts: self.address_book.contacts.clone(),
+            config: Config::load()?,
         };
-        
-        let request = Request::new(transaction);
-        let response = self.client.submit_transaction(request).await?;
-        let response_inner = response.into_inner();
-        if !response_inner.success {
-            return Err(WalletError::TransactionFailed { 
-                message: response_inner.message 
+        backup::write_backup(path, &backup, passphrase)?;
+        Ok(backup.wallets.len())
+    }
+
+    /// Merges an encrypted backup into local storage.
+    ///
+    /// Wallets and contacts missing locally are added. When a name is taken
+    /// by a different key or address, `overwrite` is asked whether to replace
+    /// it. Settings are only filled in where none are set locally. Wallets
+    /// are restored even if another name holds the same key; `doctor`
+    /// reports such duplicates.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The backup file
+    /// * `passphrase` - Passphrase the backup was encrypted with
+    /// * `overwrite` - Called with a description such as `wallet 'alice'`; returns whether to replace it
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RestoreSummary)` - What happened to each wallet, and how many contacts were restored
+    /// * `Err(WalletError::DecryptionFailed)` - If the passphrase is wrong or the file was modified
+    /// * `Err(WalletError)` - If the backup cannot be read or an entry cannot be stored
+    pub fn restore(
+        &mut self,
+        path: &str,
+        passphrase: &str,
+        mut overwrite: impl FnMut(&str) -> bool,
+    ) -> Result<RestoreSummary> {
+        let backup = backup::read_backup(path, passphrase)?;
+        let mut summary = RestoreSummary::default();
+
+        let mut wallets: Vec<_> = backup.wallets.into_iter().collect();
+        wallets.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, keypair) in wallets {
+            match self.wallets.get_wallet(&name) {
+                None => {
+                    self.wallets.add_wallet(&name, keypair, true)?;
+                    summary.added.push(name);
+                }
+                Some(existing) if existing.public_key == keypair.public_key => summary.unchanged.push(name),
+                Some(_) if overwrite(&format!("wallet '{}'", name)) => {
+                    self.wallets.update_wallet(&name, |stored| *stored = keypair)?;
+                    summary.replaced.push(name);
+                }
+                Some(_) => summary.kept.push(name),
+            }
+        }
+
+        let mut contacts: Vec<_> = backup.contacts.into_iter().collect();
+        contacts.sort();
+        for (alias, address) in contacts {
+            match self.address_book.contacts.get(&alias) {
+                Some(existing) if *existing == address => continue,
+                Some(_) if !overwrite(&format!("contact '{}'", alias)) => continue,
+                Some(_) => {
+                    self.address_book.remove_contact(&alias)?;
+                }
+                None => {}
+            }
+            self.address_book.add_contact(&alias, &address)?;
+            summary.contacts += 1;
+        }
+
+        let restored = backup.config;
+        Config::update(|settings| {
+            let before = (settings.server.is_none(), settings.decimals.is_none(), settings.default_wallet.is_none());
+            settings.server = settings.server.take().or(restored.server);
+            settings.decimals = settings.decimals.or(restored.decimals);
+            settings.default_wallet = settings.default_wallet.take().or(restored.default_wallet);
+            before != (settings.server.is_none(), settings.decimals.is_none(), settings.default_wallet.is_none())
+        })?;
+
+        Ok(summary)
+    }
+
+    /// Deletes a wallet from local storage.
+    ///
+    /// Clears the default wallet if it pointed at the deleted one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet to delete
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(KeyPair)` - The key pair of the deleted wallet
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn delete_wallet(&mut self, name: &str) -> Result<KeyPair> {
+        let keypair = self.wallets.get_wallet(name)
+            .cloned()
+            .ok_or_else(|| WalletError::WalletNotFound(name.to_string()))?;
+
+        self.wallets.remove_wallet(name)?;
+
+        Config::update(|settings| {
+            let is_default = settings.default_wallet.as_deref() == Some(name);
+            if is_default {
+                settings.default_wallet = None;
+            }
+            is_default
+        })?;
+        Ok(keypair)
+    }
+
+    /// Renames a wallet in local storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - The current name of the wallet
+    /// * `new` - The new name for the wallet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the wallet is renamed successfully
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet named `old` exists
+    /// * `Err(WalletError::WalletExists)` - If a wallet named `new` already exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn rename_wallet(&mut self, old: &str, new: &str) -> Result<()> {
+        self.wallets.rename_wallet(old, new)?;
+
+        // Derived addresses refer to their parent by name
+        for child in self.derived_wallets(old) {
+            self.wallets.update_wallet(&child, |keypair| {
+                if let Some(derivation) = &mut keypair.derivation {
+                    derivation.parent = new.to_string();
+                }
+            })?;
+        }
+
+        // Keep the default pointing at the same key pair
+        Config::update(|settings| {
+            let is_default = settings.default_wallet.as_deref() == Some(old);
+            if is_default {
+                settings.default_wallet = Some(new.to_string());
+            }
+            is_default
+        })
+    }
+
+    /// Changes the tags and label of a wallet.
+    ///
+    /// Tags are kept sorted and without duplicates. Adding a tag the wallet
+    /// already has, or removing one it does not have, changes nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet
+    /// * `add` - Tags to add
+    /// * `remove` - Tags to remove
+    /// * `label` - New label, `Some("")` to clear it, `None` to keep it
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(KeyPair)` - The wallet with its updated tags and label
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError::InvalidArgument)` - If a tag is empty or contains whitespace or commas
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn tag_wallet(&mut self, name: &str, add: &[String], remove: &[String], label: Option<&str>) -> Result<KeyPair> {
+        if let Some(tag) = add.iter().find(|tag| tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',')) {
+            return Err(WalletError::InvalidArgument {
+                message: format!("tag '{}' must not be empty or contain whitespace or commas", tag),
             });
         }
-        
-        Ok(response_inner.success)
+
+        self.wallets.update_wallet(name, |keypair| {
+            keypair.tags.extend(add.iter().cloned());
+            keypair.tags.retain(|tag| !remove.contains(tag));
+            keypair.tags.sort();
+            keypair.tags.dedup();
+            if let Some(label) = label {
+                keypair.label = Some(label.trim().to_string()).filter(|label| !label.is_empty());
+            }
+        })?;
+        Ok(self.wallets.get_wallet(name).cloned().expect("wallet was just updated"))
     }
 
-    /// Requests funds from the blockchain's faucet.
+    /// Derives an address from a mnemonic wallet's seed and stores it as a wallet.
     ///
-    /// Submits a request to the blockchain's faucet service to send funds to
-    /// the specified wallet, typically used for testing purposes.
+    /// The key is derived with BIP32 at the BIP44-style path
+    /// `m/44'/1'/0'/0/<index>`, so the same mnemonic always yields the same
+    /// addresses. The derived wallet records its parent, index and path and
+    /// can be used like any other wallet.
     ///
     /// # Arguments
     ///
-    /// * `wallet_name` - Name of the wallet to receive funds
+    /// * `wallet_name` - Name of the mnemonic wallet to derive from
+    /// * `index` - Index of the address, below 2^31
+    /// * `name` - Name for the derived wallet, `<wallet_name>-<index>` if not given
     ///
     /// # Returns
-    /// 
-    /// * `Ok(u64)` - The amount of coins received
-    /// * `Err(WalletError::WalletNotFound)` - If the wallet cannot be found
-    /// * `Err(WalletError)` - If an error occurs with the blockchain service
-    pub async fn request_faucet(&mut self, wallet_name: &str) -> Result<u64> {
-        let keypair = self.wallets.get_wallet(wallet_name)
+    ///
+    /// * `Ok((String, KeyPair))` - The derived wallet's name and key pair
+    /// * `Err(WalletError::WalletNotFound)` - If the parent wallet cannot be found
+    /// * `Err(WalletError::WalletExists)` - If the name is taken or the address was already derived
+    /// * `Err(WalletError::InvalidArgument)` - If the index is too large or the wallet has no seed to derive from
+    /// * `Err(WalletError)` - If derivation or saving fails
+    pub fn derive_address(&mut self, wallet_name: &str, index: u32, name: Option<&str>) -> Result<(String, KeyPair)> {
+        let parent = self.wallets.get_wallet(wallet_name)
             .ok_or_else(|| WalletError::WalletNotFound(wallet_name.to_string()))?;
-
-        let request = Request::new(FaucetRequest {
-            address: keypair.public_key.clone(),
-        });
-        
-        let response = self.client.request_faucet(request).await?;
-        let response_inner = response.into_inner();
-        
-        if !response_inner.success {
-            return Err(WalletError::FaucetFailed { 
-                message: response_inner.message 
+        if index >= HARDENED_INDEX {
+            return Err(WalletError::InvalidArgument {
+                message: format!("address index must be below {}, got {}", HARDENED_INDEX, index),
             });
         }
-        
-        Ok(response_inner.amount)
+        let chain_code = match (&parent.chain_code, parent.mnemonic) {
+            (Some(chain_code), _) => chain_code,
+            (None, true) => return Err(WalletError::InvalidArgument {
+                message: format!(
+                    "wallet '{}' was created before address derivation was supported; recover it from its mnemonic to derive addresses",
+                    wallet_name
+                ),
+            }),
+            (None, false) => return Err(WalletError::InvalidArgument {
+                message: format!("wallet '{}' was not created from a mnemonic, so it has no seed to derive addresses from", wallet_name),
+            }),
+        };
+        if let Some(existing) = self.derived_wallets(wallet_name).into_iter().find(|child| {
+            self.wallets.get_wallet(child)
+                .and_then(|keypair| keypair.derivation.as_ref())
+                .is_some_and(|derivation| derivation.index == index)
+        }) {
+            return Err(WalletError::WalletExists(existing));
+        }
+
+        let mut path = DERIVATION_PATH_PREFIX.to_vec();
+        path.push(index);
+        let mut secret_key = decode_stored_key(wallet_name, parent)?;
+        let mut chain_code: [u8; 32] = hex::decode(chain_code)?
+            .try_into()
+            .map_err(|_| WalletError::InvalidPrivateKey {
+                message: "chain code must be 32 bytes".to_string(),
+            })?;
+        for &child in &path {
+            (secret_key, chain_code) = derive_child_key(&secret_key, &chain_code, child)?;
+        }
+
+        let name = name
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}-{}", wallet_name, index));
+        if self.wallets.get_wallet(&name).is_some() {
+            return Err(WalletError::WalletExists(name));
+        }
+        let keypair = KeyPair {
+            private_key: Some(encode_secret_key(&secret_key, KeyFormat::Hex)),
+            public_key: hex::encode(secret_key.public_key(&Secp256k1::new()).serialize()),
+            demo: parent.demo,
+            mnemonic: false,
+            nonce: 0,
+            chain_code: None,
+            derivation: Some(Derivation {
+                parent: wallet_name.to_string(),
+                index,
+                path: format_derivation_path(&path),
+            }),
+            label: None,
+            tags: Vec::new(),
+            created_at: self.now()?,
+        };
+        self.wallets.add_wallet(&name, keypair.clone(), false)?;
+        Ok((name, keypair))
     }
 
-    /// Lists all wallets in local storage.
-    /// 
+    /// Returns the names of the wallets derived from a wallet, by index.
+    pub fn derived_wallets(&self, wallet_name: &str) -> Vec<String> {
+        let mut children: Vec<(u32, String)> = self.wallets.wallets
+            .iter()
+            .filter_map(|(name, keypair)| {
+                let derivation = keypair.derivation.as_ref()?;
+                (derivation.parent == wallet_name).then(|| (derivation.index, name.clone()))
+            })
+            .collect();
+        children.sort();
+        children.into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Makes a wallet the default for commands whose wallet argument is omitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet to use by default
+    ///
     /// # Returns
-    /// 
-    /// A vector of (name, keypair) tuples for all wallets in local storage.
-    pub fn list_wallets(&self) -> Vec<(String, KeyPair)> {
-        self.wallets.wallets.iter()
-            .map(|(name, keypair)| (name.clone(), keypair.clone()))
-            .collect()
+    ///
+    /// * `Ok(())` - If the default is saved successfully
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError)` - If an error occurs while saving the config file
+    pub fn set_default_wallet(&self, name: &str) -> Result<()> {
+        if self.wallets.get_wallet(name).is_none() {
+            return Err(WalletError::WalletNotFound(name.to_string()));
+        }
+
+        Config::update(|settings| {
+            settings.default_wallet = Some(name.to_string());
+            true
+        })
     }
 
-    /// Gets a wallet by name from local storage.
+    /// Exports the private key of a wallet.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the wallet to retrieve
+    /// * `name` - The name of the wallet to export
+    /// * `format` - The encoding to export the key in
     ///
     /// # Returns
     ///
-    /// * `Some(&KeyPair)` - The wallet's key pair if found
-    /// * `None` - If no wallet with the given name exists
+    /// * `Ok(Zeroizing<String>)` - The encoded private key, wiped from memory when dropped
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError)` - If the stored private key is invalid
+    pub fn export_private_key(&self, name: &str, format: KeyFormat) -> Result<Zeroizing<String>> {
+        let keypair = self.wallets.get_wallet(name)
+            .ok_or_else(|| WalletError::WalletNotFound(name.to_string()))?;
+
+        let mut secret_key = decode_stored_key(name, keypair)?;
+        let encoded = encode_secret_key(&secret_key, format);
+        secret_key.non_secure_erase();
+        Ok(encoded)
+    }
+
+    /// Creates a set of demo wallets derived deterministically from a seed.
+    ///
+    /// Wallet `demo-N` gets the private key `SHA-256("<seed>:<N>")`, so the same
+    /// seed always produces the same addresses. These keys are public by
+    /// construction and must never hold real value.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of wallets to create
+    /// * `seed` - Seed string the keys are derived from
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<(String, KeyPair)>)` - The names and key pairs of the demo wallets
+    /// * `Err(WalletError::WalletExists)` - If a non-demo wallet already uses one of the names
+    /// * `Err(WalletError)` - If an error occurs while deriving or storing the wallets
+    pub fn seed_wallets(&mut self, count: u32, seed: &str) -> Result<Vec<(String, KeyPair)>> {
+        let names: Vec<String> = (1..=count).map(|i| format!("demo-{}", i)).collect();
+
+        // Refuse before writing anything if a real wallet would be overwritten
+        if let Some(name) = names.iter()
+            .find(|name| self.wallets.get_wallet(name).is_some_and(|kp| !kp.demo))
+        {
+            return Err(WalletError::WalletExists(name.clone()));
+        }
+
+        let secp = Secp256k1::new();
+        let mut seeded = Vec::with_capacity(names.len());
+        for (i, name) in names.into_iter().enumerate() {
+            let digest = Sha256::digest(format!("{}:{}", seed, i + 1).as_bytes());
+            let secret_key = SecretKey::from_slice(&digest)
+                .map_err(|e| WalletError::InvalidPrivateKey { 
+                    message: e.to_string() 
+                })?;
+            let public_key = secret_key.public_key(&secp);
+
+            let keypair = KeyPair {
+                private_key: Some(encode_secret_key(&secret_key, KeyFormat::Hex)),
+                public_key: hex::encode(public_key.serialize()),
+                demo: true,
+                mnemonic: false,
+                nonce: 0,
+                chain_code: None,
+                derivation: None,
+                label: None,
+                tags: Vec::new(),
+                created_at: self.now()?,
+            };
+
+            self.wallets.add_wallet(&name, keypair.clone(), false)?;
+            seeded.push((name, keypair));
+        }
+
+        self.warnings.push(Warning::DemoKeys);
+        Ok(seeded)
+    }
+
+    /// Gets the balance for a wallet.
+    ///
+    /// Queries the blockchain service for the current balance of the wallet
+    /// specified by name or public key. With a cache TTL configured, a
+    /// balance fetched within the TTL is returned without a round-trip.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Name of a wallet in local storage or a public key
+    /// * `refresh` - Whether to ignore a cached balance and ask the node
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The wallet's balance in coins
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the input looks like a public key but is not a valid one
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn get_balance(&mut self, wallet_name_or_key: &str, refresh: bool) -> Result<u64> {
+        let address = self.resolve_address(wallet_name_or_key)?;
+        if !refresh {
+            if let Some(balance) = self.cached_balance(&address)? {
+                return Ok(balance);
+            }
+        }
+        
+        let message = BalanceRequest {
+            address: address.clone()
+        };
+        self.trace_request("GetBalance", &message);
+        
+        let response = self.call("GetBalance", |mut client| {
+            let request = Request::new(message.clone());
+            async move { client.get_balance(request).await }
+        }).await?;
+        self.trace_response("GetBalance", &response);
+        let balance = response?.into_inner().balance;
+        self.cache_balance(address, balance)?;
+        Ok(balance)
+    }
+
+    /// Returns the cached balance of an address if it is younger than the cache TTL.
+    fn cached_balance(&self, address: &str) -> Result<Option<u64>> {
+        let Some(cached) = self.balance_cache.balances.get(address) else {
+            return Ok(None);
+        };
+        let age = self.config.clock.now()?.saturating_sub(cached.fetched_at);
+        Ok((age < self.config.cache_ttl.as_secs()).then_some(cached.balance))
+    }
+
+    /// Remembers a freshly fetched balance when caching is enabled.
+    ///
+    /// Failing to write the cache file does not fail the lookup.
+    fn cache_balance(&mut self, address: String, balance: u64) -> Result<()> {
+        if self.config.cache_ttl.is_zero() {
+            return Ok(());
+        }
+        let fetched_at = self.config.clock.now()?;
+        self.balance_cache
+            .balances
+            .insert(address, CachedBalance { balance, fetched_at });
+        let _ = self.balance_cache.save();
+        Ok(())
+    }
+
+    /// Forgets the cached balances of addresses whose balance has just changed.
+    fn invalidate_balances(&mut self, addresses: &[&str]) {
+        let before = self.balance_cache.balances.len();
+        for address in addresses {
+            self.balance_cache.balances.remove(*address);
+        }
+        if self.balance_cache.balances.len() != before {
+            let _ = self.balance_cache.save();
+        }
+    }
+
+    /// Gets the balance of every wallet in local storage and their total.
+    ///
+    /// Balances are queried concurrently, with at most
+    /// `MAX_CONCURRENT_QUERIES` requests in flight. A failed lookup is
+    /// reported in the summary instead of aborting the others.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BalanceSummary)` - Per-wallet balances, failures and the total
+    pub async fn total_balance(&mut self) -> Result<BalanceSummary> {
+        let names: Vec<String> = self.wallets.wallets.keys().cloned().collect();
+        self.wallet_balances(&names, true).await
+    }
+
+    /// Gets the balances of the named local wallets and their total.
+    ///
+    /// Like `total_balance`, but only for the given wallets, and with
+    /// `refresh` unset a balance younger than the cache TTL is taken from
+    /// the cache instead of asking the node. Names that match no wallet are
+    /// skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - Names of the wallets to look up
+    /// * `refresh` - Whether to ignore cached balances and ask the node
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BalanceSummary)` - Per-wallet balances, failures and the total
+    /// * `Err(WalletError)` - If a balance is needed but the node cannot be reached
+    pub async fn wallet_balances(&mut self, names: &[String], refresh: bool) -> Result<BalanceSummary> {
+        let mut summary = BalanceSummary {
+            balances: Vec::new(),
+            failures: Vec::new(),
+            total: 0,
+        };
+        let mut uncached = Vec::new();
+        for name in names {
+            let Some(keypair) = self.wallets.wallets.get(name) else {
+                continue;
+            };
+            let address = keypair.public_key.clone();
+            match self.cached_balance(&address)? {
+                Some(balance) if !refresh => {
+                    summary.total = summary.total.saturating_add(balance);
+                    summary.balances.push((name.clone(), balance));
+                }
+                _ => uncached.push((name.clone(), address)),
+            }
+        }
+
+        if !uncached.is_empty() {
+            self.query_balances(uncached, &mut summary).await?;
+        }
+        summary.balances.sort_by(|a, b| a.0.cmp(&b.0));
+        summary.failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(summary)
+    }
+
+    /// Asks the node for the balances of (name, address) pairs and adds them to a summary.
+    async fn query_balances(&mut self, wallets: Vec<(String, String)>, summary: &mut BalanceSummary) -> Result<()> {
+        let client = self.client().await?;
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+        let mut queries = JoinSet::new();
+        for (name, address) in wallets {
+            let message = BalanceRequest { address };
+            self.trace_request("GetBalance", &message);
+
+            let name = name.clone();
+            let client = client.clone();
+            let config = self.config.clone();
+            let server = self.server().to_string();
+            let semaphore = semaphore.clone();
+            queries.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let response = with_retry("GetBalance", &server, &config, || {
+                    let mut client = client.clone();
+                    let request = Request::new(message.clone());
+                    async move { client.get_balance(request).await }
+                }).await;
+                (name, message.address, response)
+            });
+        }
+
+        while let Some(joined) = queries.join_next().await {
+            let (name, address, response) = joined.expect("balance query task panicked");
+            self.trace_response("GetBalance", &response);
+            match response {
+                Ok(response) => {
+                    let balance = response.into_inner().balance;
+                    self.cache_balance(address, balance)?;
+                    summary.total = summary.total.saturating_add(balance);
+                    summary.balances.push((name, balance));
+                }
+                Err(status) => summary.failures.push((name, status.into())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a transaction from one wallet to another.
+    ///
+    /// Signs and submits a transaction to transfer coins from the sender's wallet
+    /// to the recipient. The recipient can be specified by wallet name or public key.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_wallet` - Name of the sender's wallet in local storage
+    /// * `to_name_or_key` - Name or public key of the recipient
+    /// * `amount` - Number of coins to transfer
+    /// * `options` - Optional transaction settings
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The id of the transaction sent
+    /// * `Err(WalletError::WalletNotFound)` - If the sender wallet cannot be found
+    /// * `Err(WalletError::WalletNotFound)` - If the recipient name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the recipient looks like a public key but is not a valid one
+    /// * `Err(WalletError::AmbiguousRecipient)` - If the recipient name matches different addresses
+    /// * `Err(WalletError::SelfSend)` - If the recipient is the sender's own address and `options.allow_self` is not set
+    /// * `Err(WalletError::InsufficientFunds)` - If the balance check finds too few coins
+    /// * `Err(WalletError)` - If an error occurs during signing or submission
+    pub async fn send_transaction(
+        &mut self,
+        from_wallet: &str,
+        to_name_or_key: &str,
+        amount: u64,
+        options: &SendOptions,
+    ) -> Result<String> {
+        let transaction = self.sign_transaction(
+            from_wallet,
+            to_name_or_key,
+            amount,
+            self.config.clock.now()?,
+            options,
+        )?;
+        if !options.skip_balance_check {
+            self.check_funds(&transaction.from, amount.saturating_add(options.fee)).await?;
+        }
+        let id = transaction_id(&transaction);
+        self.submit_signed(transaction).await.map(|_| id)
+    }
+
+    /// Checks that an address's balance covers an amount before sending from it.
+    ///
+    /// The node would reject an unaffordable transaction anyway; checking
+    /// first gives a precise error without submitting anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - Address of the sender, as in the transaction's `from` field
+    /// * `amount` - Number of coins about to be spent, including any fee
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the balance is at least `amount`
+    /// * `Err(WalletError::InsufficientFunds)` - If the balance is lower than `amount`
+    /// * `Err(WalletError)` - If the balance cannot be queried
+    pub async fn check_funds(&mut self, sender: &str, amount: u64) -> Result<()> {
+        // A stale cached balance could wrongly block or allow the send. The
+        // address is looked up as a key so no wallet or contact name can shadow it
+        let sender = format!("{}{}", AddressSource::Key.prefix(), sender);
+        let available = self.get_balance(&sender, true).await?;
+        if available < amount {
+            return Err(WalletError::InsufficientFunds {
+                needed: amount,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes a percentage of a sender's balance, rounded down.
+    ///
+    /// The balance is read from the node rather than the cache, like
+    /// `sweep` does, so the share is based on what can actually be spent.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Name of a wallet in local storage, or the address of a raw key
+    /// * `hundredths` - The percentage in hundredths, as returned by `parse_percent`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((u64, u64))` - The balance and the share of it
+    /// * `Err(WalletError)` - If the sender cannot be resolved or the balance cannot be queried
+    pub async fn balance_share(&mut self, from: &str, hundredths: u64) -> Result<(u64, u64)> {
+        // A contact sharing the wallet's name must not be mistaken for the sender
+        let sender = match self.wallets.get_wallet(from) {
+            Some(_) => format!("{}{}", AddressSource::Wallet.prefix(), from),
+            None => from.to_string(),
+        };
+        let balance = self.get_balance(&sender, true).await?;
+        Ok((balance, percent_of(balance, hundredths)))
+    }
+
+    /// Sends a wallet's entire balance to another address in one transaction.
+    ///
+    /// The balance is read from the node just before signing, so nothing has
+    /// to be typed in that could go stale. Funds arriving after that are left
+    /// behind for a later sweep. The fee comes out of the balance, so the
+    /// recipient gets the rest.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_wallet` - Name of the wallet to empty
+    /// * `to_name_or_key` - Name or public key of the recipient
+    /// * `fee` - Fee to pay, 0 for none
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The amount sent, or 0 if the balance did not exceed the fee and nothing was submitted
+    /// * `Err(WalletError::WalletNotFound)` - If the sender wallet cannot be found
+    /// * `Err(WalletError::WalletNotFound)` - If the recipient name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the recipient looks like a public key but is not a valid one
+    /// * `Err(WalletError::AmbiguousRecipient)` - If the recipient name matches different addresses
+    /// * `Err(WalletError::SelfSend)` - If the recipient is the wallet being swept
+    /// * `Err(WalletError)` - If the balance cannot be queried or the node rejects the transaction
+    pub async fn sweep(&mut self, from_wallet: &str, to_name_or_key: &str, fee: u64) -> Result<u64> {
+        // get_balance also accepts raw keys, but only a local wallet can sign
+        let from = match self.wallets.get_wallet(from_wallet) {
+            Some(keypair) => keypair.public_key.clone(),
+            None => return Err(WalletError::WalletNotFound(from_wallet.to_string())),
+        };
+        let to = self.resolve_address(to_name_or_key)?;
+        let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+        if canonical(&to) == canonical(&from) {
+            return Err(WalletError::SelfSend);
+        }
+
+        let sender = format!("{}{}", AddressSource::Wallet.prefix(), from_wallet);
+        let balance = self.get_balance(&sender, true).await?;
+        if balance <= fee {
+            return Ok(0);
+        }
+        let amount = balance - fee;
+        let options = SendOptions {
+            fee,
+            ..SendOptions::default()
+        };
+        let transaction = self.sign_transaction(from_wallet, to_name_or_key, amount, self.config.clock.now()?, &options)?;
+        self.submit_signed(transaction).await?;
+        Ok(amount)
+    }
+
+    /// Builds and signs a transaction without touching the network.
+    ///
+    /// The result can be submitted right away with `submit_signed`, or saved
+    /// and broadcast later from another machine. The transaction carries the
+    /// sender's next nonce, which is only recorded once it is accepted or
+    /// handed off with `record_nonce`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_wallet` - Name of the sender's wallet in local storage
+    /// * `to_name_or_key` - Name or public key of the recipient
+    /// * `amount` - Number of coins to transfer
+    /// * `timestamp` - Creation time of the transaction (unix seconds)
+    /// * `options` - Optional transaction settings
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Transaction)` - The signed transaction
+    /// * `Err(WalletError::WalletNotFound)` - If the sender wallet cannot be found
+    /// * `Err(WalletError::WalletNotFound)` - If the recipient name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the recipient looks like a public key but is not a valid one
+    /// * `Err(WalletError::AmbiguousRecipient)` - If the recipient name matches different addresses
+    /// * `Err(WalletError::SelfSend)` - If the recipient is the sender's own address and `options.allow_self` is not set
+    /// * `Err(WalletError::InvalidArgument)` - If the memo is longer than `MAX_MEMO_BYTES`
+    /// * `Err(WalletError)` - If the stored private key is invalid or signing fails
+    pub fn sign_transaction(
+        &mut self,
+        from_wallet: &str,
+        to_name_or_key: &str,
+        amount: u64,
+        timestamp: u64,
+        options: &SendOptions,
+    ) -> Result<Transaction> {
+        // Get sender's keypair and the signer holding its key
+        let keypair = self.wallets.get_wallet(from_wallet)
+            .ok_or_else(|| WalletError::WalletNotFound(from_wallet.to_string()))?;
+        let signer = self.signer(from_wallet, keypair)?;
+        // The stored nonce only advances once the node accepts a transaction,
+        // so a failed send leaves no gap and its nonce is simply reused
+        let (from, nonce) = (keypair.public_key.clone(), keypair.nonce + 1);
+
+        let to_address = self.signing_recipient(&from, to_name_or_key, timestamp, options)?;
+        build_transaction(signer.as_ref(), from, to_address, amount, timestamp, nonce, options)
+    }
+
+    /// Resolves the signer for a stored wallet.
+    ///
+    /// A configured external signer for the wallet's public key takes
+    /// precedence, so even a watch-only wallet can send through one.
+    /// Otherwise the wallet's stored private key signs.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Box<dyn Signer>)` - The signer to sign the wallet's transactions with
+    /// * `Err(WalletError::WatchOnly)` - If the wallet has no private key and no external signer
+    /// * `Err(WalletError)` - If the stored private key is invalid
+    fn signer(&self, name: &str, keypair: &KeyPair) -> Result<Box<dyn Signer>> {
+        let address = normalize_address(&keypair.public_key).unwrap_or_else(|_| keypair.public_key.clone());
+        if let Some(signer) = self.config.signers.iter().find(|signer| display_address(&signer.public_key()) == address) {
+            return Ok(Box::new(Arc::clone(signer)));
+        }
+        Ok(Box::new(LocalSigner::new(decode_stored_key(name, keypair)?)))
+    }
+
+    /// Builds and signs a transaction with a private key that is not stored as a wallet.
+    ///
+    /// Like `sign_transaction`, but the sender is given by its private key,
+    /// which is never written to storage. If the key belongs to a local
+    /// wallet, that wallet's next nonce is used; otherwise the transaction
+    /// carries no nonce.
+    ///
+    /// # Arguments
+    ///
+    /// * `private_key` - The sender's private key (hex or WIF)
+    /// * `to_name_or_key` - Name or public key of the recipient
+    /// * `amount` - Number of coins to transfer
+    /// * `timestamp` - Creation time of the transaction (unix seconds)
+    /// * `options` - Optional transaction settings
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Transaction)` - The signed transaction
+    /// * `Err(WalletError::InvalidPrivateKey)` - If the private key is invalid
+    /// * `Err(WalletError::WalletNotFound)` - If the recipient name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the recipient looks like a public key but is not a valid one
+    /// * `Err(WalletError::AmbiguousRecipient)` - If the recipient name matches different addresses
+    /// * `Err(WalletError::SelfSend)` - If the recipient is the sender's own address and `options.allow_self` is not set
+    /// * `Err(WalletError::InvalidArgument)` - If the memo is longer than `MAX_MEMO_BYTES`
+    pub fn sign_with_key(
+        &mut self,
+        private_key: &str,
+        to_name_or_key: &str,
+        amount: u64,
+        timestamp: u64,
+        options: &SendOptions,
+    ) -> Result<Transaction> {
+        let secret_key = decode_secret_key(private_key)?;
+        let from = display_address(&secret_key.public_key(&Secp256k1::new()));
+        let nonce = self.wallets.name_for_address(&from)
+            .and_then(|name| self.wallets.get_wallet(name))
+            .map_or(0, |keypair| keypair.nonce + 1);
+
+        let to_address = self.signing_recipient(&from, to_name_or_key, timestamp, options)?;
+        build_transaction(&LocalSigner::new(secret_key), from, to_address, amount, timestamp, nonce, options)
+    }
+
+    /// Resolves the recipient of a transaction about to be signed.
+    ///
+    /// Refuses the sender's own address unless `options.allow_self` is set.
+    /// Warns about recipients that are neither a wallet nor a contact, and
+    /// about not-before times far in the future.
+    fn signing_recipient(&mut self, from: &str, to_name_or_key: &str, timestamp: u64, options: &SendOptions) -> Result<String> {
+        let to_address = self.resolve_address(to_name_or_key)?;
+        let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+        if !options.allow_self && canonical(&to_address) == canonical(from) {
+            return Err(WalletError::SelfSend);
+        }
+        let is_known = self.wallets.wallets.values().any(|kp| kp.public_key == to_address)
+            || self.address_book.contacts.values().any(|address| *address == to_address);
+        if !is_known {
+            self.warnings.push(Warning::UnknownRecipient { address: to_address.clone() });
+        }
+
+        if options.not_before > timestamp.saturating_add(NOT_BEFORE_WARNING_SECS) {
+            self.warnings.push(Warning::DistantNotBefore {
+                not_before: options.not_before,
+                seconds_ahead: options.not_before - timestamp,
+            });
+        }
+        Ok(to_address)
+    }
+
+    /// Returns the current time in unix seconds from the client's clock.
+    pub fn now(&self) -> Result<u64> {
+        self.config.clock.now()
+    }
+
+    /// Verifies a transaction's signature against its sender's public key.
+    ///
+    /// Recomputes the signing digest the same way `sign_transaction` does and
+    /// checks the compact ECDSA signature.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - The transaction to verify
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - True if the signature is valid, false if it is malformed or does not match
+    /// * `Err(WalletError::AddressInvalid)` - If the sender is not a valid public key
+    /// * `Err(WalletError)` - If the signing digest cannot be computed
+    pub fn verify_transaction(tx: &Transaction) -> Result<bool> {
+        let public_key = parse_public_key(&tx.from)?;
+        let digest = signing_digest(tx)?;
+        let msg = secp256k1::Message::from_slice(&digest)
+            .map_err(|e| WalletError::SigningFailed { 
+                message: e.to_string() 
+            })?;
+
+        let signature = match Signature::from_compact(&tx.signature) {
+            Ok(signature) => signature,
+            Err(_) => return Ok(false),
+        };
+        Ok(Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &signature, &public_key)
+            .is_ok())
+    }
+
+    /// Signs an arbitrary text message to prove control of a wallet's address.
+    ///
+    /// The signature is a compact ECDSA signature over the SHA-256 hash of the
+    /// message bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name` - The name of the wallet to sign with
+    /// * `message` - The message to sign
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The hex-encoded signature
+    /// * `Err(WalletError::WalletNotFound)` - If no wallet with the given name exists
+    /// * `Err(WalletError::WatchOnly)` - If the wallet has no private key
+    /// * `Err(WalletError::SigningFailed)` - If the message cannot be signed
+    pub fn sign_message(&self, wallet_name: &str, message: &str) -> Result<String> {
+        let keypair = self.wallets.get_wallet(wallet_name)
+            .ok_or_else(|| WalletError::WalletNotFound(wallet_name.to_string()))?;
+        let secret_key = decode_stored_key(wallet_name, keypair)?;
+
+        let digest = Sha256::digest(message.as_bytes());
+        let msg = secp256k1::Message::from_slice(&digest)
+            .map_err(|e| WalletError::SigningFailed {
+                message: e.to_string()
+            })?;
+        let signature = Secp256k1::new().sign_ecdsa(&msg, &secret_key);
+        Ok(hex::encode(signature.serialize_compact()))
+    }
+
+    /// Verifies a message signature produced by `sign_message`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The signer's public key (hex)
+    /// * `message` - The message that was signed
+    /// * `signature_hex` - The hex-encoded compact signature
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the signature is valid for the message and address
+    /// * `Err(WalletError::AddressInvalid)` - If the address is not a valid public key
+    /// * `Err(WalletError::SignatureInvalid)` - If the signature is malformed or does not match
+    pub fn verify_message(address: &str, message: &str, signature_hex: &str) -> Result<()> {
+        let public_key = parse_public_key(address)?;
+        let signature = hex::decode(signature_hex.trim())
+            .ok()
+            .and_then(|bytes| Signature::from_compact(&bytes).ok())
+            .ok_or_else(|| WalletError::SignatureInvalid {
+                message: "expected a 64-byte hex-encoded compact signature".to_string(),
+            })?;
+
+        let digest = Sha256::digest(message.as_bytes());
+        let msg = secp256k1::Message::from_slice(&digest)
+            .map_err(|e| WalletError::SigningFailed {
+                message: e.to_string()
+            })?;
+        Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &signature, &public_key)
+            .map_err(|_| WalletError::SignatureInvalid {
+                message: "signature does not match the message and address".to_string(),
+            })
+    }
+
+    /// Submits an already signed transaction to the blockchain.
+    ///
+    /// A transaction the node already has, for example because the answer
+    /// to an earlier attempt was lost, is recorded like an accepted one
+    /// before `DuplicateTransaction` is returned, so its nonce is not reused.
+    /// If the nonce cannot be saved once the node has the transaction, a
+    /// `NonceNotRecorded` warning is raised instead of an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The signed transaction
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - True if the transaction was successful
+    /// * `Err(WalletError::DuplicateTransaction)` - If the node already has this transaction
+    /// * `Err(WalletError::InsufficientFunds)` - If the node rejected the transaction for lack of funds
+    /// * `Err(WalletError::TransactionFailed)` - If the node rejected the transaction for another reason
+    /// * `Err(WalletError)` - If an error occurs during submission
+    pub async fn submit_signed(&mut self, transaction: Transaction) -> Result<bool> {
+        let id = transaction_id(&transaction);
+        self.trace_request("SubmitTransaction", &transaction);
+        let response = self.call("SubmitTransaction", |mut client| {
+            let request = Request::new(transaction.clone());
+            async move { client.submit_transaction(request).await }
+        }).await?;
+        self.trace_response("SubmitTransaction", &response);
+        let duplicate = match response {
+            Ok(response) => {
+                let response_inner = response.into_inner();
+                if response_inner.success {
+                    false
+                } else if is_duplicate_message(&response_inner.message) {
+                    true
+                } else {
+                    return Err(rejection_error(Code::Unknown, response_inner.message));
+                }
+            }
+            Err(status) if status.code() == Code::AlreadyExists => true,
+            // Transport problems and timeouts are not rejections of the transaction
+            Err(status) if is_transient(&status) => return Err(status.into()),
+            Err(status) => return Err(rejection_error(status.code(), status.message().to_string())),
+        };
+        self.invalidate_balances(&[&transaction.from, &transaction.to]);
+        // The node has accepted the transaction, so failing to save its nonce
+        // or remember it as pending must not turn a successful send into an error
+        if let Some(name) = self.wallets.name_for_address(&transaction.from).map(str::to_string) {
+            if let Err(e) = self.record_nonce(&name, transaction.nonce) {
+                self.warnings.push(Warning::NonceNotRecorded {
+                    wallet: name,
+                    nonce: transaction.nonce,
+                    error: e.to_string(),
+                });
+            }
+        }
+        // The node has no mempool RPC, so remember the transaction until it is mined
+        let _ = PendingTransactions::load().and_then(|mut pending| pending.add(&transaction));
+
+        if duplicate {
+            return Err(WalletError::DuplicateTransaction { id });
+        }
+        Ok(true)
+    }
+
+    /// Marks a wallet's nonce as used by a transaction.
+    ///
+    /// `submit_signed` does this for transactions the node accepts. Call it
+    /// directly for transactions that will be submitted elsewhere, so the
+    /// next one signed here does not reuse the nonce.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name` - Name of the sending wallet
+    /// * `nonce` - The transaction's nonce; 0 is ignored
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the nonce is recorded
+    /// * `Err(WalletError)` - If the wallet's key file cannot be updated
+    pub fn record_nonce(&mut self, wallet_name: &str, nonce: u64) -> Result<()> {
+        if nonce == 0 {
+            return Ok(());
+        }
+        // Only move forward, so a concurrent send from another process is never undone
+        self.wallets
+            .update_wallet(wallet_name, |keypair| keypair.nonce = keypair.nonce.max(nonce))
+    }
+
+    /// Sends a batch of payments from one wallet.
+    ///
+    /// Each payment is signed and submitted in turn; a failed payment is
+    /// recorded and the rest are still attempted. Payments get consecutive
+    /// timestamps so identical entries still produce distinct transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_wallet` - Name of the sender's wallet in local storage
+    /// * `payments` - The payments to make
+    /// * `fee` - Fee to pay on top of each payment, 0 for none
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Result<String>>)` - For each payment, its transaction id or the error it failed with
+    /// * `Err(WalletError::WalletNotFound)` - If the sender wallet cannot be found
+    /// * `Err(WalletError)` - If the current time cannot be determined
+    pub async fn send_batch(
+        &mut self,
+        from_wallet: &str,
+        payments: &[BatchPayment],
+        fee: u64,
+    ) -> Result<Vec<Result<String>>> {
+        if self.wallets.get_wallet(from_wallet).is_none() {
+            return Err(WalletError::WalletNotFound(from_wallet.to_string()));
+        }
+
+        let options = SendOptions {
+            fee,
+            ..SendOptions::default()
+        };
+
+        let base_timestamp = self.config.clock.now()?;
+        let mut results = Vec::with_capacity(payments.len());
+        for (i, payment) in payments.iter().enumerate() {
+            let timestamp = base_timestamp + i as u64;
+            let result = match self.sign_transaction(
+                from_wallet,
+                &payment.to,
+                payment.amount,
+                timestamp,
+                &options,
+            ) {
+                Ok(transaction) => {
+                    let id = transaction_id(&transaction);
+                    self.submit_signed(transaction).await.map(|_| id)
+                }
+                Err(e) => Err(e),
+            };
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Sends a transaction described by a template.
+    ///
+    /// The whole template is validated before anything is signed, and every
+    /// problem found is reported at once. Besides missing or invalid fields,
+    /// that covers a sender that cannot sign, a recipient that is the sender
+    /// itself, and a sender whose balance does not cover the amount and fee.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The transaction template, with any overrides already applied
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The id of the transaction sent
+    /// * `Err(WalletError::InvalidTemplate)` - If the template is incomplete, invalid or unaffordable
+    /// * `Err(WalletError)` - If an error occurs during signing or submission
+    pub async fn send_template(&mut self, template: &TransactionTemplate) -> Result<String> {
+        let mut problems = Vec::new();
+
+        let sender = match &template.from {
+            None => {
+                problems.push("missing 'from'".to_string());
+                None
+            }
+            Some(from) => match self.wallets.get_wallet(from) {
+                None => {
+                    problems.push(format!("sender wallet '{}' not found", from));
+                    None
+                }
+                Some(keypair) => {
+                    if let Err(e) = self.signer(from, keypair) {
+                        problems.push(format!("sender: {}", e));
+                    }
+                    Some(keypair.public_key.clone())
+                }
+            },
+        };
+        match &template.to {
+            None => problems.push("missing 'to'".to_string()),
+            Some(to) => match self.resolve_address(to) {
+                Err(e) => problems.push(format!("recipient: {}", e)),
+                Ok(address) => {
+                    let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+                    if sender.as_deref().is_some_and(|sender| canonical(sender) == canonical(&address)) {
+                        problems.push("recipient is the sender's own address".to_string());
+                    }
+                }
+            },
+        }
+        match template.amount {
+            None => problems.push("missing 'amount'".to_string()),
+            Some(0) => problems.push("'amount' must be greater than zero".to_string()),
+            Some(_) => {}
+        }
+        if template.memo.as_ref().is_some_and(|memo| memo.len() > MAX_MEMO_BYTES) {
+            problems.push(format!("'memo' must be at most {} bytes", MAX_MEMO_BYTES));
+        }
+        if let (Some(sender), Some(amount)) = (&sender, template.amount) {
+            match self.check_funds(sender, amount.saturating_add(template.fee)).await {
+                Ok(()) => {}
+                Err(WalletError::InsufficientFunds { needed, available }) => problems.push(format!(
+                    "amount plus fee is {} but the sender has only {}",
+                    needed, available
+                )),
+                // A node that cannot be asked is only worth reporting if nothing else is wrong
+                Err(e) if problems.is_empty() => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        match (&template.from, &template.to, template.amount) {
+            (Some(from), Some(to), Some(amount)) if problems.is_empty() => {
+                let options = SendOptions {
+                    not_before: template.not_before.unwrap_or(0),
+                    memo: template.memo.clone().unwrap_or_default(),
+                    fee: template.fee,
+                    // Checked above, with the rest of the template
+                    skip_balance_check: true,
+                    ..SendOptions::default()
+                };
+                self.send_transaction(from, to, amount, &options).await
+            }
+            _ => Err(WalletError::InvalidTemplate { problems }),
+        }
+    }
+
+    /// Requests funds from the blockchain's faucet.
+    ///
+    /// Submits a request to the blockchain's faucet service to send funds to
+    /// the specified wallet, typically used for testing purposes.
+    ///
+    /// Successful requests are logged locally. Asking again for the same
+    /// address within `FAUCET_REPEAT_WARNING_SECS` raises a warning, since the
+    /// faucet is likely to refuse; the request is still sent.
+    ///
+    /// Each request carries an idempotency key, kept in the log until the
+    /// faucet answers. If the answer is lost to a network error, asking again
+    /// for the same amount resends the same key, so a faucet that already
+    /// paid out does not pay again.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name` - Name of the wallet to receive funds
+    /// * `amount` - Amount to ask for, or `None` for the faucet's default
+    ///
+    /// # Returns
+    /// 
+    /// * `Ok(u64)` - The amount of coins received
+    /// * `Err(WalletError::WalletNotFound)` - If the wallet cannot be found
+    /// * `Err(WalletError::InvalidArgument)` - If the requested amount is zero
+    /// * `Err(WalletError::FaucetFailed)` - If the faucet refuses, with the remaining cooldown if known
+    /// * `Err(WalletError)` - If an error occurs with the blockchain service
+    pub async fn request_faucet(&mut self, wallet_name: &str, amount: Option<u64>) -> Result<u64> {
+        let keypair = self.wallets.get_wallet(wallet_name)
+            .ok_or_else(|| WalletError::WalletNotFound(wallet_name.to_string()))?;
+        if amount == Some(0) {
+            return Err(WalletError::InvalidArgument {
+                message: "faucet amount must be greater than zero".to_string(),
+            });
+        }
+
+        // The log is advisory only, so an unreadable one is treated as empty
+        let mut faucet_log = FaucetLog::load().unwrap_or_default();
+        let now = self.config.clock.now()?;
+        if let Some(last) = faucet_log.last_requests.get(&keypair.public_key) {
+            let seconds_ago = now.saturating_sub(*last);
+            if seconds_ago < FAUCET_REPEAT_WARNING_SECS {
+                self.warnings.push(Warning::RecentFaucetRequest {
+                    wallet: wallet_name.to_string(),
+                    seconds_ago,
+                });
+            }
+        }
+
+        let address = keypair.public_key.clone();
+        let amount = amount.unwrap_or(0);
+        let pending = match faucet_log.pending.get(&address) {
+            Some(pending) if pending.amount == amount => pending.clone(),
+            _ => PendingFaucetRequest {
+                key: new_idempotency_key(),
+                amount,
+                requested_at: now,
+            },
+        };
+        // Saved before sending, so even a crash mid-request leaves the key to retry with
+        let _ = faucet_log.set_pending(&address, Some(pending.clone()));
+
+        let message = FaucetRequest {
+            address,
+            amount,
+            idempotency_key: pending.key.clone(),
+        };
+        self.trace_request("RequestFaucet", &message);
+        
+        let response = self.call("RequestFaucet", |mut client| {
+            let request = Request::new(message.clone());
+            async move { client.request_faucet(request).await }
+        }).await?;
+        self.trace_response("RequestFaucet", &response);
+        let response_inner = match response {
+            Ok(response) => response.into_inner(),
+            // The faucet may have paid out, so the key stays for the next attempt
+            Err(status) if is_transient(&status) => return Err(status.into()),
+            Err(status) => {
+                let _ = faucet_log.set_pending(&message.address, None);
+                return Err(WalletError::FaucetFailed {
+                    code: status.code(),
+                    message: status.message().to_string(),
+                    retry_after: parse_retry_after(status.message()),
+                });
+            }
+        };
+        
+        if !response_inner.success {
+            let _ = faucet_log.set_pending(&message.address, None);
+            let retry_after = match response_inner.retry_after {
+                0 => parse_retry_after(&response_inner.message),
+                secs => Some(secs),
+            };
+            return Err(WalletError::FaucetFailed { 
+                code: if retry_after.is_some() { Code::ResourceExhausted } else { Code::Unknown },
+                message: response_inner.message,
+                retry_after,
+            });
+        }
+        self.invalidate_balances(&[&message.address]);
+        let _ = faucet_log.record(FaucetReceipt {
+            address: message.address,
+            amount: response_inner.amount,
+            timestamp: now,
+            key: message.idempotency_key,
+        });
+        
+        Ok(response_inner.amount)
+    }
+
+    /// Reads the local faucet log: funds received and requests still unanswered.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Only keeps entries for this wallet or address, or `None` for all
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(FaucetLog)` - The log, with receipts oldest first
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError)` - If the faucet log cannot be read
+    pub fn faucet_log(&self, wallet_name_or_key: Option<&str>) -> Result<FaucetLog> {
+        let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+        let filter = wallet_name_or_key
+            .map(|name_or_key| self.resolve_address(name_or_key))
+            .transpose()?
+            .map(|address| canonical(&address));
+        let matches = |address: &str| filter.as_ref().is_none_or(|filter| canonical(address) == *filter);
+
+        let mut log = FaucetLog::load()?;
+        log.last_requests.retain(|address, _| matches(address));
+        log.pending.retain(|address, _| matches(address));
+        log.receipts.retain(|receipt| matches(&receipt.address));
+        Ok(log)
+    }
+
+    /// Gets the transaction history for a wallet.
+    ///
+    /// Queries the blockchain service for every transaction sent from or
+    /// received by the wallet specified by name or public key, then applies
+    /// the query's filters and paging.
+    ///
+    /// Transactions are ordered oldest first (ties broken by id), so new
+    /// transactions land at the end and earlier pages stay stable.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Name of a wallet in local storage or a public key
+    /// * `query` - Filters and paging to apply
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Transaction>)` - The selected page of the wallet's transactions
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the input looks like a public key but is not a valid one
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn get_history(
+        &mut self,
+        wallet_name_or_key: &str,
+        query: &HistoryQuery,
+    ) -> Result<Vec<Transaction>> {
+        let address = self.resolve_address(wallet_name_or_key)?;
+        let query = self.resolve_history_query(query)?;
+
+        let message = HistoryRequest {
+            address
+        };
+        self.trace_request("GetHistory", &message);
+
+        let response = self.call("GetHistory", |mut client| {
+            let request = Request::new(message.clone());
+            async move { client.get_history(request).await }
+        }).await?;
+        self.trace_response("GetHistory", &response);
+
+        Ok(apply_history_query(response?.into_inner().transactions, &query))
+    }
+
+    /// Gets the merged transaction history of every wallet in local storage.
+    ///
+    /// Histories are fetched concurrently, with at most
+    /// `MAX_CONCURRENT_QUERIES` requests in flight. Transfers between two
+    /// local wallets appear in both histories but only once in the result.
+    /// A wallet whose history cannot be fetched is reported as a warning
+    /// instead of failing the whole feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Filters and paging to apply to the merged history
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Transaction>)` - The selected page of transactions, oldest first
+    pub async fn combined_history(&mut self, query: &HistoryQuery) -> Result<Vec<Transaction>> {
+        let wallets = self.wallets.wallets
+            .iter()
+            .map(|(name, keypair)| (name.clone(), keypair.public_key.clone()))
+            .collect();
+        self.merged_history(wallets, query).await
+    }
+
+    /// Gets the merged transaction history of a wallet and the addresses derived from it.
+    ///
+    /// Works like `combined_history`, limited to the wallet and its derived
+    /// addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Name of a wallet in local storage or a public key
+    /// * `query` - Filters and paging to apply to the merged history
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Transaction>)` - The selected page of transactions, oldest first
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the input looks like a public key but is not a valid one
+    pub async fn history_with_derived(&mut self, wallet_name_or_key: &str, query: &HistoryQuery) -> Result<Vec<Transaction>> {
+        let address = self.resolve_address(wallet_name_or_key)?;
+        let mut wallets = vec![(wallet_name_or_key.to_string(), address)];
+        for child in self.derived_wallets(wallet_name_or_key) {
+            if let Some(keypair) = self.wallets.get_wallet(&child) {
+                wallets.push((child.clone(), keypair.public_key.clone()));
+            }
+        }
+        self.merged_history(wallets, query).await
+    }
+
+    /// Returns the addresses whose transactions a history lists.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Name of a wallet in local storage or a public key, or `None` for every local wallet
+    /// * `include_derived` - Whether to add the addresses derived from the wallet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - The addresses in canonical form
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError::AddressInvalid)` - If the input looks like a public key but is not a valid one
+    pub fn history_addresses(&self, wallet_name_or_key: Option<&str>, include_derived: bool) -> Result<Vec<String>> {
+        let mut addresses = match wallet_name_or_key {
+            Some(name_or_key) => {
+                let mut addresses = vec![self.resolve_address(name_or_key)?];
+                if include_derived {
+                    for child in self.derived_wallets(name_or_key) {
+                        if let Some(keypair) = self.wallets.get_wallet(&child) {
+                            addresses.push(keypair.public_key.clone());
+                        }
+                    }
+                }
+                addresses
+            }
+            None => self.wallets.wallets.values().map(|keypair| keypair.public_key.clone()).collect(),
+        };
+        for address in &mut addresses {
+            if let Ok(canonical) = normalize_address(address) {
+                *address = canonical;
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Checks a history query and resolves its counterparty to a canonical address.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(HistoryQuery)` - The query, ready for `apply_history_query`
+    /// * `Err(WalletError::InvalidArgument)` - If the minimum amount is above the maximum
+    /// * `Err(WalletError)` - If the counterparty cannot be resolved
+    fn resolve_history_query(&self, query: &HistoryQuery) -> Result<HistoryQuery> {
+        if let (Some(min), Some(max)) = (query.min_amount, query.max_amount) {
+            if min > max {
+                return Err(WalletError::InvalidArgument {
+                    message: format!("minimum amount {} is above maximum amount {}", min, max),
+                });
+            }
+        }
+        let counterparty = match &query.counterparty {
+            Some(name_or_key) => Some(normalize_address(&self.resolve_address(name_or_key)?)?),
+            None => None,
+        };
+        Ok(HistoryQuery {
+            counterparty,
+            ..query.clone()
+        })
+    }
+
+    /// Fetches and merges the histories of the given `(name, address)` pairs.
+    async fn merged_history(&mut self, wallets: Vec<(String, String)>, query: &HistoryQuery) -> Result<Vec<Transaction>> {
+        let query = &self.resolve_history_query(query)?;
+        let client = self.client().await?;
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+        let mut queries = JoinSet::new();
+        for (name, address) in wallets {
+            let message = HistoryRequest { address };
+            self.trace_request("GetHistory", &message);
+
+            let client = client.clone();
+            let config = self.config.clone();
+            let server = self.server().to_string();
+            let semaphore = semaphore.clone();
+            queries.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let response = with_retry("GetHistory", &server, &config, || {
+                    let mut client = client.clone();
+                    let request = Request::new(message.clone());
+                    async move { client.get_history(request).await }
+                }).await;
+                (name, response)
+            });
+        }
+
+        let mut transactions = Vec::new();
+        let mut failures = Vec::new();
+        while let Some(joined) = queries.join_next().await {
+            let (name, response) = joined.expect("history query task panicked");
+            self.trace_response("GetHistory", &response);
+            match response {
+                Ok(response) => transactions.extend(response.into_inner().transactions),
+                Err(status) => failures.push((name, WalletError::from(status))),
+            }
+        }
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+        for (wallet, error) in failures {
+            self.warnings.push(Warning::HistoryUnavailable {
+                wallet,
+                error: error.to_string(),
+            });
+        }
+
+        // Compare whole transactions, not just signatures, so distinct
+        // transactions that happen to share a signature are both kept
+        let mut seen = HashSet::new();
+        transactions.retain(|tx| seen.insert(tx.encode_to_vec()));
+
+        Ok(apply_history_query(transactions, query))
+    }
+
+    /// Gets the entire state of the blockchain.
+    ///
+    /// The service sends the whole chain in one response, so progress is
+    /// only reported once it has arrived.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_progress` - Called with the number of blocks fetched and the total
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ProtoBlock>)` - Every block in the chain, in index order
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn get_state<F>(&mut self, mut on_progress: F) -> Result<Vec<ProtoBlock>>
+    where
+        F: FnMut(u64, u64),
+    {
+        let message = GetStateRequest {
+            address: String::new(),
+        };
+        self.trace_request("GetState", &message);
+
+        let response = self.call("GetState", |mut client| {
+            let request = Request::new(message.clone());
+            async move { client.get_state(request).await }
+        }).await?;
+        self.trace_response("GetState", &response);
+        let blocks = response?.into_inner().blocks;
+        on_progress(blocks.len() as u64, blocks.len() as u64);
+        Ok(blocks)
+    }
+
+    /// Summarizes the blockchain's current state.
+    ///
+    /// Computed from the full chain, since the service has no dedicated RPC.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ChainInfo)` - The chain's height, transaction count and latest block
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn chain_info(&mut self) -> Result<ChainInfo> {
+        let blocks = self.get_state(|_, _| {}).await?;
+        let latest = blocks.iter().max_by_key(|block| block.index);
+        Ok(ChainInfo {
+            height: blocks.len() as u64,
+            transactions: blocks.iter().map(|block| block.transactions.len() as u64).sum(),
+            latest_timestamp: latest.map(|block| block.timestamp),
+            latest_hash: latest.map(|block| block.hash.clone()),
+        })
+    }
+
+    /// Checks that the node is reachable and answering requests.
+    ///
+    /// Connects first, then times a request for the genesis block, the
+    /// cheapest call the service offers. A node without a genesis block
+    /// still counts as answering.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Duration)` - Round-trip time of the request, not counting the connection
+    /// * `Err(WalletError::ConnectionFailed)` - If the node cannot be connected to
+    /// * `Err(WalletError::Timeout)` - If the node does not answer in time
+    /// * `Err(WalletError::RpcError)` - If the node answers with an error
+    pub async fn ping(&mut self) -> Result<Duration> {
+        self.client().await?;
+        let start = Instant::now();
+        self.get_block(0).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Checks that the chain returned by the node is internally consistent.
+    ///
+    /// Every block must carry the index after the previous block's and name
+    /// the previous block's hash as its `previous_hash`. The node does not
+    /// publish how it computes block hashes, so the hashes themselves are
+    /// taken as given; a block altered without updating every later block
+    /// still breaks a link.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ChainVerification)` - The number of blocks checked and the first inconsistent one, if any
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn verify_chain(&mut self) -> Result<ChainVerification> {
+        let blocks = self.get_state(|_, _| {}).await?;
+        let first_invalid = blocks.windows(2).find_map(|pair| {
+            let (previous, block) = (&pair[0], &pair[1]);
+            if block.index != previous.index + 1 {
+                Some((block.index, ChainFault::IndexGap { previous: previous.index }))
+            } else if block.previous_hash != previous.hash {
+                Some((block.index, ChainFault::BrokenLink {
+                    expected: previous.hash.clone(),
+                    found: block.previous_hash.clone(),
+                }))
+            } else {
+                None
+            }
+        });
+        Ok(ChainVerification {
+            blocks: blocks.len() as u64,
+            first_invalid,
+        })
+    }
+
+    /// Watches for new blocks, calling `on_block` for each one as it is mined.
+    ///
+    /// Uses the server's `WatchBlocks` stream, re-establishing it with
+    /// exponential backoff when it drops. If the server does not implement
+    /// streaming, falls back to polling `get_state` every `interval`. Only
+    /// blocks mined after the call starts are reported. Runs until an error
+    /// occurs; callers stop it by dropping the future.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Polling interval used when streaming is unavailable
+    /// * `on_block` - Called with the client and each new block
+    ///
+    /// # Returns
+    ///
+    /// * `Err(WalletError)` - If a non-transient error occurs while watching
+    pub async fn watch_blocks<F>(&mut self, interval: Duration, mut on_block: F) -> Result<()>
+    where
+        F: FnMut(&Self, &ProtoBlock),
+    {
+        let mut next_index = self.get_state(|_, _| {}).await?.len() as u64;
+        let mut backoff = RETRY_BASE_DELAY;
+        loop {
+            let message = WatchBlocksRequest { from_index: next_index };
+            self.trace_request("WatchBlocks", &message);
+            let response = self.client().await?.watch_blocks(Request::new(message)).await;
+            self.trace_response("WatchBlocks", &response);
+            match response {
+                Ok(response) => {
+                    let mut stream = response.into_inner();
+                    while let Ok(Some(block)) = stream.message().await {
+                        backoff = RETRY_BASE_DELAY;
+                        next_index = next_index.max(block.index + 1);
+                        on_block(self, &block);
+                    }
+                }
+                Err(status) if status.code() == Code::Unimplemented => {
+                    return self.poll_blocks(next_index, interval, on_block).await;
+                }
+                Err(status) if is_transient(&status) => {}
+                Err(status) => return Err(status.into()),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(WATCH_MAX_BACKOFF);
+        }
+    }
+
+    /// Polls the chain for blocks at or after `next_index` every `interval`.
+    async fn poll_blocks<F>(&mut self, mut next_index: u64, interval: Duration, mut on_block: F) -> Result<()>
+    where
+        F: FnMut(&Self, &ProtoBlock),
+    {
+        loop {
+            tokio::time::sleep(interval).await;
+            for block in self.get_state(|_, _| {}).await? {
+                if block.index >= next_index {
+                    next_index = block.index + 1;
+                    on_block(self, &block);
+                }
+            }
+        }
+    }
+
+    /// Follows a wallet's activity as it is mined, like `tail -f` for its address.
+    ///
+    /// Watches new blocks with `watch_blocks`, so a dropped stream is
+    /// re-established the same way, and calls `on_transaction` for every
+    /// transaction in them that is from or to the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Wallet, contact or address to follow
+    /// * `interval` - Polling interval used when streaming is unavailable
+    /// * `count` - Stop after this many matching transactions
+    /// * `timeout` - Stop after this long
+    /// * `on_transaction` - Called with the client, the block index, the transaction and its direction
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The number of matching transactions, once `count` is reached or, without a count, `timeout` expires
+    /// * `Err(WalletError::Timeout)` - If `timeout` expires before `count` transactions matched
+    /// * `Err(WalletError::WalletNotFound)` - If the name matches no wallet or contact
+    /// * `Err(WalletError)` - If a non-transient error occurs while watching
+    pub async fn follow<F>(
+        &mut self,
+        wallet_name_or_key: &str,
+        interval: Duration,
+        count: Option<u64>,
+        timeout: Option<Duration>,
+        mut on_transaction: F,
+    ) -> Result<u64>
+    where
+        F: FnMut(&Self, u64, &Transaction, Direction),
+    {
+        let own = vec![normalize_address(&self.resolve_address(wallet_name_or_key)?)?];
+        let reached = |seen: u64| count.is_some_and(|count| seen >= count);
+        if reached(0) {
+            return Ok(0);
+        }
+
+        let done = Notify::new();
+        let mut seen = 0;
+        let watch = self.watch_blocks(interval, |client, block| {
+            for tx in &block.transactions {
+                if reached(seen) {
+                    break;
+                }
+                if let Some(direction) = transaction_direction(tx, &own) {
+                    seen += 1;
+                    on_transaction(client, block.index, tx, direction);
+                }
+            }
+            if reached(seen) {
+                done.notify_one();
+            }
+        });
+        let expired = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let timed_out = tokio::select! {
+            result = watch => return result.map(|_| seen),
+            _ = done.notified() => false,
+            _ = expired => count.is_some(),
+        };
+        if timed_out {
+            return Err(WalletError::Timeout {
+                operation: format!("wait for {} transaction(s) of {}", count.unwrap_or_default(), wallet_name_or_key),
+            });
+        }
+        Ok(seen)
+    }
+
+    /// Gets a single block by its index.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the block in the chain
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(ProtoBlock))` - The block at the given index
+    /// * `Ok(None)` - If the chain has no block at that index
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn get_block(&mut self, index: u64) -> Result<Option<ProtoBlock>> {
+        let message = GetBlockRequest { index };
+        self.trace_request("GetBlock", &message);
+
+        let response = self.call("GetBlock", |mut client| {
+            let request = Request::new(message.clone());
+            async move { client.get_block(request).await }
+        }).await?;
+        self.trace_response("GetBlock", &response);
+        match response {
+            Ok(response) => Ok(response.into_inner().block),
+            Err(status) if status.code() == Code::NotFound => Ok(None),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Gets the blocks in an inclusive range of indices.
+    ///
+    /// The service has no range request, so blocks are fetched concurrently
+    /// with at most `MAX_CONCURRENT_QUERIES` requests in flight. Indices past
+    /// the end of the chain are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Index of the first block
+    /// * `end` - Index of the last block
+    /// * `on_progress` - Called with the number of indices queried so far and the total
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ProtoBlock>)` - The blocks found in the range, in index order
+    /// * `Err(WalletError::InvalidArgument)` - If `start > end` or the range is too large
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn get_blocks<F>(&mut self, start: u64, end: u64, on_progress: F) -> Result<Vec<ProtoBlock>>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut blocks = Vec::new();
+        self.stream_blocks(start, end, on_progress, |block| blocks.push(block)).await?;
+        Ok(blocks)
+    }
+
+    /// Gets the blocks in an inclusive range of indices one at a time.
+    ///
+    /// Fetches like `get_blocks`, but hands each block to `on_block` in
+    /// index order as soon as every block before it has arrived, so only
+    /// blocks that arrive early are held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Index of the first block
+    /// * `end` - Index of the last block
+    /// * `on_progress` - Called with the number of indices queried so far and the total
+    /// * `on_block` - Called with each block found in the range, in index order
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The number of blocks found in the range
+    /// * `Err(WalletError::InvalidArgument)` - If `start > end` or the range is too large
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn stream_blocks<F, B>(&mut self, start: u64, end: u64, mut on_progress: F, mut on_block: B) -> Result<u64>
+    where
+        F: FnMut(u64, u64),
+        B: FnMut(ProtoBlock),
+    {
+        if start > end {
+            return Err(WalletError::InvalidArgument {
+                message: format!("start {} is after end {}", start, end),
+            });
+        }
+        if end - start >= MAX_BLOCK_RANGE {
+            return Err(WalletError::InvalidArgument {
+                message: format!("at most {} blocks can be fetched at once", MAX_BLOCK_RANGE),
+            });
+        }
+
+        let client = self.client().await?;
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+        let mut queries = JoinSet::new();
+        for index in start..=end {
+            let message = GetBlockRequest { index };
+            self.trace_request("GetBlock", &message);
+
+            let client = client.clone();
+            let config = self.config.clone();
+            let server = self.server().to_string();
+            let semaphore = semaphore.clone();
+            queries.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let response = with_retry("GetBlock", &server, &config, || {
+                    let mut client = client.clone();
+                    let request = Request::new(message.clone());
+                    async move { client.get_block(request).await }
+                }).await;
+                (index, response)
+            });
+        }
+
+        let total = end - start + 1;
+        // Answers that arrived before those of lower indices, by index
+        let mut early = BTreeMap::new();
+        let mut next = start;
+        let mut found = 0;
+        let mut done = 0;
+        while let Some(joined) = queries.join_next().await {
+            let (index, response) = joined.expect("block query task panicked");
+            done += 1;
+            on_progress(done, total);
+            self.trace_response("GetBlock", &response);
+            let block = match response {
+                Ok(response) => response.into_inner().block,
+                Err(status) if status.code() == Code::NotFound => None,
+                Err(status) => return Err(status.into()),
+            };
+            early.insert(index, block);
+            while let Some(block) = early.remove(&next) {
+                if let Some(block) = block {
+                    found += 1;
+                    on_block(block);
+                }
+                next += 1;
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Lists all wallets in local storage.
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of (name, keypair) tuples for all wallets in local storage.
+    pub fn list_wallets(&self) -> Vec<(String, KeyPair)> {
+        self.wallets.wallets.iter()
+            .map(|(name, keypair)| (name.clone(), keypair.clone()))
+            .collect()
+    }
+
+    /// Labels an address for display.
+    ///
+    /// Addresses of local wallets are shown as `name (04ab12cd…a1b2c3)`; any
+    /// other address is shown as abbreviated hex. Both abbreviate the same
+    /// way, and with `--full` the whole address is shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key address to label
+    ///
+    /// # Returns
+    ///
+    /// The display label for the address.
+    pub fn label_address(&self, address: &str) -> String {
+        match self.wallets.name_for_address(address) {
+            Some(name) => format!("{} ({})", name, display::key(address)),
+            None => display::key(address),
+        }
+    }
+
+    /// Dumps an outgoing RPC message to stderr when RPC tracing is enabled.
+    ///
+    /// Proto messages never carry private keys, so they are printed in full.
+    fn trace_request<T: Debug>(&self, method: &str, message: &T) {
+        if self.config.trace_rpc {
+            eprintln!("[rpc] {} request: {:#?}", method, message);
+        }
+    }
+
+    /// Dumps an RPC response or error status to stderr when RPC tracing is enabled.
+    fn trace_response<T: Debug>(&self, method: &str, response: &std::result::Result<Response<T>, Status>) {
+        if self.config.trace_rpc {
+            match response {
+                Ok(response) => eprintln!("[rpc] {} response: {:#?}", method, response.get_ref()),
+                Err(status) => eprintln!("[rpc] {} status: {:?}", method, status),
+            }
+        }
+    }
+
+    /// Adds a warning raised by the command itself rather than by the client.
+    ///
+    /// It is emitted with the client's own warnings, and appears in the
+    /// `warnings` array of JSON output.
+    pub fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    /// Takes the warnings raised since the last call.
+    ///
+    /// # Returns
+    ///
+    /// The collected warnings, leaving the client's list empty.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Gets a wallet by name from local storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet to retrieve
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&KeyPair)` - The wallet's key pair if found
+    /// * `None` - If no wallet with the given name exists
     pub fn get_wallet(&self, name: &str) -> Option<&KeyPair> {
         self.wallets.get_wallet(name)
     }
+
+    /// Finds a transaction by its signature.
+    ///
+    /// Fetches the chain state and scans blocks from the newest backwards,
+    /// so recent transactions are found quickly. Given the time the
+    /// transaction was signed at, the block is instead searched for by
+    /// timestamp, which takes far fewer requests on a long chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `sig_hex` - The hex-encoded transaction signature
+    /// * `timestamp` - The transaction's timestamp, if known
+    /// * `on_progress` - Called with the number of blocks fetched and the total
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some((u64, Transaction)))` - The index of the containing block and the transaction
+    /// * `Ok(None)` - If no block contains a transaction with that signature
+    /// * `Err(WalletError::InvalidTransactionId)` - If the signature is not valid hex
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn find_transaction<F>(
+        &mut self,
+        sig_hex: &str,
+        timestamp: Option<u64>,
+        on_progress: F,
+    ) -> Result<Option<(u64, Transaction)>>
+    where
+        F: FnMut(u64, u64),
+    {
+        let signature = hex::decode(sig_hex)
+            .map_err(|_| WalletError::InvalidTransactionId(sig_hex.to_string()))?;
+
+        let (_, found) = self.locate_transaction(&signature, timestamp, on_progress).await?;
+        Ok(found)
+    }
+
+    /// Counts the confirmations of a transaction.
+    ///
+    /// A transaction in the latest block has one confirmation, and each block
+    /// built on top of it adds one more.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction
+    /// * `timestamp` - The transaction's timestamp, if known, to search for its block by time
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(u64))` - The chain height minus the index of the block containing the transaction
+    /// * `Ok(None)` - If no block contains the transaction yet
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn confirmations(&mut self, signature: &[u8], timestamp: Option<u64>) -> Result<Option<u64>> {
+        let (height, found) = self.locate_transaction(signature, timestamp, |_, _| {}).await?;
+        Ok(found.map(|(index, _)| height.saturating_sub(index)))
+    }
+
+    /// Finds the block holding a transaction, along with the chain height.
+    ///
+    /// Tries the timestamp search first when the timestamp is known, and
+    /// otherwise, or if that search comes up empty, scans the whole chain
+    /// from the newest block backwards.
+    async fn locate_transaction<F>(
+        &mut self,
+        signature: &[u8],
+        timestamp: Option<u64>,
+        on_progress: F,
+    ) -> Result<(u64, Option<(u64, Transaction)>)>
+    where
+        F: FnMut(u64, u64),
+    {
+        if let Some(timestamp) = timestamp {
+            if let Some((height, index, tx)) = self.search_by_time(signature, timestamp).await? {
+                return Ok((height, Some((index, tx))));
+            }
+        }
+
+        let blocks = self.get_state(on_progress).await?;
+        let height = blocks.len() as u64;
+        let found = blocks.into_iter().rev().find_map(|block| {
+            let index = block.index;
+            block.transactions.into_iter().find(|tx| tx.signature == signature).map(|tx| (index, tx))
+        });
+        Ok((height, found))
+    }
+
+    /// Looks for a transaction in the blocks mined around its timestamp.
+    ///
+    /// Blocks are mined in time order, so a binary search over block indices
+    /// finds the first block mined at or after the timestamp. The blocks
+    /// around that guess are scanned, and the window is widened a few times
+    /// in case block and transaction clocks disagree. All of this takes
+    /// `GetBlock` requests in the order of log2(height) plus the window size,
+    /// rather than the whole chain.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some((u64, u64, Transaction)))` - The chain height, the index of the containing block and the transaction
+    /// * `Ok(None)` - If the transaction is not near its timestamp, or block timestamps turn out not to be ordered
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    async fn search_by_time(&mut self, signature: &[u8], timestamp: u64) -> Result<Option<(u64, u64, Transaction)>> {
+        let height = self.chain_height().await?;
+        let find = |block: &ProtoBlock| block.transactions.iter().find(|tx| tx.signature == signature).cloned();
+
+        // Every probe is checked against the earlier ones, since a block
+        // timestamp out of order would send the search the wrong way
+        let mut probed: BTreeMap<u64, i64> = BTreeMap::new();
+        let (mut low, mut high) = (0, height);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let Some(block) = self.get_block(mid).await? else {
+                return Ok(None);
+            };
+            if let Some(tx) = find(&block) {
+                return Ok(Some((height, mid, tx)));
+            }
+            let ordered = probed.range(..mid).next_back().is_none_or(|(_, &before)| before <= block.timestamp)
+                && probed.range(mid + 1..).next().is_none_or(|(_, &after)| block.timestamp <= after);
+            if !ordered {
+                debug!(index = mid, "block timestamps are out of order, scanning the whole chain");
+                return Ok(None);
+            }
+            probed.insert(mid, block.timestamp);
+            if block.timestamp < timestamp as i64 {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        // Scan outwards from the guess, skipping the blocks already scanned
+        let (mut scanned_from, mut scanned_to) = (low, low);
+        let mut window = TIME_SEARCH_WINDOW;
+        for _ in 0..TIME_SEARCH_WIDENINGS {
+            let from = low.saturating_sub(window);
+            let to = low.saturating_add(window).min(height);
+            for (start, end) in [(from, scanned_from), (scanned_to, to)] {
+                if start >= end {
+                    continue;
+                }
+                for block in self.get_blocks(start, end - 1, |_, _| {}).await? {
+                    if let Some(tx) = find(&block) {
+                        return Ok(Some((height, block.index, tx)));
+                    }
+                }
+            }
+            (scanned_from, scanned_to) = (from, to);
+            window *= 4;
+        }
+        debug!(timestamp, "transaction not found near its timestamp, scanning the whole chain");
+        Ok(None)
+    }
+
+    /// Counts the blocks in the chain using `GetBlock` requests only.
+    ///
+    /// Probes indices 1, 2, 4 and so on until one is missing, then bisects
+    /// between the last two probes.
+    async fn chain_height(&mut self) -> Result<u64> {
+        if self.get_block(0).await?.is_none() {
+            return Ok(0);
+        }
+        let (mut present, mut missing) = (0, 1);
+        while self.get_block(missing).await?.is_some() {
+            present = missing;
+            missing = missing.saturating_mul(2);
+        }
+        while missing - present > 1 {
+            let mid = present + (missing - present) / 2;
+            if self.get_block(mid).await?.is_some() {
+                present = mid;
+            } else {
+                missing = mid;
+            }
+        }
+        Ok(present + 1)
+    }
+
+    /// Lists the transactions submitted from here that are not in a block yet.
+    ///
+    /// The node has no mempool RPC, so the transactions `submit_signed`
+    /// recorded are reconciled against the chain instead: the ones that have
+    /// been mined are forgotten and the rest are returned. Transactions the
+    /// node dropped stay pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet_name_or_key` - Only include transactions from or to this wallet, contact or address
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Transaction>)` - The pending transactions, oldest first
+    /// * `Err(WalletError::WalletNotFound)` - If the wallet name matches no wallet or contact
+    /// * `Err(WalletError)` - If the chain cannot be fetched or the pending list cannot be updated
+    pub async fn get_mempool(&mut self, wallet_name_or_key: Option<&str>) -> Result<Vec<Transaction>> {
+        let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+        let filter = wallet_name_or_key
+            .map(|name_or_key| self.resolve_address(name_or_key))
+            .transpose()?
+            .map(|address| canonical(&address));
+
+        let mut pending = PendingTransactions::load()?;
+        if !pending.transactions.is_empty() {
+            let blocks = self.get_state(|_, _| {}).await?;
+            let mined: HashSet<String> = blocks.iter()
+                .flat_map(|block| &block.transactions)
+                .map(transaction_id)
+                .collect();
+            pending.prune(|id| mined.contains(id))?;
+        }
+
+        let mut transactions = Vec::with_capacity(pending.transactions.len());
+        for view in pending.transactions {
+            let tx = Transaction::try_from(view)?;
+            let matches = filter.as_ref()
+                .is_none_or(|address| canonical(&tx.from) == *address || canonical(&tx.to) == *address);
+            if matches {
+                transactions.push(tx);
+            }
+        }
+        Ok(transactions)
+    }
+
+    /// Waits until a transaction is mined into a block.
+    ///
+    /// Checks the current chain first, since the transaction may already be
+    /// mined, then polls for each new block by index. `on_pending` is called
+    /// with the time waited so far whenever no new block is available yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction to wait for
+    /// * `timeout` - How long to wait before giving up
+    /// * `on_pending` - Called before each pause while the transaction is unconfirmed
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The index of the block containing the transaction
+    /// * `Err(WalletError::Timeout)` - If the transaction is not mined within `timeout`
+    /// * `Err(WalletError)` - If an error occurs while querying the blockchain
+    pub async fn wait_for_confirmation<F>(
+        &mut self,
+        signature: &[u8],
+        timeout: Duration,
+        mut on_pending: F,
+    ) -> Result<u64>
+    where
+        F: FnMut(Duration),
+    {
+        let start = tokio::time::Instant::now();
+        let contains = |block: &ProtoBlock| block.transactions.iter().any(|tx| tx.signature == signature);
+
+        let blocks = self.get_state(|_, _| {}).await?;
+        if let Some(block) = blocks.iter().rev().find(|block| contains(block)) {
+            return Ok(block.index);
+        }
+
+        let mut next_index = blocks.len() as u64;
+        loop {
+            match self.get_block(next_index).await? {
+                Some(block) if contains(&block) => return Ok(block.index),
+                Some(_) => next_index += 1,
+                None => {
+                    let waited = start.elapsed();
+                    if waited >= timeout {
+                        return Err(WalletError::Timeout {
+                            operation: format!("wait for transaction {} to be mined", hex::encode(signature)),
+                        });
+                    }
+                    on_pending(waited);
+                    tokio::time::sleep(CONFIRMATION_POLL_INTERVAL.min(timeout - waited)).await;
+                }
+            }
+        }
+    }
+
+    /// Submits a signed transaction and waits until it is mined.
+    ///
+    /// Unlike `submit_signed`, a transaction the node already has counts as
+    /// submitted, since it exists and will be mined like a new one. This
+    /// makes a retried send that was in fact accepted the first time report
+    /// its block instead of failing.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The signed transaction
+    /// * `timeout` - How long to wait for the transaction to be mined
+    /// * `on_pending` - Called before each pause while the transaction is unconfirmed
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The index of the block containing the transaction
+    /// * `Err(WalletError::Timeout)` - If the transaction is not mined within `timeout`
+    /// * `Err(WalletError)` - If the node rejects the transaction or an error occurs while querying it
+    pub async fn submit_and_wait<F>(&mut self, transaction: Transaction, timeout: Duration, on_pending: F) -> Result<u64>
+    where
+        F: FnMut(Duration),
+    {
+        let signature = transaction.signature.clone();
+        match self.submit_signed(transaction).await {
+            Ok(_) | Err(WalletError::DuplicateTransaction { .. }) => {}
+            Err(e) => return Err(e),
+        }
+        self.wait_for_confirmation(&signature, timeout, on_pending).await
+    }
+
+    /// Attaches a local note to a transaction.
+    ///
+    /// Notes are stored only in local storage and are never sent to the
+    /// blockchain service.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The transaction id (hex-encoded signature)
+    /// * `note` - The note text
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the note is stored successfully
+    /// * `Err(WalletError::InvalidTransactionId)` - If the id is not valid hex
+    /// * `Err(WalletError)` - If an error occurs while saving the note
+    pub fn add_tx_note(&mut self, id: &str, note: &str) -> Result<()> {
+        let id = id.to_lowercase();
+        if id.is_empty() || hex::decode(&id).is_err() {
+            return Err(WalletError::InvalidTransactionId(id));
+        }
+
+        self.tx_notes.add_note(&id, note)
+    }
+
+    /// Lists all local transaction notes.
+    ///
+    /// # Returns
+    ///
+    /// A vector of (transaction id, notes) tuples, sorted by id.
+    pub fn list_tx_notes(&self) -> Vec<(String, Vec<String>)> {
+        let mut notes: Vec<_> = self.tx_notes.notes.iter()
+            .map(|(id, notes)| (id.clone(), notes.clone()))
+            .collect();
+        notes.sort_by(|a, b| a.0.cmp(&b.0));
+        notes
+    }
+
+    /// Adds a contact to the address book.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The name of the contact
+    /// * `address` - The contact's hex-encoded public key
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The stored address
+    /// * `Err(WalletError::AddressInvalid)` - If the address is not a valid public key
+    /// * `Err(WalletError::ContactExists)` - If a contact with the alias already exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn add_contact(&mut self, alias: &str, address: &str) -> Result<String> {
+        let address = display_address(&parse_public_key(address)?);
+        self.address_book.add_contact(alias, &address)?;
+        Ok(address)
+    }
+
+    /// Removes a contact from the address book.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The name of the contact to remove
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The address of the removed contact
+    /// * `Err(WalletError::ContactNotFound)` - If no contact with the alias exists
+    /// * `Err(WalletError)` - If an error occurs while saving
+    pub fn remove_contact(&mut self, alias: &str) -> Result<String> {
+        self.address_book.remove_contact(alias)
+    }
+
+    /// Lists the contacts in the address book.
+    ///
+    /// # Returns
+    ///
+    /// A vector of (alias, address) tuples, sorted by alias.
+    pub fn list_contacts(&self) -> Vec<(String, String)> {
+        let mut contacts: Vec<_> = self.address_book.contacts.iter()
+            .map(|(alias, address)| (alias.clone(), address.clone()))
+            .collect();
+        contacts.sort_by(|a, b| a.0.cmp(&b.0));
+        contacts
+    }
+
+    /// Resolves a wallet name, contact alias or public key to an address.
+    ///
+    /// A name prefixed with `wallet:`, `contact:` or `key:` is only looked up
+    /// in that namespace. Without a prefix all three are tried, and a name
+    /// that resolves to different addresses in two of them is rejected
+    /// rather than guessed at, so a contact can never shadow a wallet.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_or_key` - Wallet name, contact alias or public key, optionally prefixed
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(ResolvedAddress))` - The address and where it was found
+    /// * `Ok(None)` - If the name matches nothing
+    /// * `Err(WalletError::AmbiguousRecipient)` - If the name matches different addresses
+    pub fn resolve_recipient(&self, name_or_key: &str) -> Result<Option<ResolvedAddress>> {
+        const SOURCES: [AddressSource; 3] = [AddressSource::Wallet, AddressSource::Contact, AddressSource::Key];
+        let lookup = |source: AddressSource, name: &str| {
+            let address = match source {
+                AddressSource::Wallet => self.wallets.get_wallet(name).map(|keypair| keypair.public_key.clone()),
+                AddressSource::Contact => self.address_book.contacts.get(name).cloned(),
+                AddressSource::Key => normalize_address(name).ok(),
+            };
+            address.map(|address| ResolvedAddress { address, source })
+        };
+
+        let prefixed = SOURCES
+            .iter()
+            .find_map(|&source| name_or_key.strip_prefix(source.prefix()).map(|name| (source, name)));
+        if let Some((source, name)) = prefixed {
+            return Ok(lookup(source, name));
+        }
+
+        let matches: Vec<ResolvedAddress> = SOURCES
+            .iter()
+            .filter_map(|&source| lookup(source, name_or_key))
+            .collect();
+        // Compare canonical forms, as older wallets may store an uncompressed key
+        let canonical = |address: &str| normalize_address(address).unwrap_or_else(|_| address.to_string());
+        if matches.iter().any(|m| canonical(&m.address) != canonical(&matches[0].address)) {
+            return Err(WalletError::AmbiguousRecipient {
+                name: name_or_key.to_string(),
+                choices: matches
+                    .iter()
+                    .map(|m| format!("{}{}", m.source.prefix(), name_or_key))
+                    .collect(),
+            });
+        }
+        Ok(matches.into_iter().next())
+    }
+
+    /// Resolves a wallet name, contact alias or public key to an address.
+    ///
+    /// See `resolve_recipient` for how names are looked up. When nothing
+    /// matches, the error says whether to check the name or the key:
+    /// `AddressInvalid` (with the reason) for input that looks like a key,
+    /// `ContactNotFound` for a `contact:` name, and `WalletNotFound` otherwise.
+    fn resolve_address(&self, name_or_key: &str) -> Result<String> {
+        if let Some(resolved) = self.resolve_recipient(name_or_key)? {
+            return Ok(resolved.address);
+        }
+        if let Some(alias) = name_or_key.strip_prefix(AddressSource::Contact.prefix()) {
+            return Err(WalletError::ContactNotFound(alias.to_string()));
+        }
+        if let Some(name) = name_or_key.strip_prefix(AddressSource::Wallet.prefix()) {
+            return Err(WalletError::WalletNotFound(name.to_string()));
+        }
+        let key = name_or_key.strip_prefix(AddressSource::Key.prefix());
+        match key {
+            Some(key) => validate_public_key(key).and(Err(WalletError::AddressInvalid(key.to_string()))),
+            None if looks_like_key(name_or_key) => {
+                validate_public_key(name_or_key).and(Err(WalletError::AddressInvalid(name_or_key.to_string())))
+            }
+            None => Err(WalletError::WalletNotFound(name_or_key.to_string())),
+        }
+    }
+
+    /// Gets the local notes attached to a transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - The transaction to look up
+    ///
+    /// # Returns
+    ///
+    /// The notes for the transaction, empty if none were recorded.
+    pub fn tx_notes(&self, tx: &Transaction) -> &[String] {
+        self.tx_notes.get_notes(&transaction_id(tx))
+    }
+}
+
+/// Builds the TLS configuration for an https:// endpoint.
+///
+/// Without a CA certificate the system's root certificates are trusted.
+/// A client certificate and key must be given together.
+fn tls_config(options: &TlsOptions) -> Result<ClientTlsConfig> {
+    let mut tls = ClientTlsConfig::new();
+    if let Some(path) = &options.ca_cert {
+        tls = tls.ca_certificate(Certificate::from_pem(read_pem(path, "CERTIFICATE")?));
+    }
+    match (&options.client_cert, &options.client_key) {
+        (Some(cert), Some(key)) => {
+            tls = tls.identity(Identity::from_pem(
+                read_pem(cert, "CERTIFICATE")?,
+                read_pem(key, "PRIVATE KEY")?,
+            ));
+        }
+        (None, None) => {}
+        _ => {
+            return Err(WalletError::TlsConfig {
+                message: "--client-cert and --client-key must be given together".to_string(),
+            })
+        }
+    }
+    Ok(tls)
+}
+
+/// Reads a PEM file, checking that it holds a block of the expected kind.
+fn read_pem(path: &str, kind: &str) -> Result<Vec<u8>> {
+    let pem = std::fs::read(path).map_err(|e| WalletError::TlsConfig {
+        message: format!("cannot read {}: {}", path, e),
+    })?;
+    let text = String::from_utf8_lossy(&pem);
+    if !text.contains("-----BEGIN") || !text.contains(kind) {
+        return Err(WalletError::TlsConfig {
+            message: format!("{} does not contain a PEM {}", path, kind.to_lowercase()),
+        });
+    }
+    Ok(pem)
+}
+
+/// Applies a history query's filters and paging.
+///
+/// The query's counterparty must already be resolved to a canonical
+/// address, as `resolve_history_query` does. Transactions are ordered
+/// oldest first (ties broken by id) before paging.
+fn apply_history_query(transactions: Vec<Transaction>, query: &HistoryQuery) -> Vec<Transaction> {
+    let is_counterparty = |address: &str, counterparty: &str| {
+        normalize_address(address).is_ok_and(|address| address == counterparty)
+    };
+    let mut transactions: Vec<Transaction> = transactions
+        .into_iter()
+        .filter(|tx| query.since.is_none_or(|since| tx.timestamp >= since))
+        .filter(|tx| query.until.is_none_or(|until| tx.timestamp <= until))
+        .filter(|tx| query.min_amount.is_none_or(|min| tx.amount >= min))
+        .filter(|tx| query.max_amount.is_none_or(|max| tx.amount <= max))
+        .filter(|tx| {
+            query.counterparty.as_deref().is_none_or(|counterparty| {
+                is_counterparty(&tx.from, counterparty) || is_counterparty(&tx.to, counterparty)
+            })
+        })
+        .collect();
+    transactions.sort_by(|a, b| {
+        a.timestamp.cmp(&b.timestamp).then_with(|| a.signature.cmp(&b.signature))
+    });
+
+    transactions
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Decodes the stored private key of a wallet.
+///
+/// Fails with `WalletError::WatchOnly` if the wallet has no private key.
+fn decode_stored_key(name: &str, keypair: &KeyPair) -> Result<SecretKey> {
+    let private_key = keypair.private_key.as_ref()
+        .ok_or_else(|| WalletError::WatchOnly(name.to_string()))?;
+    let secret_key_bytes = Zeroizing::new(hex::decode(private_key)?);
+    SecretKey::from_slice(&secret_key_bytes)
+        .map_err(|e| WalletError::InvalidPrivateKey { 
+            message: e.to_string() 
+        })
+}
+
+/// Finds a cooldown such as "try again in 30s" or "retry after 45 seconds" in a faucet message.
+///
+/// Returns the first number directly followed by a seconds unit, or `None`
+/// if the message gives no remaining time.
+fn parse_retry_after(message: &str) -> Option<u64> {
+    let words: Vec<String> = message
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '(' | ')'))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.trim_end_matches('.').to_lowercase())
+        .collect();
+    let is_seconds = |unit: &str| matches!(unit, "s" | "sec" | "secs" | "second" | "seconds");
+    words.iter().enumerate().find_map(|(i, word)| {
+        let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = &word[digits.len()..];
+        let seconds = digits.parse().ok()?;
+        let has_unit = if unit.is_empty() {
+            words.get(i + 1).is_some_and(|next| is_seconds(next))
+        } else {
+            is_seconds(unit)
+        };
+        has_unit.then_some(seconds)
+    })
+}
+
+/// Builds and signs a transaction from fully resolved inputs.
+///
+/// Deterministic with a `LocalSigner`: ECDSA signing uses RFC 6979 nonces,
+/// so the same inputs always give the same signature bytes.
+///
+/// # Arguments
+///
+/// * `signer` - Holds the sender's private key
+/// * `from` - The sender's address, matching the signer's public key
+/// * `to` - The recipient's address
+/// * `amount` - Number of coins to transfer
+/// * `timestamp` - Creation time of the transaction (unix seconds)
+/// * `nonce` - The sender's sequence number for this transaction
+/// * `options` - Optional transaction settings
+///
+/// # Returns
+///
+/// * `Ok(Transaction)` - The signed transaction
+/// * `Err(WalletError::InvalidArgument)` - If the memo is longer than `MAX_MEMO_BYTES`
+/// * `Err(WalletError::SigningFailed)` - If the signature does not verify against `from`
+fn build_transaction(
+    signer: &dyn Signer,
+    from: String,
+    to: String,
+    amount: u64,
+    timestamp: u64,
+    nonce: u64,
+    options: &SendOptions,
+) -> Result<Transaction> {
+    if options.memo.len() > MAX_MEMO_BYTES {
+        return Err(WalletError::InvalidArgument {
+            message: format!(
+                "memo is {} bytes, at most {} are allowed",
+                options.memo.len(),
+                MAX_MEMO_BYTES
+            ),
+        });
+    }
+
+    let mut transaction = Transaction {
+        from,
+        to,
+        amount,
+        timestamp,
+        signature: Vec::new(),
+        not_before: options.not_before,
+        memo: options.memo.clone(),
+        nonce,
+        fee: options.fee,
+    };
+
+    let digest = signing_digest(&transaction)?;
+    transaction.signature = signer.sign(&digest)?.to_vec();
+
+    // Catch any drift between the signing and verification encodings
+    if !WalletClient::verify_transaction(&transaction)? {
+        return Err(WalletError::SigningFailed {
+            message: "signature does not verify against the sender key".to_string(),
+        });
+    }
+
+    Ok(transaction)
+}
+
+/// Generates a random version 4 UUID to use as a faucet request's idempotency key.
+fn new_idempotency_key() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Returns the address of a raw private key (hex or WIF) without storing it.
+pub fn key_address(private_key: &str) -> Result<String> {
+    let mut secret_key = decode_secret_key(private_key)?;
+    let address = display_address(&secret_key.public_key(&Secp256k1::signing_only()));
+    secret_key.non_secure_erase();
+    Ok(address)
+}
+
+/// Returns the id of a transaction.
+///
+/// A transaction is identified by its hex-encoded signature.
+pub fn transaction_id(tx: &Transaction) -> String {
+    hex::encode(&tx.signature)
+}
+
+/// Encodes a transaction as the hex of its protobuf wire format.
+///
+/// This is the form other clients of the blockchain proto exchange
+/// transactions in; `decode_raw_transaction` reverses it exactly.
+pub fn encode_raw_transaction(tx: &Transaction) -> String {
+    hex::encode(tx.encode_to_vec())
+}
+
+/// Decodes a transaction from the hex of its protobuf wire format.
+///
+/// # Arguments
+///
+/// * `raw` - Hex-encoded protobuf `Transaction`, surrounding whitespace allowed
+///
+/// # Returns
+///
+/// * `Ok(Transaction)` - The decoded transaction, signature untouched
+/// * `Err(WalletError::InvalidRawTransaction)` - If the input is not hex or not a transaction
+pub fn decode_raw_transaction(raw: &str) -> Result<Transaction> {
+    let bytes = hex::decode(raw.trim())
+        .map_err(|e| WalletError::InvalidRawTransaction { message: e.to_string() })?;
+    Transaction::decode(bytes.as_slice())
+        .map_err(|e| WalletError::InvalidRawTransaction { message: e.to_string() })
+}
+
+/// Classifies a transaction as seen from the addresses a history belongs to.
+///
+/// Addresses are compared in canonical form, so a transaction naming a key
+/// in its uncompressed encoding still matches. Only public keys are needed,
+/// so this works for watch-only wallets and external addresses alike.
+///
+/// # Arguments
+///
+/// * `tx` - The transaction to classify
+/// * `own` - The history's addresses, in canonical form as returned by `history_addresses`
+///
+/// # Returns
+///
+/// * `Some(Direction)` - Which way the transaction moved funds
+/// * `None` - If the transaction involves none of the addresses
+pub fn transaction_direction(tx: &Transaction, own: &[String]) -> Option<Direction> {
+    let is_own = |address: &str| {
+        let address = normalize_address(address).unwrap_or_else(|_| address.to_string());
+        own.contains(&address)
+    };
+    match (is_own(&tx.from), is_own(&tx.to)) {
+        (true, true) => Some(Direction::SelfTransfer),
+        (true, false) => Some(Direction::Sent),
+        (false, true) => Some(Direction::Received),
+        (false, false) => None,
+    }
+}
+
+/// Returns how much a transaction changed the balance of the history's addresses.
+///
+/// The sender pays the fee, so a self-transfer costs just the fee.
+pub fn net_change(tx: &Transaction, direction: Direction) -> i128 {
+    match direction {
+        Direction::Sent => -(i128::from(tx.amount) + i128::from(tx.fee)),
+        Direction::Received => i128::from(tx.amount),
+        Direction::SelfTransfer => -i128::from(tx.fee),
+    }
+}
+
+/// How often `wait_for_confirmation` checks for a new block.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest delay between attempts to re-establish a block stream.
+const WATCH_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Base delay before the first retry of a transient RPC failure.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Runs an RPC, retrying transient failures with exponential backoff.
+///
+/// Each attempt is bounded by the configured call timeout; an attempt that
+/// runs out of time fails with `DeadlineExceeded`. Only `Unavailable` and
+/// `DeadlineExceeded` statuses are retried; any other status is returned
+/// immediately. The delay doubles after each attempt.
+///
+/// Every attempt is logged inside an `rpc` span with its latency and result.
+///
+/// # Arguments
+///
+/// * `method` - Name of the RPC, used to describe timeouts
+/// * `endpoint` - URL of the service the client is connected to, for logs
+/// * `config` - Client settings providing the retry limit and call timeout
+/// * `call` - Builds and sends the request; invoked once per attempt
+///
+/// # Returns
+///
+/// The response of the first successful attempt, or the last error.
+async fn with_retry<F, Fut, T>(
+    method: &str,
+    endpoint: &str,
+    config: &ClientConfig,
+    mut call: F,
+) -> std::result::Result<T, Status>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, Status>>,
+{
+    let span = tracing::info_span!("rpc", method, endpoint);
+    async {
+        let mut attempt = 0;
+        loop {
+            let start = Instant::now();
+            let result = match tokio::time::timeout(config.call_timeout, call()).await {
+                Ok(result) => result,
+                Err(_) => Err(Status::deadline_exceeded(method)),
+            };
+            let latency_ms = start.elapsed().as_millis() as u64;
+            match &result {
+                Ok(_) => info!(attempt, latency_ms, "rpc succeeded"),
+                Err(status) => info!(attempt, latency_ms, code = ?status.code(), message = status.message(), "rpc failed"),
+            }
+
+            match result {
+                Err(status) if attempt < config.max_retries && is_transient(&status) => {
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    debug!(delay_ms = delay.as_millis() as u64, "retrying");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Returns whether an RPC status is worth retrying.
+fn is_transient(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+}
+
+/// Resolves the blockchain service URLs, in the order to try them.
+///
+/// Uses, in priority order, the `--server` flags, the comma-separated
+/// `MOCKCHAIN_SERVERS` environment variable, the `MOCKCHAIN_SERVER`
+/// environment variable, the `server` key in the config file, and finally
+/// the default address. When several URLs are given, the one that last
+/// accepted a connection is moved to the front, so failover does not
+/// start over from a node that is known to be down.
+///
+/// # Arguments
+///
+/// * `flags` - The values of the `--server` flags
+/// * `config` - The stored user settings
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - The validated server URLs
+/// * `Err(WalletError::InvalidEndpoint)` - If a resolved URL is malformed
+pub fn resolve_endpoints(flags: &[String], config: &Config) -> Result<Vec<String>> {
+    let split = |list: String| -> Vec<String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let urls = Some(flags.to_vec())
+        .filter(|urls| !urls.is_empty())
+        .or_else(|| std::env::var(SERVERS_ENV).ok().map(split).filter(|urls| !urls.is_empty()))
+        .or_else(|| std::env::var(SERVER_ENV).ok().map(|url| vec![url]))
+        .or_else(|| config.server.clone().map(|url| vec![url]))
+        .unwrap_or_else(|| vec![DEFAULT_SERVER.to_string()]);
+
+    for url in &urls {
+        validate_endpoint(url)?;
+    }
+    let mut urls = urls;
+    if let Some(last) = config.last_endpoint.as_ref().and_then(|last| urls.iter().position(|url| url == last)) {
+        urls[..=last].rotate_right(1);
+    }
+    Ok(urls)
+}
+
+/// Checks that a server URL is an absolute http(s) URL with a host.
+fn validate_endpoint(url: &str) -> Result<()> {
+    let invalid = |message: &str| WalletError::InvalidEndpoint {
+        url: url.to_string(),
+        message: message.to_string(),
+    };
+
+    let uri: Uri = url.parse().map_err(|e: InvalidUri| invalid(&e.to_string()))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        Some(_) => return Err(invalid("scheme must be http or https")),
+        None => return Err(invalid("missing scheme (expected http:// or https://)")),
+    }
+    if uri.host().is_none() {
+        return Err(invalid("missing host"));
+    }
+
+    Ok(())
+}
+
+/// Derives a BIP32 child private key and chain code.
+///
+/// Indexes from `HARDENED_INDEX` up give hardened children, derived from
+/// the parent's private key; lower indexes use the parent's public key.
+fn derive_child_key(parent: &SecretKey, chain_code: &[u8; 32], index: u32) -> Result<(SecretKey, [u8; 32])> {
+    let invalid = |message: String| WalletError::InvalidPrivateKey { message };
+
+    let mut mac = Hmac::<Sha512>::new_from_slice(chain_code).map_err(|e| invalid(e.to_string()))?;
+    if index >= HARDENED_INDEX {
+        mac.update(&[0]);
+        mac.update(&parent.secret_bytes());
+    } else {
+        mac.update(&parent.public_key(&Secp256k1::new()).serialize());
+    }
+    mac.update(&index.to_be_bytes());
+    let output: Zeroizing<[u8; 64]> = Zeroizing::new(mac.finalize().into_bytes().into());
+
+    let tweak: [u8; 32] = output[..32].try_into().expect("HMAC-SHA512 output is 64 bytes");
+    let tweak = Scalar::from_be_bytes(tweak).map_err(|e| invalid(e.to_string()))?;
+    let child = parent.add_tweak(&tweak).map_err(|e| invalid(e.to_string()))?;
+    let child_chain_code = output[32..].try_into().expect("HMAC-SHA512 output is 64 bytes");
+    Ok((child, child_chain_code))
+}
+
+/// Formats a BIP32 path such as `m/44'/1'/0'/0/3`.
+fn format_derivation_path(path: &[u32]) -> String {
+    path.iter().fold("m".to_string(), |formatted, &index| {
+        if index >= HARDENED_INDEX {
+            format!("{}/{}'", formatted, index - HARDENED_INDEX)
+        } else {
+            format!("{}/{}", formatted, index)
+        }
+    })
+}
+
+/// Derives a wallet key pair from a BIP39 mnemonic.
+///
+/// The mnemonic's seed (with an empty passphrase) is turned into a BIP32
+/// master key, whose private key becomes the wallet key. The master chain
+/// code is kept so addresses can later be derived with `derive_address`.
+fn keypair_from_mnemonic(mnemonic: &Mnemonic, created_at: u64) -> Result<KeyPair> {
+    let seed = Zeroizing::new(mnemonic.to_seed(""));
+
+    let mut mac = Hmac::<Sha512>::new_from_slice(b"Bitcoin seed")
+        .map_err(|e| WalletError::InvalidPrivateKey { 
+            message: e.to_string() 
+        })?;
+    mac.update(seed.as_slice());
+    let master: Zeroizing<[u8; 64]> = Zeroizing::new(mac.finalize().into_bytes().into());
+
+    let secret_key = SecretKey::from_slice(&master[..32])
+        .map_err(|e| WalletError::InvalidPrivateKey { 
+            message: e.to_string() 
+        })?;
+    let public_key = secret_key.public_key(&Secp256k1::new());
+
+    Ok(KeyPair {
+        private_key: Some(encode_secret_key(&secret_key, KeyFormat::Hex)),
+        public_key: hex::encode(public_key.serialize()),
+        demo: false,
+        mnemonic: true,
+        nonce: 0,
+        chain_code: Some(hex::encode(&master[32..])),
+        derivation: None,
+        label: None,
+        tags: Vec::new(),
+        created_at,
+    })
+}
+
+/// Builds the error for a transaction the node rejected.
+///
+/// Recognizes insufficient-funds rejections that state both amounts, such as
+/// "Insufficient funds: have 5, need 10", and reports them as
+/// `WalletError::InsufficientFunds`; anything else is `TransactionFailed`.
+fn rejection_error(code: Code, message: String) -> WalletError {
+    let lower = message.to_lowercase();
+    if lower.contains("insufficient") {
+        let needed = number_near(&lower, &["need", "required", "requested"]);
+        let available = number_near(&lower, &["have", "available", "balance"]);
+        if let (Some(needed), Some(available)) = (needed, available) {
+            return WalletError::InsufficientFunds { needed, available };
+        }
+    }
+    WalletError::TransactionFailed { code, message }
+}
+
+/// Finds the number stated next to any of the keywords in a message.
+///
+/// Accepts the number right after the keyword ("need 10", "need: 10") or
+/// right before it ("10 needed"), but never across punctuation, so in
+/// "10 needed, 5 available" each keyword gets its own amount.
+fn number_near(message: &str, keywords: &[&str]) -> Option<u64> {
+    keywords.iter().find_map(|keyword| {
+        let start = message.find(keyword)?;
+        let after = message[start + keyword.len()..]
+            .trim_start_matches(char::is_alphabetic)
+            .trim_start_matches([' ', ':', '=']);
+        let digits: String = after.chars().take_while(char::is_ascii_digit).collect();
+        if !digits.is_empty() {
+            return digits.parse().ok();
+        }
+
+        let before = message[..start].trim_end();
+        let digits = &before[before.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+        digits.parse().ok()
+    })
+}
+
+/// Returns whether a rejection message from the node reports a duplicate transaction.
+fn is_duplicate_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("duplicate") || message.contains("already")
+}
+
+/// Version of the canonical encoding used to build signing preimages.
+///
+/// Version 1 is the JSON array `[from, to, amount, timestamp]`.
+pub const SIGNING_ENCODING_V1: u32 = 1;
+
+/// Version 2 appends the not-before time: `[from, to, amount, timestamp, not_before]`.
+pub const SIGNING_ENCODING_V2: u32 = 2;
+
+/// Version 3 appends the memo: `[from, to, amount, timestamp, not_before, memo]`.
+pub const SIGNING_ENCODING_V3: u32 = 3;
+
+/// Version 4 appends the nonce: `[from, to, amount, timestamp, not_before, memo, nonce]`.
+pub const SIGNING_ENCODING_V4: u32 = 4;
+
+/// Version 5 appends the fee: `[from, to, amount, timestamp, not_before, memo, nonce, fee]`.
+pub const SIGNING_ENCODING_V5: u32 = 5;
+
+/// Returns the signing encoding version a transaction was signed under.
+///
+/// Each version is only used when the field it adds is set, so transactions
+/// without those fields keep their original encoding and their signatures
+/// stay valid for nodes that don't know the newer fields.
+pub fn signing_encoding_version(tx: &Transaction) -> u32 {
+    if tx.fee != 0 {
+        SIGNING_ENCODING_V5
+    } else if tx.nonce != 0 {
+        SIGNING_ENCODING_V4
+    } else if !tx.memo.is_empty() {
+        SIGNING_ENCODING_V3
+    } else if tx.not_before != 0 {
+        SIGNING_ENCODING_V2
+    } else {
+        SIGNING_ENCODING_V1
+    }
+}
+
+/// Builds the canonical signing preimage of a transaction.
+///
+/// The signature field is not part of the preimage, so this can be used
+/// both before signing and to reconstruct the message of a signed transaction.
+pub fn signing_preimage(tx: &Transaction) -> Result<Vec<u8>> {
+    let preimage = match signing_encoding_version(tx) {
+        SIGNING_ENCODING_V1 => serde_json::to_string(&(
+            &tx.from,
+            &tx.to,
+            tx.amount,
+            tx.timestamp,
+        )),
+        SIGNING_ENCODING_V2 => serde_json::to_string(&(
+            &tx.from,
+            &tx.to,
+            tx.amount,
+            tx.timestamp,
+            tx.not_before,
+        )),
+        SIGNING_ENCODING_V3 => serde_json::to_string(&(
+            &tx.from,
+            &tx.to,
+            tx.amount,
+            tx.timestamp,
+            tx.not_before,
+            &tx.memo,
+        )),
+        SIGNING_ENCODING_V4 => serde_json::to_string(&(
+            &tx.from,
+            &tx.to,
+            tx.amount,
+            tx.timestamp,
+            tx.not_before,
+            &tx.memo,
+            tx.nonce,
+        )),
+        _ => serde_json::to_string(&(
+            &tx.from,
+            &tx.to,
+            tx.amount,
+            tx.timestamp,
+            tx.not_before,
+            &tx.memo,
+            tx.nonce,
+            tx.fee,
+        )),
+    }.map_err(|e| WalletError::JsonSerialize { 
+        error: e 
+    })?;
+
+    Ok(preimage.into_bytes())
+}
+
+/// Computes the SHA-256 digest of a transaction's signing preimage.
+///
+/// This is the message that is signed with the sender's private key.
+pub fn signing_digest(tx: &Transaction) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    hasher.update(signing_preimage(tx)?);
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::parse_percent;
+    use crate::clock::FixedClock;
+    use crate::mock_server::{MockProxy, MockProxyKind, MockServer, TestDir, FAUCET_AMOUNT};
+    use crate::proxy::Proxy;
+    use crate::signer::CompactSignature;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Private key of the wallet used for pinned signatures.
+    const SIGNER_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+    /// Stands in for a hardware wallet: signs digests with a key that is
+    /// never stored, and counts the requests it gets.
+    struct DeviceSigner {
+        secret_key: SecretKey,
+        requests: AtomicUsize,
+    }
+
+    impl Signer for DeviceSigner {
+        fn public_key(&self) -> secp256k1::PublicKey {
+            self.secret_key.public_key(&Secp256k1::new())
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<CompactSignature> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let message = secp256k1::Message::from_slice(message).unwrap();
+            Ok(Secp256k1::new().sign_ecdsa(&message, &self.secret_key).serialize_compact())
+        }
+    }
+
+    /// Creates a wallet and returns its address.
+    fn create(wallet: &mut WalletClient, name: &str) -> String {
+        wallet.create_wallet(name, None).unwrap();
+        wallet.get_wallet(name).unwrap().public_key.clone()
+    }
+
+    #[tokio::test]
+    async fn balance_comes_from_the_node() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let address = create(&mut wallet, "alice");
+        server.set_balance(&address, 42);
+
+        assert_eq!(wallet.get_balance("alice", false).await.unwrap(), 42);
+        assert_eq!(server.calls(), ["GetBalance"]);
+    }
+
+    #[tokio::test]
+    async fn sending_and_faucet_requests_invalidate_cached_balances() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        wallet.config.cache_ttl = Duration::from_secs(3600);
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+
+        assert_eq!(wallet.get_balance("alice", false).await.unwrap(), 10);
+        assert_eq!(wallet.get_balance("bob", false).await.unwrap(), 0);
+        assert_eq!(wallet.get_balance("alice", false).await.unwrap(), 10);
+        assert_eq!(server.calls(), ["GetBalance", "GetBalance"]);
+
+        wallet.send_transaction("alice", "bob", 3, &SendOptions::default()).await.unwrap();
+        assert_eq!(wallet.get_balance("alice", false).await.unwrap(), 7);
+        assert_eq!(wallet.get_balance("bob", false).await.unwrap(), 3);
+
+        wallet.request_faucet("bob", Some(5)).await.unwrap();
+        assert_eq!(wallet.get_balance("bob", false).await.unwrap(), 8);
+        // The funds check before the send always asks the node
+        let calls = ["GetBalance", "GetBalance", "GetBalance", "SubmitTransaction", "GetBalance", "GetBalance", "RequestFaucet", "GetBalance"];
+        assert_eq!(server.calls(), calls);
+    }
+
+    #[tokio::test]
+    async fn listed_balances_cover_only_the_named_wallets_and_survive_failures() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        for (name, balance) in [("alice", 5), ("bob", 7), ("carol", 11)] {
+            let address = create(&mut wallet, name);
+            server.set_balance(&address, balance);
+        }
+        server.fail_next("GetBalance", Status::invalid_argument("bad address"));
+
+        let names = ["alice".to_string(), "bob".to_string(), "nobody".to_string()];
+        let summary = wallet.wallet_balances(&names, false).await.unwrap();
+        assert_eq!(summary.balances.len(), 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert!(summary.total == 5 || summary.total == 7);
+        assert_eq!(server.calls(), ["GetBalance", "GetBalance"]);
+    }
+
+    #[tokio::test]
+    async fn send_submits_a_signed_transaction_with_the_next_nonce() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+
+        let options = SendOptions::default();
+        wallet.send_transaction("alice", "bob", 3, &options).await.unwrap();
+        wallet.send_transaction("alice", &bob, 4, &options).await.unwrap();
+
+        let submitted = server.submitted();
+        assert_eq!(submitted.len(), 2);
+        assert_eq!((submitted[0].from.as_str(), submitted[0].to.as_str()), (alice.as_str(), bob.as_str()));
+        assert_eq!((submitted[0].amount, submitted[1].amount), (3, 4));
+        assert_eq!((submitted[0].nonce, submitted[1].nonce), (1, 2));
+        for tx in &submitted {
+            assert!(WalletClient::verify_transaction(tx).unwrap());
+        }
+        assert_eq!(wallet.get_wallet("alice").unwrap().nonce, 2);
+        assert_eq!(server.balance(&bob), 7);
+    }
+
+    #[tokio::test]
+    async fn signing_with_a_pinned_clock_is_reproducible() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        wallet.config.clock = Arc::new(FixedClock(1_700_000_000));
+        wallet.import_key("signer", SIGNER_KEY, None, false).unwrap();
+        let options = SendOptions {
+            memo: "rent".to_string(),
+            allow_self: true,
+            ..SendOptions::default()
+        };
+
+        let tx = wallet.sign_transaction("signer", "signer", 25, wallet.now().unwrap(), &options).unwrap();
+        let again = wallet.sign_transaction("signer", "signer", 25, wallet.now().unwrap(), &options).unwrap();
+        assert_eq!(tx.signature, again.signature);
+        assert_eq!(tx.timestamp, 1_700_000_000);
+        assert_eq!(
+            hex::encode(signing_digest(&tx).unwrap()),
+            "e829ecaca9faab7d0289f6730b7a41f7fce9adedfc2712c8da21de244bd2e32c"
+        );
+        assert_eq!(hex::encode(&tx.signature), "13c19ae77bce7d92f03f7ea5ae8d5d06e9bd5adfe56b2d57295afa6764f48ac52d54e5217654c53f0dc1bfa64e0e2eea822316a0138034fd8397b12bb8a0a8b6");
+    }
+
+    #[tokio::test]
+    async fn signatures_verify_until_any_signed_field_changes() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        let options = SendOptions {
+            not_before: 1_700_000_100,
+            memo: "invoice 7".to_string(),
+            fee: 1,
+            ..SendOptions::default()
+        };
+        let tx = wallet.sign_transaction("alice", "bob", 25, 1_700_000_000, &options).unwrap();
+        assert_eq!(signing_encoding_version(&tx), SIGNING_ENCODING_V5);
+        assert!(WalletClient::verify_transaction(&tx).unwrap());
+
+        let tampered: [fn(&mut Transaction); 4] = [
+            |tx| tx.amount += 1,
+            |tx| tx.memo.push('!'),
+            |tx| tx.nonce += 1,
+            |tx| tx.timestamp -= 1,
+        ];
+        for tamper in tampered {
+            let mut altered = tx.clone();
+            tamper(&mut altered);
+            assert!(!WalletClient::verify_transaction(&altered).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn signed_messages_verify_against_the_signing_address() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+
+        let signature = wallet.sign_message("alice", "I own this address").unwrap();
+        WalletClient::verify_message(&alice, "I own this address", &signature).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_tampered_message_fails_verification() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+
+        let signature = wallet.sign_message("alice", "pay bob 5").unwrap();
+        let result = WalletClient::verify_message(&alice, "pay bob 50", &signature);
+        assert!(matches!(result, Err(WalletError::SignatureInvalid { .. })));
+    }
+
+    #[tokio::test]
+    async fn a_message_signature_fails_for_another_address() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+
+        let signature = wallet.sign_message("alice", "I own this address").unwrap();
+        let result = WalletClient::verify_message(&bob, "I own this address", &signature);
+        assert!(matches!(result, Err(WalletError::SignatureInvalid { .. })));
+    }
+
+    #[tokio::test]
+    async fn raw_transactions_round_trip_losslessly() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        wallet.import_key("signer", SIGNER_KEY, None, false).unwrap();
+        let options = SendOptions {
+            not_before: 1_700_000_100,
+            memo: "rent".to_string(),
+            fee: 2,
+            allow_self: true,
+            ..SendOptions::default()
+        };
+        let tx = wallet.sign_transaction("signer", "signer", 25, 1_700_000_000, &options).unwrap();
+
+        let raw = encode_raw_transaction(&tx);
+        let decoded = decode_raw_transaction(&format!(" {}\n", raw)).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(encode_raw_transaction(&decoded), raw);
+        assert!(WalletClient::verify_transaction(&decoded).unwrap());
+        assert!(matches!(decode_raw_transaction("zz"), Err(WalletError::InvalidRawTransaction { .. })));
+        assert!(matches!(decode_raw_transaction("ff"), Err(WalletError::InvalidRawTransaction { .. })));
+    }
+
+    #[tokio::test]
+    async fn only_not_before_times_past_the_warning_window_warn() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        let now = 1_700_000_000;
+
+        let cases = [
+            (now, 1, false),
+            (now, now - 60, false),
+            (now, now + NOT_BEFORE_WARNING_SECS, false),
+            (now, now + NOT_BEFORE_WARNING_SECS + 1, true),
+            (now, u64::MAX, true),
+            (u64::MAX - 1, u64::MAX, false),
+        ];
+        for (timestamp, not_before, warns) in cases {
+            let options = SendOptions { not_before, ..SendOptions::default() };
+            // A not-before time is never rejected, however far away
+            let tx = wallet.sign_transaction("alice", "bob", 1, timestamp, &options).unwrap();
+            assert_eq!(tx.not_before, not_before);
+            assert!(WalletClient::verify_transaction(&tx).unwrap());
+            let warnings = wallet.take_warnings();
+            match warnings[..] {
+                [] => assert!(!warns, "no warning for not_before {}", not_before),
+                [Warning::DistantNotBefore { not_before: warned, seconds_ahead }] => {
+                    assert!(warns, "unexpected warning for not_before {}", not_before);
+                    assert_eq!((warned, seconds_ahead), (not_before, not_before - timestamp));
+                }
+                _ => panic!("unexpected warnings for not_before {}", not_before),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn an_external_signer_sends_for_a_wallet_without_a_stored_key() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let device = Arc::new(DeviceSigner {
+            secret_key: decode_secret_key(SIGNER_KEY).unwrap(),
+            requests: Default::default(),
+        });
+        let address = wallet.add_watch("device", &display_address(&device.public_key()), false).unwrap();
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&address, 10);
+
+        assert!(matches!(
+            wallet.sign_transaction("device", "bob", 3, 1_700_000_000, &SendOptions::default()),
+            Err(WalletError::WatchOnly(_))
+        ));
+
+        wallet.config.signers.push(device.clone());
+        wallet.send_transaction("device", &bob, 3, &SendOptions::default()).await.unwrap();
+        let submitted = server.submitted();
+        assert_eq!((submitted[0].from.as_str(), submitted[0].nonce), (address.as_str(), 1));
+        assert!(WalletClient::verify_transaction(&submitted[0]).unwrap());
+        assert_eq!(device.requests.load(Ordering::SeqCst), 1);
+        assert!(Wallets::load().unwrap().get_wallet("device").unwrap().private_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn send_stamps_transactions_with_the_client_clock() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        wallet.config.clock = Arc::new(FixedClock(1_234));
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 1);
+
+        wallet.send_transaction("alice", &bob, 1, &SendOptions::default()).await.unwrap();
+        assert_eq!(server.submitted()[0].timestamp, 1_234);
+    }
+
+    #[tokio::test]
+    async fn sending_to_yourself_is_refused_unless_allowed() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let alice_uncompressed = hex::encode(parse_public_key(&alice).unwrap().serialize_uncompressed());
+        server.set_balance(&alice, 10);
+
+        for to in ["alice", alice.as_str(), alice_uncompressed.as_str()] {
+            assert!(matches!(
+                wallet.send_transaction("alice", to, 1, &SendOptions::default()).await,
+                Err(WalletError::SelfSend)
+            ));
+        }
+        assert!(server.submitted().is_empty());
+
+        let options = SendOptions {
+            allow_self: true,
+            ..SendOptions::default()
+        };
+        wallet.send_transaction("alice", "alice", 1, &options).await.unwrap();
+        assert_eq!(server.submitted().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn send_without_funds_is_not_submitted() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        server.set_balance(&alice, 2);
+
+        let result = wallet.send_transaction("alice", "bob", 5, &SendOptions::default()).await;
+        assert!(matches!(result, Err(WalletError::InsufficientFunds { needed: 5, available: 2 })));
+        assert!(server.submitted().is_empty());
+        assert_eq!(wallet.get_wallet("alice").unwrap().nonce, 0);
+    }
+
+    #[tokio::test]
+    async fn fees_are_signed_and_covered_by_the_balance_check() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+        let options = SendOptions {
+            fee: 3,
+            ..SendOptions::default()
+        };
+
+        let result = wallet.send_transaction("alice", "bob", 8, &options).await;
+        assert!(matches!(result, Err(WalletError::InsufficientFunds { needed: 11, available: 10 })));
+
+        wallet.send_transaction("alice", "bob", 7, &options).await.unwrap();
+        let tx = &server.submitted()[0];
+        assert_eq!((tx.amount, tx.fee), (7, 3));
+        assert_eq!(signing_encoding_version(tx), SIGNING_ENCODING_V5);
+        assert!(WalletClient::verify_transaction(tx).unwrap());
+        assert_eq!((server.balance(&alice), server.balance(&bob)), (0, 7));
+
+        // Changing the fee after signing breaks the signature
+        let mut altered = tx.clone();
+        altered.fee = 1;
+        assert!(!WalletClient::verify_transaction(&altered).unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_transaction_the_node_already_has_is_a_recorded_duplicate() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+        server.lose_submit_answers(true);
+
+        // The first attempt is accepted but its answer lost, so the retry finds it known
+        let result = wallet.send_transaction("alice", "bob", 3, &SendOptions::default()).await;
+        assert!(matches!(result, Err(WalletError::DuplicateTransaction { .. })));
+        assert_eq!(server.submitted().len(), 1);
+        assert_eq!((server.balance(&alice), server.balance(&bob)), (7, 3));
+        assert_eq!(wallet.get_wallet("alice").unwrap().nonce, 1);
+
+        server.lose_submit_answers(false);
+        wallet.send_transaction("alice", "bob", 2, &SendOptions::default()).await.unwrap();
+        assert_eq!(server.submitted()[1].nonce, 2);
+    }
+
+    #[tokio::test]
+    async fn every_batch_payment_pays_the_fee() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        let carol = create(&mut wallet, "carol");
+        server.set_balance(&alice, 20);
+        let payments = [
+            BatchPayment { to: "bob".to_string(), amount: 5 },
+            BatchPayment { to: "carol".to_string(), amount: 7 },
+        ];
+
+        let results = wallet.send_batch("alice", &payments, 2).await.unwrap();
+        assert!(results.iter().all(|result| result.is_ok()));
+        for tx in server.submitted() {
+            assert_eq!(tx.fee, 2);
+            assert!(WalletClient::verify_transaction(&tx).unwrap());
+        }
+        assert_eq!((server.balance(&alice), server.balance(&bob), server.balance(&carol)), (4, 5, 7));
+    }
+
+    #[tokio::test]
+    async fn templates_carry_a_fee() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+        std::fs::write("template.json", r#"{ "from": "alice", "to": "bob", "amount": 6, "fee": 2 }"#).unwrap();
+
+        let template = TransactionTemplate::load("template.json", 0).unwrap();
+        let id = wallet.send_template(&template).await.unwrap();
+        let tx = &server.submitted()[0];
+        assert_eq!(id, transaction_id(tx));
+        assert_eq!((tx.amount, tx.fee), (6, 2));
+        assert!(WalletClient::verify_transaction(tx).unwrap());
+        assert_eq!((server.balance(&alice), server.balance(&bob)), (2, 6));
+    }
+
+    #[tokio::test]
+    async fn template_amounts_are_in_coins_like_the_command_line() {
+        let _dir = TestDir::enter().await;
+        std::fs::write("template.json", r#"{ "from": "alice", "to": "bob", "amount": 6, "fee": "0.25" }"#).unwrap();
+        let template = TransactionTemplate::load("template.json", 2).unwrap();
+        assert_eq!((template.amount, template.fee), (Some(600), 25));
+
+        std::fs::write("template.json", r#"{ "amount": "6.001", "fee": "lots" }"#).unwrap();
+        let problems = match TransactionTemplate::load("template.json", 2) {
+            Err(WalletError::InvalidTemplate { problems }) => problems,
+            _ => panic!("expected an invalid template"),
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("'amount'") && problems[1].starts_with("'fee'"), "{:?}", problems);
+    }
+
+    #[tokio::test]
+    async fn templates_report_every_problem_before_sending() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let watched = create(&mut wallet, "alice");
+        wallet.add_watch("watched", &watched, true).unwrap();
+        server.set_balance(&watched, 5);
+
+        let template = TransactionTemplate {
+            from: Some("watched".to_string()),
+            to: Some(watched.clone()),
+            amount: Some(4),
+            fee: 2,
+            ..TransactionTemplate::default()
+        };
+        let problems = match wallet.send_template(&template).await {
+            Err(WalletError::InvalidTemplate { problems }) => problems,
+            other => panic!("expected an invalid template, got {:?}", other.map_err(|e| e.to_string())),
+        };
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("watch-only"), "{:?}", problems);
+        assert!(problems[1].contains("sender's own address"), "{:?}", problems);
+        assert!(problems[2].contains("6 but the sender has only 5"), "{:?}", problems);
+        assert!(server.submitted().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_pays_the_fee_from_the_balance() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 2);
+
+        assert_eq!(wallet.sweep("alice", "bob", 2).await.unwrap(), 0);
+        assert!(matches!(wallet.sweep("alice", &alice, 0).await, Err(WalletError::SelfSend)));
+        assert!(server.submitted().is_empty());
+
+        server.set_balance(&alice, 12);
+        assert_eq!(wallet.sweep("alice", "bob", 2).await.unwrap(), 10);
+        assert_eq!((server.balance(&alice), server.balance(&bob)), (0, 10));
+    }
+
+    /// Encrypts a secret into version 3 keystore JSON with fast KDF settings.
+    fn keystore_json(secret: &[u8], passphrase: &str, kdf: &str) -> String {
+        use aes::cipher::{KeyIvInit, StreamCipher};
+        use serde_json::json;
+        use sha3::{Digest, Keccak256};
+
+        let (salt, iv) = ([7u8; 32], [9u8; 16]);
+        let mut derived = [0u8; 32];
+        let kdfparams = if kdf == "scrypt" {
+            let params = scrypt::Params::new(4, 8, 1, 32).unwrap();
+            scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut derived).unwrap();
+            json!({ "n": 16, "r": 8, "p": 1, "dklen": 32, "salt": hex::encode(salt) })
+        } else {
+            pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), &salt, 16, &mut derived);
+            json!({ "c": 16, "dklen": 32, "prf": "hmac-sha256", "salt": hex::encode(salt) })
+        };
+        let mut ciphertext = secret.to_vec();
+        ctr::Ctr128BE::<aes::Aes128>::new_from_slices(&derived[..16], &iv)
+            .unwrap()
+            .apply_keystream(&mut ciphertext);
+        let mac = Keccak256::new().chain_update(&derived[16..]).chain_update(&ciphertext).finalize();
+        json!({
+            "version": 3,
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": hex::encode(iv) },
+                "ciphertext": hex::encode(ciphertext),
+                "kdf": kdf,
+                "kdfparams": kdfparams,
+                "mac": hex::encode(mac),
+            },
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn keys_import_from_hex_wif_pem_and_keystore_json() {
+        use pkcs8::der::asn1::AnyRef;
+        use pkcs8::der::oid::ObjectIdentifier;
+        use pkcs8::der::pem::LineEnding;
+        use pkcs8::der::{Encode, EncodePem};
+        use pkcs8::{AlgorithmIdentifierRef, PrivateKeyInfo};
+        use sec1::{EcParameters, EcPrivateKey};
+
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let secret = secret_key.secret_bytes();
+        let address = hex::encode(secret_key.public_key(&Secp256k1::new()).serialize());
+
+        let secp256k1 = ObjectIdentifier::new_unwrap("1.3.132.0.10");
+        let sec1 = EcPrivateKey {
+            private_key: &secret,
+            parameters: Some(EcParameters::NamedCurve(secp256k1)),
+            public_key: None,
+        };
+        let sec1_der = sec1.to_der().unwrap();
+        let pkcs8 = PrivateKeyInfo::new(
+            AlgorithmIdentifierRef {
+                oid: ObjectIdentifier::new_unwrap("1.2.840.10045.2.1"),
+                parameters: Some(AnyRef::from(&secp256k1)),
+            },
+            &sec1_der,
+        );
+
+        let encodings = [
+            (encode_secret_key(&secret_key, KeyFormat::Hex).to_string(), None),
+            (encode_secret_key(&secret_key, KeyFormat::Wif).to_string(), None),
+            (sec1.to_pem(LineEnding::LF).unwrap(), None),
+            (pkcs8.to_pem(LineEnding::LF).unwrap(), None),
+            (keystore_json(&secret, "hunter2", "scrypt"), Some("hunter2")),
+            (keystore_json(&secret, "hunter2", "pbkdf2"), Some("hunter2")),
+        ];
+        for (index, (encoded, passphrase)) in encodings.iter().enumerate() {
+            let name = format!("imported{}", index);
+            let keypair = wallet.import_key(&name, encoded, *passphrase, true).unwrap();
+            assert_eq!(keypair.public_key, address);
+            let exported = wallet.export_private_key(&name, KeyFormat::Hex).unwrap();
+            assert_eq!(exported.as_str(), hex::encode(secret));
+        }
+
+        let wrong_passphrase = wallet.import_key("wrong", &keystore_json(&secret, "hunter2", "scrypt"), Some("hunter3"), true);
+        assert!(matches!(wrong_passphrase, Err(WalletError::InvalidPrivateKey { message }) if message.contains("wrong passphrase")));
+        let public_pem = "-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n";
+        let public_key = wallet.import_key("public", public_pem, None, true);
+        assert!(matches!(public_key, Err(WalletError::InvalidPrivateKey { message }) if message.contains("PEM PUBLIC KEY")));
+    }
+
+    #[tokio::test]
+    async fn raw_keys_sign_without_being_stored() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let bob = create(&mut wallet, "bob");
+        let stored = std::fs::read_dir(".wallets/keys").unwrap().count();
+
+        let tx = wallet.sign_with_key(SIGNER_KEY, "bob", 5, 1_000, &SendOptions::default()).unwrap();
+        let expected = display_address(&decode_secret_key(SIGNER_KEY).unwrap().public_key(&Secp256k1::new()));
+        assert_eq!((tx.from.as_str(), tx.to.as_str(), tx.nonce), (expected.as_str(), bob.as_str(), 0));
+        assert!(WalletClient::verify_transaction(&tx).unwrap());
+        server.set_balance(&tx.from, 5);
+        wallet.submit_signed(tx).await.unwrap();
+        assert_eq!(server.balance(&bob), 5);
+        assert_eq!(std::fs::read_dir(".wallets/keys").unwrap().count(), stored);
+
+        let result = wallet.sign_with_key("not a key", "bob", 5, 1_000, &SendOptions::default());
+        assert!(matches!(result, Err(WalletError::InvalidPrivateKey { .. })));
+
+        // A key that belongs to a local wallet continues its nonce sequence
+        wallet.import_key("signer", SIGNER_KEY, None, false).unwrap();
+        wallet.record_nonce("signer", 4).unwrap();
+        let tx = wallet.sign_with_key(SIGNER_KEY, "bob", 1, 1_000, &SendOptions::default()).unwrap();
+        assert_eq!(tx.nonce, 5);
+    }
+
+    #[tokio::test]
+    async fn a_percentage_of_the_balance_rounds_down() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        server.set_balance(&alice, 25);
+
+        assert_eq!(wallet.balance_share("alice", parse_percent("50").unwrap()).await.unwrap(), (25, 12));
+        assert_eq!(wallet.balance_share("alice", parse_percent("3.99%").unwrap()).await.unwrap(), (25, 0));
+        assert_eq!(wallet.balance_share(&alice, parse_percent("100").unwrap()).await.unwrap(), (25, 25));
+        assert!(parse_percent("100.01").is_err());
+        assert!(parse_percent("-5").is_err());
+        assert!(parse_percent("12.345").is_err());
+    }
+
+    #[tokio::test]
+    async fn sweep_sends_the_whole_balance() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        server.set_balance(&alice, 17);
+
+        assert_eq!(wallet.sweep("alice", "bob", 0).await.unwrap(), 17);
+        assert_eq!((server.balance(&alice), server.balance(&bob)), (0, 17));
+
+        // An empty wallet submits nothing
+        assert_eq!(wallet.sweep("alice", "bob", 0).await.unwrap(), 0);
+        assert_eq!(server.submitted().len(), 1);
+        assert!(matches!(wallet.sweep("carol", "bob", 0).await, Err(WalletError::WalletNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn unresolvable_recipients_say_whether_to_check_the_name_or_the_key() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        let sign = |wallet: &mut WalletClient, to: &str| wallet.sign_transaction("alice", to, 1, 1_000, &SendOptions::default());
+
+        assert!(matches!(sign(&mut wallet, "bobb"), Err(WalletError::WalletNotFound(name)) if name == "bobb"));
+        assert!(matches!(sign(&mut wallet, "wallet:bobb"), Err(WalletError::WalletNotFound(name)) if name == "bobb"));
+        assert!(matches!(sign(&mut wallet, "contact:bobb"), Err(WalletError::ContactNotFound(alias)) if alias == "bobb"));
+
+        // A truncated key is reported as a bad key, with the reason
+        let truncated = "02".to_string() + &"11".repeat(31);
+        match sign(&mut wallet, &truncated) {
+            Err(WalletError::AddressInvalid(message)) => assert!(message.contains("32 bytes")),
+            other => panic!("expected an invalid address, got {:?}", other.map_err(|e| e.to_string())),
+        }
+        assert!(matches!(sign(&mut wallet, "key:cafe"), Err(WalletError::AddressInvalid(_))));
+        assert!(matches!(wallet.get_balance(&truncated, true).await, Err(WalletError::AddressInvalid(_))));
+    }
+
+    #[tokio::test]
+    async fn names_in_two_namespaces_must_be_disambiguated() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let other = Secp256k1::new().generate_keypair(&mut rand::thread_rng()).1;
+        let other = wallet.add_contact("alice", &hex::encode(other.serialize())).unwrap();
+        wallet.add_contact("bob", &alice).unwrap();
+        server.set_balance(&alice, 10);
+
+        let result = wallet.send_transaction("alice", "alice", 1, &SendOptions::default()).await;
+        match result {
+            Err(WalletError::AmbiguousRecipient { choices, .. }) => assert_eq!(choices, ["wallet:alice", "contact:alice"]),
+            other => panic!("expected an ambiguous recipient, got {:?}", other.map_err(|e| e.to_string())),
+        }
+        assert!(server.submitted().is_empty());
+
+        let resolved = wallet.resolve_recipient("contact:alice").unwrap().unwrap();
+        assert_eq!((resolved.address.as_str(), resolved.source), (other.as_str(), AddressSource::Contact));
+        let resolved = wallet.resolve_recipient("wallet:alice").unwrap().unwrap();
+        assert_eq!((resolved.address.as_str(), resolved.source), (alice.as_str(), AddressSource::Wallet));
+        assert!(wallet.resolve_recipient("wallet:bob").unwrap().is_none());
+
+        // Names that agree on the address are not ambiguous
+        let resolved = wallet.resolve_recipient(&alice).unwrap().unwrap();
+        assert_eq!(resolved.source, AddressSource::Key);
+        wallet.send_transaction("alice", "contact:alice", 1, &SendOptions::default()).await.unwrap();
+        assert_eq!(server.submitted()[0].to, other);
+    }
+
+    #[tokio::test]
+    async fn uncompressed_keys_reach_the_same_account() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let recipient = Secp256k1::new().generate_keypair(&mut rand::thread_rng()).1;
+        let compressed = hex::encode(recipient.serialize());
+        let uncompressed = hex::encode(recipient.serialize_uncompressed());
+        server.set_balance(&alice, 10);
+        server.set_balance(&compressed, 5);
+
+        assert_eq!(wallet.get_balance(&uncompressed, false).await.unwrap(), 5);
+        wallet.send_transaction("alice", &uncompressed, 2, &SendOptions::default()).await.unwrap();
+        assert_eq!(server.submitted()[0].to, compressed);
+        assert_eq!(wallet.get_history(&uncompressed, &HistoryQuery::default()).await.unwrap().len(), 1);
+        assert_eq!(server.balance(&compressed), 7);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let address = create(&mut wallet, "alice");
+        server.set_balance(&address, 7);
+        server.fail_next("GetBalance", Status::unavailable("node restarting"));
+        server.fail_next("GetBalance", Status::unavailable("node restarting"));
+
+        assert_eq!(wallet.get_balance("alice", false).await.unwrap(), 7);
+        assert_eq!(server.calls().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        server.fail_next("GetBalance", Status::invalid_argument("bad address"));
+
+        let result = wallet.get_balance("alice", false).await;
+        assert!(matches!(result, Err(WalletError::RpcError { status }) if status.code() == Code::InvalidArgument));
+        assert_eq!(server.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_calls_time_out() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        server.set_delay(Duration::from_secs(5));
+        wallet.config.call_timeout = Duration::from_millis(100);
+        wallet.config.max_retries = 1;
+
+        let result = wallet.get_balance("alice", false).await;
+        assert!(matches!(result, Err(WalletError::Timeout { .. })));
+        assert_eq!(server.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn exit_codes_separate_missing_wallets_node_failures_and_low_funds() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        server.set_balance(&alice, 2);
+
+        let missing = wallet.get_balance("carol", false).await.unwrap_err();
+        assert_eq!(missing.exit_code(), 2);
+        let poor = wallet.send_transaction("alice", "bob", 5, &SendOptions::default()).await.unwrap_err();
+        assert_eq!(poor.exit_code(), 4);
+        server.fail_next("GetBalance", Status::invalid_argument("bad address"));
+        let invalid = wallet.get_balance("alice", false).await.unwrap_err();
+        assert_eq!(invalid.exit_code(), 6);
+
+        let mut offline = WalletClient::new(ClientConfig {
+            servers: vec!["http://127.0.0.1:1".to_string()],
+            ..wallet.config.clone()
+        })
+        .unwrap();
+        let down = offline.get_balance("alice", true).await.unwrap_err();
+        assert_eq!(down.exit_code(), 3);
+    }
+
+    #[tokio::test]
+    async fn the_last_working_endpoint_is_reported_and_tried_first() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let down = "http://127.0.0.1:1".to_string();
+        let servers = vec![down.clone(), server.url.clone()];
+        let mut wallet = WalletClient::new(ClientConfig {
+            servers: servers.clone(),
+            ..server.client().config.clone()
+        })
+        .unwrap();
+        assert_eq!(wallet.connected_endpoint(), None);
+        wallet.ping().await.unwrap();
+        assert_eq!(wallet.connected_endpoint(), Some(server.url.as_str()));
+
+        let mut settings = Config {
+            last_endpoint: Some(server.url.clone()),
+            ..Config::default()
+        };
+        assert_eq!(resolve_endpoints(&servers, &settings).unwrap(), [server.url.clone(), down.clone()]);
+        settings.last_endpoint = Some("http://elsewhere:50051".to_string());
+        assert_eq!(resolve_endpoints(&servers, &settings).unwrap(), servers);
+    }
+
+    #[tokio::test]
+    async fn history_directions_match_any_encoding_of_the_wallet_key() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        let alice_uncompressed = hex::encode(parse_public_key(&alice).unwrap().serialize_uncompressed());
+        let own = wallet.history_addresses(Some("alice"), false).unwrap();
+
+        let tx = |from: &str, to: &str| Transaction {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount: 10,
+            fee: 2,
+            ..Transaction::default()
+        };
+        let sent = tx(&alice_uncompressed, &bob);
+        assert_eq!(transaction_direction(&sent, &own), Some(Direction::Sent));
+        assert_eq!(net_change(&sent, Direction::Sent), -12);
+        let received = tx(&bob, &alice);
+        assert_eq!(transaction_direction(&received, &own), Some(Direction::Received));
+        assert_eq!(net_change(&received, Direction::Received), 10);
+        let own_transfer = tx(&alice, &alice_uncompressed);
+        assert_eq!(transaction_direction(&own_transfer, &own), Some(Direction::SelfTransfer));
+        assert_eq!(net_change(&own_transfer, Direction::SelfTransfer), -2);
+        assert_eq!(transaction_direction(&tx(&bob, &bob), &own), None);
+    }
+
+    #[tokio::test]
+    async fn wallets_keep_tags_labels_and_creation_time() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        wallet.config.clock = Arc::new(FixedClock(1_700_000_000));
+        create(&mut wallet, "alice");
+        assert_eq!(wallet.get_wallet("alice").unwrap().created_at, 1_700_000_000);
+
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        wallet.tag_wallet("alice", &tags(&["savings", "cold", "cold"]), &[], Some(" Rainy day ")).unwrap();
+        let keypair = wallet.tag_wallet("alice", &[], &tags(&["savings"]), None).unwrap();
+        assert_eq!(keypair.tags, ["cold"]);
+        assert_eq!(keypair.label.as_deref(), Some("Rainy day"));
+        let reloaded = Wallets::load().unwrap();
+        assert_eq!(reloaded.get_wallet("alice").unwrap().tags, ["cold"]);
+        assert!(wallet.tag_wallet("alice", &[], &[], Some("")).unwrap().label.is_none());
+        assert!(matches!(
+            wallet.tag_wallet("alice", &tags(&["two words"]), &[], None),
+            Err(WalletError::InvalidArgument { .. })
+        ));
+
+        // Key files written before these fields existed still load
+        let old: KeyPair = serde_json::from_str(r#"{"private_key": "11", "public_key": "02", "nonce": 4}"#).unwrap();
+        assert_eq!((old.label, old.tags.len(), old.created_at, old.nonce), (None, 0, 0, 4));
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_leaves_the_wallet_file_and_its_backup_intact() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+        wallet.tag_wallet("alice", &["savings".to_string()], &[], None).unwrap();
+        let path = ".wallets/keys/alice.json";
+        let (contents, backup) = (std::fs::read(path).unwrap(), std::fs::read(format!("{}.bak", path)).unwrap());
+
+        // A directory in the way of the temporary file makes the write fail, even as root
+        std::fs::create_dir(format!("{}.tmp", path)).unwrap();
+        let result = wallet.tag_wallet("alice", &["cold".to_string()], &[], None);
+        assert!(matches!(result, Err(WalletError::StorageWrite { .. })));
+        assert_eq!(std::fs::read(path).unwrap(), contents);
+        assert_eq!(std::fs::read(format!("{}.bak", path)).unwrap(), backup);
+
+        std::fs::remove_dir(format!("{}.tmp", path)).unwrap();
+        let reloaded = server.client();
+        assert_eq!(reloaded.get_wallet("alice").unwrap().tags, ["savings"]);
+    }
+
+    #[tokio::test]
+    async fn wallet_labels_abbreviate_addresses_like_other_keys() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let stranger = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+        assert_eq!(wallet.label_address(&alice), format!("alice ({})", display::key(&alice)));
+        assert!(wallet.label_address(&alice).ends_with(&format!("{})", &alice[alice.len() - 6..])));
+        assert_eq!(wallet.label_address(stranger), "0279be66…f81798");
+    }
+
+    #[tokio::test]
+    async fn an_accepted_send_whose_nonce_cannot_be_saved_only_warns() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+
+        std::fs::create_dir(".wallets/keys/alice.json.tmp").unwrap();
+        wallet.send_transaction("alice", "bob", 3, &SendOptions::default()).await.unwrap();
+        assert_eq!(server.submitted().len(), 1);
+        let warnings = wallet.take_warnings();
+        assert!(
+            warnings.iter().any(|w| matches!(w, Warning::NonceNotRecorded { wallet, nonce: 1, .. } if wallet == "alice")),
+            "{:?}",
+            warnings
+        );
+    }
+
+    #[tokio::test]
+    async fn imported_keys_are_queried_at_their_derived_address() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        // The address of private key 1 is the curve's generator point
+        let address = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        server.set_balance(address, 17);
+
+        assert_eq!(wallet.import_key("hex", SIGNER_KEY, None, false).unwrap().public_key, address);
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        assert_eq!(wallet.import_key("wif", wif, None, true).unwrap().public_key, address);
+        assert_eq!(wallet.get_balance("hex", false).await.unwrap(), 17);
+        assert_eq!(wallet.get_balance("wif", false).await.unwrap(), 17);
+
+        assert!(matches!(
+            wallet.import_key("hex", wif, None, true),
+            Err(WalletError::WalletExists(name)) if name == "hex"
+        ));
+        assert!(matches!(
+            wallet.import_key("other", wif, None, false),
+            Err(WalletError::DuplicateKey { existing_name }) if existing_name == "hex" || existing_name == "wif"
+        ));
+        assert!(wallet.get_wallet("other").is_none());
+    }
+
+    #[tokio::test]
+    async fn duplicate_keys_are_refused_unless_allowed_and_reported_by_doctor() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let private_key = wallet.get_wallet("alice").unwrap().private_key.clone().unwrap();
+        let alice_uncompressed = hex::encode(parse_public_key(&alice).unwrap().serialize_uncompressed());
+
+        assert!(matches!(
+            wallet.import_key("copy", &private_key, None, false),
+            Err(WalletError::DuplicateKey { existing_name }) if existing_name == "alice"
+        ));
+        assert!(matches!(
+            wallet.add_watch("watched", &alice_uncompressed, false),
+            Err(WalletError::DuplicateKey { existing_name }) if existing_name == "alice"
+        ));
+        assert!(wallet.get_wallet("copy").is_none());
+        assert!(wallet.doctor().is_empty());
+
+        wallet.import_key("copy", &private_key, None, true).unwrap();
+        let problems = wallet.doctor();
+        assert_eq!(problems.len(), 1);
+        let StoreProblem::DuplicateKey { address, names } = &problems[0] else {
+            panic!("expected a duplicate key");
+        };
+        assert_eq!(address, &alice);
+        assert_eq!(names, &["alice".to_string(), "copy".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn doctor_reports_and_fixes_public_keys_that_do_not_match_the_private_key() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        // An uncompressed copy of the right key is not a mismatch
+        let bob_uncompressed = hex::encode(parse_public_key(&bob).unwrap().serialize_uncompressed());
+        wallet.wallets.update_wallet("bob", |keypair| keypair.public_key = bob_uncompressed).unwrap();
+        let other = SecretKey::from_slice(&[7; 32]).unwrap();
+        let other = display_address(&other.public_key(&Secp256k1::signing_only()));
+        wallet.wallets.update_wallet("alice", |keypair| keypair.public_key = other.clone()).unwrap();
+
+        let problems = wallet.doctor();
+        assert_eq!(problems.len(), 1);
+        let StoreProblem::KeyMismatch { name, stored, derived } = &problems[0] else {
+            panic!("expected a key mismatch");
+        };
+        assert_eq!((name.as_str(), stored, derived), ("alice", &other, &alice));
+
+        assert_eq!(wallet.fix_key_mismatches().unwrap(), ["alice"]);
+        assert!(wallet.doctor().is_empty());
+        assert_eq!(wallet.get_wallet("alice").unwrap().public_key, alice);
+    }
+
+    #[tokio::test]
+    async fn history_filters_by_amount_and_counterparty() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        create(&mut wallet, "carol");
+        server.set_balance(&alice, 100);
+        for (to, amount) in [("bob", 3), ("carol", 7), ("bob", 12)] {
+            wallet.send_transaction("alice", to, amount, &SendOptions::default()).await.unwrap();
+        }
+
+        // Sent within the same second, so history order is by signature
+        let amounts = |transactions: Vec<Transaction>| {
+            let mut amounts: Vec<u64> = transactions.iter().map(|tx| tx.amount).collect();
+            amounts.sort();
+            amounts
+        };
+        let query = HistoryQuery {
+            min_amount: Some(5),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(amounts(wallet.get_history("alice", &query).await.unwrap()), [7, 12]);
+        let query = HistoryQuery {
+            max_amount: Some(10),
+            counterparty: Some("bob".to_string()),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(amounts(wallet.get_history("alice", &query).await.unwrap()), [3]);
+
+        let inverted = HistoryQuery {
+            min_amount: Some(10),
+            max_amount: Some(5),
+            ..HistoryQuery::default()
+        };
+        assert!(matches!(wallet.get_history("alice", &inverted).await, Err(WalletError::InvalidArgument { .. })));
+        let unknown = HistoryQuery {
+            counterparty: Some("dave".to_string()),
+            ..HistoryQuery::default()
+        };
+        assert!(matches!(wallet.get_history("alice", &unknown).await, Err(WalletError::WalletNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn connections_tunnel_through_http_and_socks5_proxies() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let port = server.url.rsplit(':').next().unwrap();
+        let config = server.client().config;
+
+        for kind in [MockProxyKind::Http, MockProxyKind::Socks5] {
+            let proxy = MockProxy::start(kind).await;
+            let mut wallet = WalletClient::new(ClientConfig {
+                servers: vec![format!("http://localhost:{}", port)],
+                proxy: Some(Proxy::parse(&proxy.url, Vec::new()).unwrap()),
+                ..config.clone()
+            })
+            .unwrap();
+            wallet.ping().await.unwrap();
+            assert_eq!(proxy.requests(), [format!("localhost:{}", port)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn ping_times_a_cheap_call_and_reports_timeouts() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+
+        // An empty chain has no genesis block, but the node still answered
+        wallet.ping().await.unwrap();
+        assert_eq!(server.calls(), ["GetBlock"]);
+
+        server.set_delay(Duration::from_secs(5));
+        wallet.config.call_timeout = Duration::from_millis(100);
+        wallet.config.max_retries = 0;
+        assert!(matches!(wallet.ping().await, Err(WalletError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn faucet_credits_the_wallet() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let address = create(&mut wallet, "alice");
+
+        assert_eq!(wallet.request_faucet("alice", None).await.unwrap(), FAUCET_AMOUNT);
+        assert_eq!(server.balance(&address), FAUCET_AMOUNT);
+        assert!(wallet.take_warnings().is_empty());
+
+        assert_eq!(wallet.request_faucet("alice", Some(7)).await.unwrap(), 7);
+        assert_eq!(server.balance(&address), FAUCET_AMOUNT + 7);
+        assert!(matches!(wallet.take_warnings()[..], [Warning::RecentFaucetRequest { .. }]));
+    }
+
+    #[tokio::test]
+    async fn a_faucet_request_whose_answer_was_lost_is_retried_with_the_same_key() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let address = create(&mut wallet, "alice");
+
+        server.lose_faucet_answers(true);
+        assert!(wallet.request_faucet("alice", None).await.is_err());
+        assert_eq!(server.balance(&address), FAUCET_AMOUNT);
+        let log = wallet.faucet_log(Some("alice")).unwrap();
+        assert!(log.receipts.is_empty());
+        let key = log.pending[&address].key.clone();
+
+        server.lose_faucet_answers(false);
+        assert_eq!(wallet.request_faucet("alice", None).await.unwrap(), FAUCET_AMOUNT);
+        assert_eq!(server.balance(&address), FAUCET_AMOUNT);
+        let log = wallet.faucet_log(None).unwrap();
+        assert!(log.pending.is_empty());
+        assert_eq!(log.receipts.len(), 1);
+        assert_eq!((log.receipts[0].amount, &log.receipts[0].key), (FAUCET_AMOUNT, &key));
+
+        // A new request gets a new key and is paid again
+        assert_eq!(wallet.request_faucet("alice", None).await.unwrap(), FAUCET_AMOUNT);
+        assert_eq!(server.balance(&address), 2 * FAUCET_AMOUNT);
+    }
+
+    #[tokio::test]
+    async fn faucet_cooldown_is_reported() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        create(&mut wallet, "alice");
+
+        server.refuse_faucet("address is cooling down", 42);
+        let error = wallet.request_faucet("alice", None).await.unwrap_err();
+        assert!(matches!(error, WalletError::FaucetFailed { retry_after: Some(42), .. }));
+        assert!(error.to_string().ends_with("try again in 42s"));
+
+        server.refuse_faucet("rate limited, retry after 15 seconds", 0);
+        let error = wallet.request_faucet("alice", None).await.unwrap_err();
+        assert!(matches!(error, WalletError::FaucetFailed { retry_after: Some(15), .. }));
+
+        server.refuse_faucet("faucet is empty", 0);
+        let error = wallet.request_faucet("alice", None).await.unwrap_err();
+        assert!(matches!(error, WalletError::FaucetFailed { retry_after: None, .. }));
+    }
+
+    #[test]
+    fn retry_after_is_found_in_faucet_messages() {
+        assert_eq!(parse_retry_after("cooldown active, try again in 30s"), Some(30));
+        assert_eq!(parse_retry_after("Retry after 45 seconds."), Some(45));
+        assert_eq!(parse_retry_after("limit of 3 requests (wait 12 sec)"), Some(12));
+        assert_eq!(parse_retry_after("limit of 3 requests per day"), None);
+    }
+
+    #[tokio::test]
+    async fn chain_info_summarizes_the_state() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        for index in 0..3 {
+            server.push_block(ProtoBlock {
+                index,
+                timestamp: 1_700_000_000 + index as i64,
+                transactions: vec![Transaction::default(); index as usize],
+                hash: format!("{:064x}", index),
+                ..ProtoBlock::default()
+            });
+        }
+
+        let info = wallet.chain_info().await.unwrap();
+        assert_eq!((info.height, info.transactions), (3, 3));
+        assert_eq!(info.latest_timestamp, Some(1_700_000_002));
+        assert_eq!(info.latest_hash, Some(format!("{:064x}", 2)));
+        assert!(wallet.get_block(7).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn verify_chain_reports_the_first_broken_link() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let block = |index: u64, previous_hash: &str| ProtoBlock {
+            index,
+            previous_hash: previous_hash.to_string(),
+            hash: format!("hash-{}", index),
+            ..ProtoBlock::default()
+        };
+        server.push_block(block(0, ""));
+        server.push_block(block(1, "hash-0"));
+        server.push_block(block(2, "hash-1"));
+
+        let verification = wallet.verify_chain().await.unwrap();
+        assert_eq!((verification.blocks, verification.first_invalid), (3, None));
+
+        server.push_block(block(3, "hash-9"));
+        server.push_block(block(5, "hash-3"));
+        let verification = wallet.verify_chain().await.unwrap();
+        assert_eq!(
+            verification.first_invalid,
+            Some((3, ChainFault::BrokenLink { expected: "hash-2".to_string(), found: "hash-9".to_string() }))
+        );
+    }
+
+    #[tokio::test]
+    async fn mempool_lists_submitted_transactions_until_they_are_mined() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        create(&mut wallet, "carol");
+        server.set_balance(&alice, 100);
+        assert!(wallet.get_mempool(None).await.unwrap().is_empty());
+
+        wallet.send_transaction("alice", "bob", 3, &SendOptions::default()).await.unwrap();
+        wallet.send_transaction("alice", "carol", 4, &SendOptions::default()).await.unwrap();
+        let mut amounts: Vec<u64> = wallet.get_mempool(None).await.unwrap().iter().map(|tx| tx.amount).collect();
+        amounts.sort();
+        assert_eq!(amounts, [3, 4]);
+        let to_carol = wallet.get_mempool(Some("carol")).await.unwrap();
+        assert_eq!(to_carol.len(), 1);
+
+        server.push_block(ProtoBlock { index: 0, transactions: to_carol, ..ProtoBlock::default() });
+        let pending = wallet.get_mempool(None).await.unwrap();
+        assert_eq!(pending.iter().map(|tx| tx.amount).collect::<Vec<_>>(), [3]);
+        assert_eq!(PendingTransactions::load().unwrap().transactions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn confirmations_count_the_blocks_from_the_containing_one() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let mined = Transaction { signature: vec![7; 64], ..Transaction::default() };
+        server.push_block(ProtoBlock { index: 0, ..ProtoBlock::default() });
+        server.push_block(ProtoBlock { index: 1, transactions: vec![mined], ..ProtoBlock::default() });
+
+        assert_eq!(wallet.confirmations(&[7; 64], None).await.unwrap(), Some(1));
+        server.push_block(ProtoBlock { index: 2, ..ProtoBlock::default() });
+        server.push_block(ProtoBlock { index: 3, ..ProtoBlock::default() });
+        assert_eq!(wallet.confirmations(&[7; 64], None).await.unwrap(), Some(3));
+        assert_eq!(wallet.confirmations(&[8; 64], None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_transaction_the_node_already_has_reports_its_block() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        create(&mut wallet, "bob");
+        server.set_balance(&alice, 10);
+        let tx = wallet.sign_transaction("alice", "bob", 3, 1_000, &SendOptions::default()).unwrap();
+        server.push_block(ProtoBlock { index: 0, ..ProtoBlock::default() });
+        server.push_block(ProtoBlock { index: 1, transactions: vec![tx.clone()], ..ProtoBlock::default() });
+        server.fail_next("SubmitTransaction", Status::already_exists("transaction already known"));
+
+        let index = wallet.submit_and_wait(tx.clone(), Duration::from_secs(1), |_| {}).await.unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(wallet.get_wallet("alice").unwrap().nonce, tx.nonce);
+
+        // Other rejections still fail
+        server.fail_next("SubmitTransaction", Status::failed_precondition("bad nonce"));
+        assert!(wallet.submit_and_wait(tx, Duration::from_secs(1), |_| {}).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_known_timestamp_finds_the_block_with_few_requests() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let mined = Transaction { timestamp: 1_367, signature: vec![7; 64], ..Transaction::default() };
+        for index in 0..300 {
+            let transactions = if index == 37 { vec![mined.clone()] } else { Vec::new() };
+            server.push_block(ProtoBlock { index, timestamp: 1_000 + 10 * index as i64, transactions, ..ProtoBlock::default() });
+        }
+
+        let found = wallet.find_transaction(&hex::encode([7; 64]), Some(1_367), |_, _| {}).await.unwrap();
+        assert_eq!(found.map(|(index, tx)| (index, tx.timestamp)), Some((37, 1_367)));
+        assert_eq!(wallet.confirmations(&[7; 64], Some(1_367)).await.unwrap(), Some(263));
+        let calls = server.calls();
+        assert!(!calls.iter().any(|call| call == "GetState"));
+        assert!(calls.len() < 100, "{} requests", calls.len());
+
+        // A timestamp far off still finds it, through the full scan
+        assert_eq!(wallet.confirmations(&[7; 64], Some(3_500)).await.unwrap(), Some(263));
+        assert!(server.calls().iter().any(|call| call == "GetState"));
+    }
+
+    #[tokio::test]
+    async fn unordered_block_timestamps_fall_back_to_a_full_scan() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let mined = Transaction { timestamp: 1_000, signature: vec![7; 64], ..Transaction::default() };
+        for index in 0..64 {
+            // Timestamps run backwards, so the binary search would head the wrong way
+            let transactions = if index == 60 { vec![mined.clone()] } else { Vec::new() };
+            server.push_block(ProtoBlock { index, timestamp: 2_000 - index as i64, transactions, ..ProtoBlock::default() });
+        }
+
+        assert_eq!(wallet.confirmations(&[7; 64], Some(1_000)).await.unwrap(), Some(4));
+        assert!(server.calls().iter().any(|call| call == "GetState"));
+    }
+
+    #[tokio::test]
+    async fn following_a_wallet_reports_its_new_transactions() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        let alice = create(&mut wallet, "alice");
+        let bob = create(&mut wallet, "bob");
+        let tx = |from: &str, to: &str, amount| Transaction {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            ..Transaction::default()
+        };
+        server.push_block(ProtoBlock { index: 0, transactions: vec![tx(&bob, &alice, 1)], ..ProtoBlock::default() });
+
+        let mut seen = Vec::new();
+        let follow = wallet.follow("alice", Duration::from_millis(20), Some(2), None, |_, index, tx, direction| {
+            seen.push((index, tx.amount, direction.label()));
+        });
+        let mine = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let transactions = vec![tx(&bob, &bob, 2), tx(&alice, &bob, 3), tx(&bob, &alice, 4), tx(&bob, &alice, 5)];
+            server.push_block(ProtoBlock { index: 1, transactions, ..ProtoBlock::default() });
+        };
+        let (followed, ()) = tokio::join!(follow, mine);
+        assert_eq!(followed.unwrap(), 2);
+        assert_eq!(seen, [(1, 3, "SENT"), (1, 4, "RECEIVED")]);
+
+        let timeout = Some(Duration::from_millis(50));
+        let result = wallet.follow("alice", Duration::from_millis(20), Some(1), timeout, |_, _, _, _| {}).await;
+        assert!(matches!(result, Err(WalletError::Timeout { .. })));
+        assert_eq!(wallet.follow("alice", Duration::from_millis(20), None, timeout, |_, _, _, _| {}).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn chain_verification_catches_index_gaps() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        server.push_block(ProtoBlock { index: 0, hash: "a".to_string(), ..ProtoBlock::default() });
+        server.push_block(ProtoBlock { index: 2, previous_hash: "a".to_string(), ..ProtoBlock::default() });
+
+        let verification = wallet.verify_chain().await.unwrap();
+        assert_eq!(verification.first_invalid, Some((2, ChainFault::IndexGap { previous: 0 })));
+    }
+
+    #[tokio::test]
+    async fn block_range_reports_progress_for_every_index() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        for index in 0..4 {
+            server.push_block(ProtoBlock { index, ..ProtoBlock::default() });
+        }
+
+        let mut reports = Vec::new();
+        let blocks = wallet.get_blocks(2, 6, |done, total| reports.push((done, total))).await.unwrap();
+        assert_eq!(blocks.iter().map(|block| block.index).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(reports, [(1, 5), (2, 5), (3, 5), (4, 5), (5, 5)]);
+
+        let mut reports = Vec::new();
+        wallet.get_state(|done, total| reports.push((done, total))).await.unwrap();
+        assert_eq!(reports, [(4, 4)]);
+    }
+
+    #[tokio::test]
+    async fn streamed_blocks_arrive_in_index_order() {
+        let _dir = TestDir::enter().await;
+        let server = MockServer::start().await;
+        let mut wallet = server.client();
+        for index in 0..12 {
+            server.push_block(ProtoBlock { index, ..ProtoBlock::default() });
+        }
+        // The retried query answers after the others
+        server.fail_next("GetBlock", Status::unavailable("busy"));
+
+        let mut indices = Vec::new();
+        let found = wallet
+            .stream_blocks(0, 15, |_, _| {}, |block| indices.push(block.index))
+            .await
+            .unwrap();
+        assert_eq!(found, 12);
+        assert_eq!(indices, (0..12).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn an_existing_legacy_wallet_dir_is_kept_unless_another_is_chosen() {
+        let _dir = TestDir::enter().await;
+        std::fs::create_dir(".wallets").unwrap();
+
+        let chosen = crate::storage::resolve_wallet_dir(Some("elsewhere"));
+        assert_eq!(chosen, std::path::PathBuf::from("elsewhere"));
+        if std::env::var_os("MOCKCHAIN_WALLET_DIR").is_none() {
+            assert_eq!(crate::storage::resolve_wallet_dir(None), std::path::PathBuf::from(".wallets"));
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Advisory conditions raised while running a command.
+///
+/// Warnings never stop a command from completing. They are collected by the
+/// wallet client and emitted once the command has finished.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// Keys were derived from a public demo seed.
+    DemoKeys,
+
+    /// Recipient address does not belong to any local wallet.
+    UnknownRecipient { address: String },
+
+    /// A private key is about to be printed.
+    SecretExposure,
+
+    /// Transaction cannot execute until far in the future.
+    DistantNotBefore { not_before: u64, seconds_ahead: u64 },
+
+    /// A wallet's history could not be fetched and is missing from the results.
+    HistoryUnavailable { wallet: String, error: String },
+
+    /// A blockchain service could not be reached and the next one was used.
+    EndpointUnavailable { url: String },
+
+    /// Faucet funds were requested again shortly after the last request.
+    RecentFaucetRequest { wallet: String, seconds_ago: u64 },
+
+    /// A wallet file could not be parsed and was replaced by its backup.
+    CorruptFileRestored { path: String, error: String },
+
+    /// A wallet file could not be parsed and was moved aside.
+    CorruptFileMoved { path: String, moved_to: String, error: String },
+
+    /// A command is about to print a very long listing.
+    LargeOutput { blocks: usize, transactions: usize },
+
+    /// A sent transaction's nonce could not be saved with its wallet.
+    NonceNotRecorded { wallet: String, nonce: u64, error: String },
+}
+
+/// Formats the warning for display.
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::DemoKeys => 
+                write!(f, "demo keys are derived from a public seed and are NOT secret; never send real value to them"),
+            Warning::UnknownRecipient { address } => 
+                write!(f, "recipient {} is not one of your wallets or contacts; double-check the address", address),
+            Warning::SecretExposure => 
+                write!(f, "anyone who sees this private key can spend the wallet's funds"),
+            Warning::DistantNotBefore { not_before, seconds_ahead } => 
+                write!(f, "transaction cannot execute before {} ({} days from now)", not_before, seconds_ahead / 86_400),
+            Warning::HistoryUnavailable { wallet, error } => 
+                write!(f, "history for '{}' is missing: {}", wallet, error),
+            Warning::EndpointUnavailable { url } => 
+                write!(f, "blockchain service at {} is unavailable; trying the next server", url),
+            Warning::RecentFaucetRequest { wallet, seconds_ago } => 
+                write!(f, "'{}' received faucet funds {}s ago; the faucet may refuse another request this soon", wallet, seconds_ago),
+            Warning::CorruptFileRestored { path, error } => 
+                write!(f, "{} was corrupt ({}) and has been restored from its backup; its most recent change may be lost", path, error),
+            Warning::CorruptFileMoved { path, moved_to, error } => 
+                write!(f, "{} was corrupt ({}) and has been moved to {}; its wallets are missing until you run `mockallet repair`", path, error, moved_to),
+            Warning::LargeOutput { blocks, transactions } => 
+                write!(f, "printing {} blocks and {} transactions; use `get-block` or `get-blocks` to inspect specific blocks", blocks, transactions),
+            Warning::NonceNotRecorded { wallet, nonce, error } => 
+                write!(f, "the transaction was sent but nonce {} could not be saved for '{}' ({}); its next transaction may reuse it and be rejected", nonce, wallet, error),
+        }
+    }
+}
+
+/// Emits warnings to stderr.
+///
+/// This is the single place advisory output is written, so commands should
+/// report warnings through the wallet client rather than printing them.
+pub fn emit(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}